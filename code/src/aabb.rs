@@ -0,0 +1,115 @@
+use crate::vec3::{Vec3, Point3};
+use crate::ray::Ray;
+use crate::interval::Interval;
+use crate::constants::INFINITY;
+
+// Axis-aligned bounding box, the region of space an object is guaranteed to stay within
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Aabb {
+    min: Point3,
+    max: Point3,
+}
+
+impl Aabb {
+    // The box spanned by two opposite corners, given in any order
+    pub fn new (a: Point3, b: Point3) -> Self {
+        Aabb {
+            min: Point3::new(a.x().min(b.x()), a.y().min(b.y()), a.z().min(b.z())),
+            max: Point3::new(a.x().max(b.x()), a.y().max(b.y()), a.z().max(b.z())),
+        }
+    }
+
+    // Contains nothing; the identity for union
+    pub fn empty () -> Self {
+        Aabb { min: Point3::new(INFINITY, INFINITY, INFINITY), max: Point3::new(-INFINITY, -INFINITY, -INFINITY) }
+    }
+
+    // Contains everything, for objects with no finite extent
+    pub fn universe () -> Self {
+        Aabb { min: Point3::new(-INFINITY, -INFINITY, -INFINITY), max: Point3::new(INFINITY, INFINITY, INFINITY) }
+    }
+
+    pub fn min (&self) -> Point3 {
+        self.min
+    }
+
+    pub fn max (&self) -> Point3 {
+        self.max
+    }
+
+    pub fn is_empty (&self) -> bool {
+        (0..3).any(|axis| self.min[axis] > self.max[axis])
+    }
+
+    pub fn is_finite (&self) -> bool {
+        (0..3).all(|axis| self.min[axis].is_finite() && self.max[axis].is_finite())
+    }
+
+    pub fn union (&self, other: &Aabb) -> Aabb {
+        Aabb {
+            min: Point3::new(self.min.x().min(other.min.x()), self.min.y().min(other.min.y()), self.min.z().min(other.min.z())),
+            max: Point3::new(self.max.x().max(other.max.x()), self.max.y().max(other.max.y()), self.max.z().max(other.max.z())),
+        }
+    }
+
+    // Grown by `delta` on every side
+    pub fn padded (&self, delta: f32) -> Aabb {
+        let delta = Vec3::new(delta, delta, delta);
+        Aabb { min: self.min - delta, max: self.max + delta }
+    }
+
+    pub fn contains (&self, p: &Point3) -> bool {
+        (0..3).all(|axis| self.min[axis] <= p[axis] && p[axis] <= self.max[axis])
+    }
+
+    // The eight corners, with bit i of the index picking the max side along axis i
+    pub fn corners (&self) -> [Point3; 8] {
+        std::array::from_fn(|i| Point3::new(
+            if i & 1 == 0 { self.min.x() } else { self.max.x() },
+            if i & 2 == 0 { self.min.y() } else { self.max.y() },
+            if i & 4 == 0 { self.min.z() } else { self.max.z() },
+        ))
+    }
+
+    // Slab test: whether `r` passes through the box anywhere within `ray_t`
+    pub fn hit (&self, r: &Ray, ray_t: &Interval) -> bool {
+        let mut t_min = ray_t.min();
+        let mut t_max = ray_t.max();
+        for axis in 0..3 {
+            let inverse = 1.0 / r.direction()[axis];
+            let t0 = (self.min[axis] - r.origin()[axis]) * inverse;
+            let t1 = (self.max[axis] - r.origin()[axis]) * inverse;
+            let (near, far) = if t0 < t1 { (t0, t1) } else { (t1, t0) };
+            t_min = t_min.max(near);
+            t_max = t_max.min(far);
+            if t_max < t_min {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_aabb_hit_and_union(){
+        let a = Aabb::new(Point3::new(1.0, 1.0, 1.0), Point3::new(-1.0, -1.0, -1.0));
+        assert_eq!(a.min(), Point3::new(-1.0, -1.0, -1.0));
+
+        let interval = Interval::new(0.0, INFINITY);
+        assert!(a.hit(&Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), &interval));
+        assert!(!a.hit(&Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, 1.0)), &interval));
+        assert!(!a.hit(&Ray::new(Point3::new(2.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), &interval));
+        assert!(!a.hit(&Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), &Interval::new(0.0, 3.0)));
+
+        let b = Aabb::new(Point3::new(2.0, 0.0, 0.0), Point3::new(3.0, 4.0, 0.5));
+        let both = a.union(&b);
+        assert_eq!(both, Aabb::new(Point3::new(-1.0, -1.0, -1.0), Point3::new(3.0, 4.0, 1.0)));
+        assert!(b.corners().iter().all(|corner| both.contains(corner)));
+        assert_eq!(Aabb::empty().union(&a), a);
+        assert!(Aabb::empty().is_empty() && !Aabb::universe().is_finite());
+    }
+}
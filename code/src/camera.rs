@@ -1,9 +1,10 @@
 use crate::vec3::{Vec3, Point3};
 use crate::color::Color;
-use crate::ray::Ray;
-use crate::constants::{INFINITY, random_generator_range, random_generator, linear_to_gamma};
-use crate::interval::{Interval};
-use crate::hittable::{HitRecord, HittableList, Hittable, Sphere};
+use crate::ray::{Ray, RayDifferential};
+use crate::scene::Scene;
+use crate::integrator::{Integrator, AovSample};
+use crate::film::Film;
+use crate::sampler::{Sampler, RandomSampler};
 
 
 #[derive(Default)]
@@ -22,7 +23,10 @@ pub struct Camera {
     pub focal_length: f32,
     pub samples_per_pixel: f32,
     pub pixel_sample_scale: f32,
-    pub max_recursive_depth: i32,
+    // The shutter is open over [shutter_open, shutter_close] in the time scale moving objects use, where they move
+    // from their first to their last position between 0 and 1. An empty interval freezes everything at shutter_open
+    pub shutter_open: f32,
+    pub shutter_close: f32,
 }
 
 impl Camera {
@@ -35,7 +39,7 @@ impl Camera {
     pub fn initialize(&mut self) {
 
         let aspect_ratio = 9.0 / 16.0;
-        let mut width: i32 = ((self.image_height as f32) / aspect_ratio) as i32;
+        let width: i32 = ((self.image_height as f32) / aspect_ratio) as i32;
 
         if width < 1 {
             self.image_width = 1;
@@ -61,87 +65,95 @@ impl Camera {
         self.pixel00_loc = self.viewport_upper_left + 0.5 * (self.pixel_delta_u + self.pixel_delta_v);
         self.samples_per_pixel = 100.0;
         self.pixel_sample_scale = 1.0 / self.samples_per_pixel;
-        self.max_recursive_depth = 50;
     }
 
-    fn sample_square (&self) -> Vec3 {
-        Vec3::new((random_generator() as f32) - 0.5, (random_generator() as f32) - 0.5, 0.0)
+    // Unit vector the camera looks along
+    pub fn forward (&self) -> Vec3 {
+        (self.viewport_upper_left + self.viewport_u / 2.0 + self.viewport_v / 2.0 - self.camera_center).unit_vector()
     }
 
-    fn get_ray(&self, i: i32, j: i32) -> Ray {
-        let offset = self.sample_square();
+    fn sample_square (&self, sampler: &mut dyn Sampler) -> Vec3 {
+        let (x, y) = sampler.next_2d();
+        Vec3::new(x - 0.5, y - 0.5, 0.0)
+    }
+
+    fn get_ray(&self, i: i32, j: i32, sampler: &mut dyn Sampler) -> Ray {
+        let offset = self.sample_square(sampler);
         let pixel_sample = self.pixel00_loc + (self.pixel_delta_u * (i as f32 + offset.x())) + (self.pixel_delta_v * (j as f32 + offset.y()));
         let ray_direction = pixel_sample - self.camera_center;
-        let r = Ray::new(self.camera_center, ray_direction);
-        r
 
+        // Differentials reach one pixel over, shrunk as samples increase since each then only has to cover a
+        // fraction of the pixel
+        let scale = (1.0 / self.samples_per_pixel.sqrt()).max(0.125);
+        let differentials = RayDifferential {
+            rx_origin: self.camera_center,
+            rx_direction: ray_direction + scale * self.pixel_delta_u,
+            ry_origin: self.camera_center,
+            ry_direction: ray_direction + scale * self.pixel_delta_v,
+        };
+        Ray::new(self.camera_center, ray_direction).with_differentials(differentials).with_time(self.sample_time(sampler))
     }
 
-    fn ray_color (&self, r: Ray, max_recursive_depth: i32, world: &dyn Hittable) -> Color {
-
-        if max_recursive_depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0)
+    // Motion blur comes from spreading each pixel's rays evenly over the time the shutter is open
+    fn sample_time (&self, sampler: &mut dyn Sampler) -> f32 {
+        if self.shutter_close <= self.shutter_open {
+            return self.shutter_open;
         }
-        let mut rec: HitRecord = HitRecord::default();
-        let interval = Interval::new(0.0001, INFINITY);
-        let hit = world.hit(r, interval, &mut rec); 
-        match hit {
-            Some(hit_record) => {
-                let n = hit_record.normal();
-                let direction: Vec3 = Vec3::random_on_hemisphere(&n) + Vec3::random_unit_vector();
-                
-                return 0.5 * self.ray_color(Ray::new(hit_record.p(), direction), max_recursive_depth - 1,  world)
-                // println!("{}", hit_record.normal());
-                /* return Color::new(
-                    0.5 * n.x() + 0.5,
-                    0.5 * n.y() + 0.5,
-                    0.5 * n.z() + 0.5,
-                );    */     
-            }
-            None => {
-                let t: f32 = 0.5 * (r.direction().unit_vector().y() as f32 + 1.0);
-                return Color::new(
-                    (1.0 - t) * 1.0 + t * 0.5,
-                    (1.0 - t) * 1.0 + t * 0.7,
-                    (1.0 - t) * 1.0 + t * 1.0,
-                );
-            }
-        }
-    
-    
-        // Background color if no hit
-        let unit_direction = r.direction().unit_vector();
-        let t = 0.5 * (unit_direction.y() + 1.0);
-        Color::new(
-            (1.0 - t) * 1.0 + t * 0.5,
-            (1.0 - t) * 1.0 + t * 0.7,
-            (1.0 - t) * 1.0 + t * 1.0,
-        )
+        self.shutter_open + sampler.next_1d() * (self.shutter_close - self.shutter_open)
     }
 
-    pub fn render(&mut self, world: &dyn Hittable) {
+    // Renders the beauty image and all AOVs in one pass. Each buffer holds the average over a pixel's samples
+    pub fn render(&self, scene: &Scene, integrator: &dyn Integrator) -> Film {
 
-        println!("P3\n{} {} \n255\n", self.image_width, self.image_height);
+        let width = self.image_width as usize;
+        let height = self.image_height as usize;
+        let light_count = scene.lights.objects().len();
+        let forward = self.forward();
+        let mut film = Film::new(width, height, light_count);
+        film.color_space = scene.color_space;
 
+        let mut sampler = RandomSampler::new();
         for j in 0..self.image_height {
             for i in 0..self.image_width{
-/*                 let pixel_center = self.pixel00_loc + (self.pixel_delta_u * (i as f32)) + (self.pixel_delta_v * (j as f32));
-                let ray_direction = pixel_center - self.camera_center;
-                let r = Ray::new(self.camera_center, ray_direction);
-
-                let pixel_color: Color = self.ray_color(r, world);
-                
-                println!("{}", pixel_color);
- */
+                let mut pixel = AovSample::new(light_count);
                 let mut pixel_color: Color = Color::new(0.0, 0.0, 0.0);
-                for sample in 0..(self.samples_per_pixel as i32) {
-                    let r: Ray = self.get_ray(i, j);
-                    pixel_color = pixel_color + self.ray_color(r, self.max_recursive_depth, world); 
+                // Depth is only defined where a sample hits something, so it averages over those samples alone
+                let mut pixel_depth: f32 = 0.0;
+                let mut depth_samples = 0;
+                for _ in 0..(self.samples_per_pixel as i32) {
+                    let r: Ray = self.get_ray(i, j, &mut sampler);
+                    let mut aov = AovSample::new(light_count);
+                    pixel_color = pixel_color + integrator.li_aov(r, scene, &mut sampler, &mut aov); 
+
+                    if aov.hit {
+                        pixel_depth += (aov.position - self.camera_center).dot(&forward);
+                        depth_samples += 1;
+                    }
+                    pixel.normal += aov.normal;
+                    pixel.albedo = pixel.albedo + aov.albedo;
+                    pixel.direct = pixel.direct + aov.direct;
+                    pixel.indirect = pixel.indirect + aov.indirect;
+                    for (total, light) in pixel.lights.iter_mut().zip(aov.lights.iter()) {
+                        *total = *total + *light;
+                    }
+                }
+
+                let (x, y) = (i as usize, j as usize);
+                let scale = self.pixel_sample_scale;
+                let depth = if depth_samples > 0 { pixel_depth / depth_samples as f32 } else { 0.0 };
+                film.beauty.set(x, y, scale * pixel_color);
+                film.albedo.set(x, y, scale * pixel.albedo);
+                film.normal.set(x, y, Color::from(scale * pixel.normal));
+                film.depth.set(x, y, Color::new(depth, depth, depth));
+                film.direct.set(x, y, scale * pixel.direct);
+                film.indirect.set(x, y, scale * pixel.indirect);
+                for (image, light) in film.lights.iter_mut().zip(pixel.lights.iter()) {
+                    image.set(x, y, scale * *light);
                 }
-                println!("{}", self.pixel_sample_scale * pixel_color);
- 
             }
         }
+
+        film
     }
 }
 
@@ -160,4 +172,66 @@ mod tests {
         assert_eq!(c.viewport_width, c.viewport_height * 1.7775);
 
     }
+
+    #[test]
+    fn test_camera_forward(){
+        let c: Camera = Camera::new(400);
+        assert!((c.forward() - Vec3::new(0.0, 0.0, -1.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn test_camera_render_fills_film(){
+        let mut c: Camera = Camera::new(4);
+        c.samples_per_pixel = 2.0;
+        c.pixel_sample_scale = 0.5;
+
+        let scene = Scene::default();
+        let film = c.render(&scene, &crate::integrator::PathIntegrator::default());
+
+        assert_eq!(film.beauty.width(), 7);
+        assert_eq!(film.beauty.height(), 4);
+        // With only a sky, all of the image is direct light from the environment
+        assert_eq!(film.lights.len(), 1);
+        assert_eq!(film.beauty, film.direct);
+        assert_eq!(film.beauty, film.lights[0]);
+    }
+
+    #[test]
+    fn test_camera_depth_averages_only_hits(){
+        use crate::hittable::{HittableList, Sphere};
+        use crate::material::Lambertian;
+        use std::sync::Arc;
+
+        let mut c: Camera = Camera::new(4);
+        c.samples_per_pixel = 16.0;
+        c.pixel_sample_scale = 1.0 / 16.0;
+
+        // A large ball whose left edge grazes the view axis, so it covers about half of the center column of
+        // pixels, where its surface is between 6.5 and 12 units away
+        let mut world = HittableList::new();
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        world.add(Arc::new(Sphere::new(Point3::new(-10.0, 0.0, -12.0), 10.0, mat)));
+        let scene = Scene::new(world, HittableList::new());
+        let film = c.render(&scene, &crate::integrator::PathIntegrator::default());
+
+        for j in 1..3 {
+            let depth = film.depth.get(3, j).x();
+            assert!((6.5..=12.0).contains(&depth), "edge pixel depth {}", depth);
+            assert_eq!(film.depth.get(6, j).x(), 0.0);
+        }
+    }
+
+    #[test]
+    fn test_camera_rays_sample_the_shutter_interval(){
+        let mut c: Camera = Camera::new(4);
+        let mut sampler = RandomSampler::new();
+        assert_eq!(c.get_ray(1, 1, &mut sampler).time(), 0.0);
+
+        c.shutter_open = 0.25;
+        c.shutter_close = 0.75;
+        let times: Vec<f32> = (0..1000).map(|_| c.get_ray(1, 1, &mut sampler).time()).collect();
+        assert!(times.iter().all(|t| (0.25..=0.75).contains(t)));
+        let mean = times.iter().sum::<f32>() / times.len() as f32;
+        assert!((mean - 0.5).abs() < 0.02);
+    }
 }
@@ -0,0 +1,289 @@
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord, Ior};
+use crate::microfacet::fresnel_dielectric;
+use crate::spectrum::{LAMBDA_D_LINE, spectrum_to_rgb};
+use crate::constants::{PI, random_generator};
+use std::sync::Arc;
+
+// Wavelengths integrated when a wavelength-dependent reflectance has to be turned into RGB
+const RGB_SPECTRUM_STEPS: usize = 32;
+
+// A dielectric film a few hundred nanometres thick, in air over a dielectric substrate. Light reflected off its top
+// and bottom interfaces interferes, so the reflectance swings with wavelength, thickness and viewing angle
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct ThinFilm {
+    // Film thickness in nanometres
+    thickness: f32,
+    ior: f32,
+    // Index of what lies under the film; 1 for a free-standing film such as a soap bubble
+    substrate_ior: f32,
+}
+
+impl ThinFilm {
+    pub fn new (thickness: f32, ior: f32, substrate_ior: f32) -> Self {
+        ThinFilm { thickness: thickness.max(0.0), ior, substrate_ior }
+    }
+
+    // Airy reflectance for unpolarized light arriving at `cos_theta` from air, at wavelength `lambda` in nanometres
+    pub fn reflectance (&self, cos_theta: f32, lambda: f32) -> f32 {
+        let cos1 = cos_theta.clamp(0.0, 1.0);
+        let sin2 = 1.0 - cos1 * cos1;
+        let (n2, n3) = (self.ior, self.substrate_ior);
+        let cos2_squared = 1.0 - sin2 / (n2 * n2);
+        let cos3_squared = 1.0 - sin2 / (n3 * n3);
+        if cos2_squared <= 0.0 || cos3_squared <= 0.0 {
+            return 1.0;
+        }
+        let (cos2, cos3) = (cos2_squared.sqrt(), cos3_squared.sqrt());
+
+        // Amplitude reflection coefficients of the top and bottom interfaces for both polarizations
+        let r12_s = (cos1 - n2 * cos2) / (cos1 + n2 * cos2);
+        let r23_s = (n2 * cos2 - n3 * cos3) / (n2 * cos2 + n3 * cos3);
+        let r12_p = (n2 * cos1 - cos2) / (n2 * cos1 + cos2);
+        let r23_p = (n3 * cos2 - n2 * cos3) / (n3 * cos2 + n2 * cos3);
+
+        // Phase picked up by one round trip through the film
+        let cos_delta = (4.0 * PI * n2 * self.thickness * cos2 / lambda).cos();
+        let airy = |r12: f32, r23: f32| {
+            let cross = 2.0 * r12 * r23 * cos_delta;
+            (r12 * r12 + r23 * r23 + cross) / (1.0 + r12 * r12 * r23 * r23 + cross)
+        };
+        (0.5 * (airy(r12_s, r23_s) + airy(r12_p, r23_p))).clamp(0.0, 1.0)
+    }
+
+    // The reflectance integrated against the visible spectrum, as linear sRGB
+    pub fn reflectance_rgb (&self, cos_theta: f32) -> Color {
+        spectrum_to_rgb(|lambda| self.reflectance(cos_theta, lambda), RGB_SPECTRUM_STEPS)
+    }
+}
+
+// The reflective layer of a Coated material
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Coating {
+    // Clear lacquer reflecting by the Fresnel equations of its index
+    Clear(Ior),
+    // Interference film; its substrate index should roughly match the base material's
+    ThinFilm(ThinFilm),
+}
+
+impl Coating {
+    // Reflectance at `cos_theta`. With a wavelength it is a grey holding the value at that wavelength, as the
+    // spectral integrator expects from dispersive materials; without one it is the RGB reflectance
+    pub fn reflectance (&self, cos_theta: f32, wavelength: Option<f32>) -> Color {
+        let grey = |value: f32| Color::new(value, value, value);
+        match (self, wavelength) {
+            (Coating::Clear(ior), _) => grey(fresnel_dielectric(cos_theta, ior.eval(wavelength.unwrap_or(LAMBDA_D_LINE)))),
+            (Coating::ThinFilm(film), Some(lambda)) => grey(film.reflectance(cos_theta, lambda)),
+            (Coating::ThinFilm(film), None) => film.reflectance_rgb(cos_theta),
+        }
+    }
+
+    pub fn dispersive (&self) -> bool {
+        !matches!(self, Coating::Clear(Ior::Constant(_)))
+    }
+}
+
+// Fraction of the reflectance's average energy, used as the chance of taking the coating's mirror path
+fn reflect_probability (reflectance: Color) -> f32 {
+    ((reflectance.r() + reflectance.g() + reflectance.b()) / 3.0).clamp(0.0, 1.0)
+}
+
+// A smooth coating over any base material. Light either reflects off the coating as off a mirror, or is
+// transmitted into the base, weighted by what the coating let through; the base then scatters as it would bare.
+// Back faces, which paths inside the base hit, are left uncoated
+pub struct Coated {
+    base: Arc<dyn Material>,
+    coating: Coating,
+}
+
+impl Coated {
+    pub fn new (base: Arc<dyn Material>, coating: Coating) -> Self {
+        Coated { base, coating }
+    }
+
+    // The coating's reflectance seen along `r_in` and the probability of reflecting off it
+    fn reflectance (&self, r_in: &Ray, rec: &HitRecord) -> (Color, f32) {
+        if !rec.front_face() {
+            return (Color::new(0.0, 0.0, 0.0), 0.0);
+        }
+        let cos_theta = (-r_in.direction().unit_vector()).dot(&rec.shading_normal());
+        let reflectance = self.coating.reflectance(cos_theta, r_in.wavelength());
+        (reflectance, reflect_probability(reflectance))
+    }
+
+    // Weight of the base's scattering: the light the coating transmits, over the chance of picking the base
+    fn base_weight (&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        let (reflectance, probability) = self.reflectance(r_in, rec);
+        if probability >= 1.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        (Color::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - probability)
+    }
+}
+
+impl Material for Coated {
+    fn emitted (&self, r_in: &Ray, rec: &HitRecord) -> Color {
+        self.base.emitted(r_in, rec)
+    }
+
+    fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let (reflectance, probability) = self.reflectance(r_in, rec);
+        if random_generator() < probability {
+            let direction = r_in.direction().unit_vector().reflect(&rec.shading_normal());
+            let mut ray = Ray::new(rec.p(), direction);
+            if let Some(differentials) = rec.reflect_differentials(r_in, direction) {
+                ray = ray.with_differentials(differentials);
+            }
+            return Some(ScatterRecord::Specular { attenuation: reflectance / probability, ray });
+        }
+
+        let weight = self.base_weight(r_in, rec);
+        match self.base.scatter(r_in, rec)? {
+            ScatterRecord::Pdf { attenuation, pdf } => Some(ScatterRecord::Pdf { attenuation: attenuation * weight, pdf }),
+            ScatterRecord::Specular { attenuation, ray } => Some(ScatterRecord::Specular { attenuation: attenuation * weight, ray }),
+            ScatterRecord::Walk { attenuation, ray } => Some(ScatterRecord::Walk { attenuation: attenuation * weight, ray }),
+            // The weight is applied in eval
+            ScatterRecord::Bsdf { pdf } => Some(ScatterRecord::Bsdf { pdf }),
+        }
+    }
+
+    fn scattering_pdf (&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
+        self.base.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn eval (&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        self.base.eval(r_in, rec, scattered) * self.base_weight(r_in, rec)
+    }
+
+    fn albedo (&self, rec: &HitRecord) -> Color {
+        self.base.albedo(rec)
+    }
+
+    fn dispersive (&self) -> bool {
+        self.coating.dispersive() || self.base.dispersive()
+    }
+}
+
+// A free-standing thin film with air on both sides, such as a soap bubble. Light reflects off it with the film's
+// interference colors or passes straight through; the sheet is too thin to offset the transmitted ray
+pub struct ThinFilmSheet {
+    film: ThinFilm,
+}
+
+impl ThinFilmSheet {
+    pub fn new (thickness: f32, ior: f32) -> Self {
+        ThinFilmSheet { film: ThinFilm::new(thickness, ior, 1.0) }
+    }
+}
+
+impl Material for ThinFilmSheet {
+    fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = (-unit_direction).dot(&rec.shading_normal());
+        let reflectance = Coating::ThinFilm(self.film).reflectance(cos_theta, r_in.wavelength());
+        let probability = reflect_probability(reflectance);
+
+        let (attenuation, direction, differentials) = if random_generator() < probability {
+            let direction = unit_direction.reflect(&rec.shading_normal());
+            (reflectance / probability, direction, rec.reflect_differentials(r_in, direction))
+        } else {
+            let transmittance = (Color::new(1.0, 1.0, 1.0) - reflectance) / (1.0 - probability);
+            (transmittance, unit_direction, rec.refract_differentials(r_in, unit_direction, 1.0))
+        };
+
+        let mut ray = Ray::new(rec.p(), direction);
+        if let Some(differentials) = differentials {
+            ray = ray.with_differentials(differentials);
+        }
+        Some(ScatterRecord::Specular { attenuation, ray })
+    }
+
+    fn albedo (&self, _rec: &HitRecord) -> Color {
+        self.film.reflectance_rgb(1.0)
+    }
+
+    fn dispersive (&self) -> bool {
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::Vec3;
+    use crate::material::Lambertian;
+
+    fn hit_from (wo: Vec3) -> (Ray, HitRecord) {
+        let mut rec = HitRecord::default();
+        let r_in = Ray::new(wo, -wo);
+        rec.set_face_normal(&r_in, Vec3::new(0.0, 0.0, 1.0));
+        (r_in, rec)
+    }
+
+    // Mean path throughput after one scattering event, assuming the base samples its own scattering pdf
+    fn mean_throughput (material: &dyn Material, r_in: &Ray, rec: &HitRecord, n: usize) -> Color {
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            sum = sum + match material.scatter(r_in, rec) {
+                Some(ScatterRecord::Specular { attenuation, .. }) => attenuation,
+                Some(ScatterRecord::Pdf { attenuation, .. }) => attenuation,
+                _ => Color::new(0.0, 0.0, 0.0),
+            };
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn test_zero_thickness_film_is_plain_fresnel(){
+        // With no film the two interfaces collapse into one between air and the substrate
+        let film = ThinFilm::new(0.0, 1.33, 1.5);
+        for cos_theta in [1.0, 0.7, 0.2] {
+            assert!((film.reflectance(cos_theta, 550.0) - fresnel_dielectric(cos_theta, 1.5)).abs() < 1e-4);
+        }
+        let rgb = film.reflectance_rgb(1.0);
+        assert!((rgb.r() - rgb.g()).abs() < 1e-3 && (rgb.g() - rgb.b()).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_thin_film_interference(){
+        let bubble = ThinFilm::new(400.0, 1.33, 1.0);
+        let values: Vec<f32> = (400..700).step_by(10).map(|lambda| bubble.reflectance(1.0, lambda as f32)).collect();
+        let (min, max) = values.iter().fold((1.0f32, 0.0f32), |(lo, hi), &v| (lo.min(v), hi.max(v)));
+        assert!(min >= 0.0 && max <= 1.0);
+        // Constructive and destructive interference within the visible range
+        assert!(max > 0.05 && min < 0.01, "reflectance ranged over {}..{}", min, max);
+
+        // Thickness changes the color
+        let thicker = ThinFilm::new(550.0, 1.33, 1.0);
+        assert!((bubble.reflectance_rgb(1.0) - thicker.reflectance_rgb(1.0)).length() > 0.01);
+    }
+
+    #[test]
+    fn test_coated_conserves_energy(){
+        let (r_in, rec) = hit_from(Vec3::new(0.0, 0.3, 1.0).unit_vector());
+        let coating = Coating::Clear(Ior::Constant(1.5));
+
+        // Over a black base only the coating's reflection remains
+        let black = Coated::new(Arc::new(Lambertian::new(Color::new(0.0, 0.0, 0.0))), coating);
+        let reflected = mean_throughput(&black, &r_in, &rec, 20000).r();
+        assert!((reflected - 0.04).abs() < 0.01, "coating reflected {}", reflected);
+
+        // Over a white base what the coating doesn't reflect is scattered by the base
+        let white = Coated::new(Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))), coating);
+        assert!((mean_throughput(&white, &r_in, &rec, 20000).r() - 1.0).abs() < 1e-3);
+
+        assert!(!white.dispersive());
+        assert!(Coated::new(Arc::new(Lambertian::new(Color::new(1.0, 1.0, 1.0))), Coating::ThinFilm(ThinFilm::new(300.0, 1.4, 1.5))).dispersive());
+    }
+
+    #[test]
+    fn test_thin_film_sheet_is_lossless(){
+        let sheet = ThinFilmSheet::new(350.0, 1.33);
+        let (r_in, rec) = hit_from(Vec3::new(0.2, 0.0, 1.0).unit_vector());
+        let r_in = r_in.with_wavelength(520.0);
+        let total = mean_throughput(&sheet, &r_in, &rec, 20000);
+        assert!((total.r() - 1.0).abs() < 1e-3);
+    }
+}
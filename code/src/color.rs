@@ -1,6 +1,6 @@
 use crate::vec3::Vec3;
 use crate::interval::{Interval};
-use crate::constants::{linear_to_gamma};
+use crate::colorspace::{srgb_oetf};
 use std::fmt;
 use std::ops::{Deref, DerefMut, Div, Mul, Add, Sub};
 
@@ -23,6 +23,12 @@ impl DerefMut for Color {
     }
 }
 
+impl From<Vec3> for Color {
+    fn from(v: Vec3) -> Self {
+        Color(v)
+    }
+}
+
 impl Add for Color {
     type Output = Color;
 
@@ -87,9 +93,9 @@ impl fmt::Display for Color {
         let mut g = self.y();
         let mut b = self.z();
 
-        r = linear_to_gamma(r);
-        g = linear_to_gamma(g);
-        b = linear_to_gamma(b);
+        r = srgb_oetf(r);
+        g = srgb_oetf(g);
+        b = srgb_oetf(b);
 
         let intensity: Interval = Interval::new(0.000, 0.999);
         let ir = (intensity.clamp(r) * 256.0) as i32;
@@ -122,6 +128,15 @@ impl Color {
         self[2]
     }
 
+    // Relative luminance with Rec. 709 / sRGB primaries
+    pub fn luminance (&self) -> f32 {
+        0.2126 * self[0] + 0.7152 * self[1] + 0.0722 * self[2]
+    }
+
+    pub fn max_component (&self) -> f32 {
+        self[0].max(self[1]).max(self[2])
+    }
+
     pub fn length_squared (&self) -> f32 {
         self[0] * self[0] + self[1] * self[1] + self[2] * self[2]
     }
@@ -155,7 +170,7 @@ mod tests {
     #[test]
     fn test_color_display () {
         let c = Color::new(0.5, 0.4, 0.3);
-        assert_eq!(format!("{}", c), "127 102 76");
+        assert_eq!(format!("{}", c), "188 170 149");
     }
 
     #[test]
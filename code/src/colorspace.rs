@@ -0,0 +1,210 @@
+use crate::color::Color;
+use crate::film::Image;
+use std::str::FromStr;
+use std::sync::OnceLock;
+
+// sRGB opto-electronic transfer function: linear light in [0, 1] to the encoded value stored in 8-bit images
+pub fn srgb_oetf (x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x <= 0.0031308 {
+        return 12.92 * x;
+    }
+    1.055 * x.powf(1.0 / 2.4) - 0.055
+}
+
+// Inverse of srgb_oetf: an encoded sRGB value back to linear light
+pub fn srgb_eotf (x: f32) -> f32 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    if x <= 0.04045 {
+        return x / 12.92;
+    }
+    ((x + 0.055) / 1.055).powf(2.4)
+}
+
+pub(crate) type Mat3 = [[f32; 3]; 3];
+
+fn mat3_mul (a: &Mat3, b: &Mat3) -> Mat3 {
+    let mut m = [[0.0; 3]; 3];
+    for (i, row) in m.iter_mut().enumerate() {
+        for (j, value) in row.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[i][k] * b[k][j]).sum();
+        }
+    }
+    m
+}
+
+pub(crate) fn mat3_inverse (m: &Mat3) -> Mat3 {
+    let [[a, b, c], [d, e, f], [g, h, i]] = *m;
+    let co_a = e * i - f * h;
+    let co_b = -(d * i - f * g);
+    let co_c = d * h - e * g;
+    let det = a * co_a + b * co_b + c * co_c;
+
+    [
+        [co_a / det, -(b * i - c * h) / det, (b * f - c * e) / det],
+        [co_b / det, (a * i - c * g) / det, -(a * f - c * d) / det],
+        [co_c / det, -(a * h - b * g) / det, (a * e - b * d) / det],
+    ]
+}
+
+pub(crate) fn mat3_apply (m: &Mat3, c: Color) -> Color {
+    Color::new(
+        m[0][0] * c.r() + m[0][1] * c.g() + m[0][2] * c.b(),
+        m[1][0] * c.r() + m[1][1] * c.g() + m[1][2] * c.b(),
+        m[2][0] * c.r() + m[2][1] * c.g() + m[2][2] * c.b(),
+    )
+}
+
+// Linear RGB working spaces the renderer can shade in. Colors given to materials and lights are interpreted in the
+// scene's working space, and output is converted from it
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub enum ColorSpace {
+    // Rec. 709 primaries, D65 white: the space of ordinary 8-bit images
+    #[default]
+    LinearSrgb,
+    // ACES AP1 primaries, ACES (~D60) white
+    AcesCg,
+    // ITU-R BT.2020 primaries, D65 white
+    Rec2020,
+}
+
+impl FromStr for ColorSpace {
+    type Err = String;
+
+    fn from_str (s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "srgb" | "linear-srgb" => Ok(ColorSpace::LinearSrgb),
+            "acescg" => Ok(ColorSpace::AcesCg),
+            "rec2020" => Ok(ColorSpace::Rec2020),
+            _ => Err(format!("unknown color space '{}'", s)),
+        }
+    }
+}
+
+impl ColorSpace {
+    pub fn name (&self) -> &'static str {
+        match self {
+            ColorSpace::LinearSrgb => "linear-srgb",
+            ColorSpace::AcesCg => "acescg",
+            ColorSpace::Rec2020 => "rec2020",
+        }
+    }
+
+    // CIE xy of the red, green and blue primaries followed by the white point, as stored in EXR headers
+    pub fn chromaticities (&self) -> [f32; 8] {
+        match self {
+            ColorSpace::LinearSrgb => [0.64, 0.33, 0.30, 0.60, 0.15, 0.06, 0.3127, 0.3290],
+            ColorSpace::AcesCg => [0.713, 0.293, 0.165, 0.830, 0.128, 0.044, 0.32168, 0.33767],
+            ColorSpace::Rec2020 => [0.708, 0.292, 0.170, 0.797, 0.131, 0.046, 0.3127, 0.3290],
+        }
+    }
+
+    // RGB to CIE XYZ relative to a D65 white. ACEScg includes a Bradford adaptation from its own white point
+    fn xyz_matrix (self) -> Mat3 {
+        match self {
+            ColorSpace::LinearSrgb => [
+                [0.412391, 0.357584, 0.180481],
+                [0.212639, 0.715169, 0.072192],
+                [0.019331, 0.119195, 0.950532],
+            ],
+            ColorSpace::AcesCg => [
+                [0.652238, 0.128236, 0.169982],
+                [0.267672, 0.674340, 0.057988],
+                [-0.005382, 0.001369, 1.093071],
+            ],
+            ColorSpace::Rec2020 => [
+                [0.636958, 0.144617, 0.168881],
+                [0.262700, 0.677998, 0.059302],
+                [0.000000, 0.028073, 1.060985],
+            ],
+        }
+    }
+
+    // CIE XYZ (D65 white) to this space
+    pub fn from_xyz (&self, xyz: Color) -> Color {
+        mat3_apply(&mat3_inverse(&self.xyz_matrix()), xyz)
+    }
+
+    const ALL: [ColorSpace; 3] = [ColorSpace::LinearSrgb, ColorSpace::AcesCg, ColorSpace::Rec2020];
+
+    // Built and inverted once for every pair of spaces, since integrators convert colors on every bounce and miss
+    fn conversion_matrix (&self, to: ColorSpace) -> Mat3 {
+        static MATRICES: OnceLock<[[Mat3; 3]; 3]> = OnceLock::new();
+        let matrices = MATRICES.get_or_init(|| {
+            ColorSpace::ALL.map(|from| ColorSpace::ALL.map(|to| mat3_mul(&mat3_inverse(&to.xyz_matrix()), &from.xyz_matrix())))
+        });
+        matrices[*self as usize][to as usize]
+    }
+
+    pub fn convert (&self, c: Color, to: ColorSpace) -> Color {
+        if *self == to {
+            return c;
+        }
+        mat3_apply(&self.conversion_matrix(to), c)
+    }
+
+    pub fn convert_image (&self, image: &Image, to: ColorSpace) -> Image {
+        if *self == to {
+            return image.clone();
+        }
+
+        let m = self.conversion_matrix(to);
+        let mut output = Image::new(image.width(), image.height());
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                output.set(x, y, mat3_apply(&m, image.get(x, y)));
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_srgb_transfer_round_trip(){
+        for i in 0..=100 {
+            let x = i as f32 / 100.0;
+            assert!((srgb_eotf(srgb_oetf(x)) - x).abs() < 1e-5);
+        }
+        assert!((srgb_oetf(0.5) - 0.7353569).abs() < 1e-5);
+        assert!((srgb_oetf(0.001) - 0.01292).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_acescg_to_srgb_matches_reference(){
+        // Known ACEScg to linear sRGB matrix, first column
+        let c = ColorSpace::AcesCg.convert(Color::new(1.0, 0.0, 0.0), ColorSpace::LinearSrgb);
+        assert!((c.r() - 1.70505).abs() < 1e-3);
+        assert!((c.g() + 0.13026).abs() < 1e-3);
+        assert!((c.b() + 0.02400).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_white_is_preserved(){
+        for space in [ColorSpace::AcesCg, ColorSpace::Rec2020] {
+            let there = ColorSpace::LinearSrgb.convert(Color::new(1.0, 1.0, 1.0), space);
+            let back = space.convert(there, ColorSpace::LinearSrgb);
+            assert!((there - Color::new(1.0, 1.0, 1.0)).length() < 1e-3);
+            assert!((back - Color::new(1.0, 1.0, 1.0)).length() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_conversions_round_trip_between_all_spaces(){
+        let c = Color::new(0.9, 0.2, 0.05);
+        for from in ColorSpace::ALL {
+            for to in ColorSpace::ALL {
+                let back = to.convert(from.convert(c, to), from);
+                assert!((back - c).length() < 1e-4, "{:?} to {:?} and back gave {:?}", from, to, back);
+            }
+        }
+        assert!((ColorSpace::Rec2020.convert(c, ColorSpace::AcesCg) - ColorSpace::Rec2020.convert(c, ColorSpace::LinearSrgb)).length() > 1e-3);
+    }
+}
@@ -1,12 +1,9 @@
 use rand::Rng;
-use rand::rngs::ThreadRng;
-
-pub struct RandomGenerator {
-    rng: ThreadRng
-}
+use rand::SeedableRng;
+use rand::rngs::{StdRng, ThreadRng};
 
 pub const INFINITY: f32 = f32::INFINITY;
-pub const PI: f32 = 3.141592;
+pub const PI: f32 = std::f32::consts::PI;
 
 pub fn degrees_to_radians (degrees: f32) -> f32 {
     degrees * PI / 180.0
@@ -24,21 +21,25 @@ pub fn random_generator_range(min: f32, max: f32) -> f32 {
     RNG.with(|rng| rng.borrow_mut().gen_range(min..max))
 }
 
-pub fn linear_to_gamma(x: f32) -> f32 {
-    if x > 0.0 {
-        return x.sqrt();
-    }
-    return 0.0
+// A generator that yields the same sequence for the same seed, for anything that must be reproducible between runs
+pub fn seeded_generator(seed: u64) -> StdRng {
+    StdRng::seed_from_u64(seed)
 }
 
 #[cfg(test)]
-
 mod tests {
     use super::*;
 
     #[test]
     pub fn generate_numbers() {
         let a: f32 = random_generator();
-        assert!(0.0 <= a && a <= 1.0);
+        assert!((0.0..=1.0).contains(&a));
+    }
+
+    #[test]
+    pub fn seeded_generator_is_deterministic() {
+        let a: f32 = seeded_generator(7).gen();
+        let b: f32 = seeded_generator(7).gen();
+        assert_eq!(a, b);
     }
 }
\ No newline at end of file
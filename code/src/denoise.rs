@@ -0,0 +1,179 @@
+use crate::color::Color;
+use crate::film::{Film, Image};
+
+// Edge-avoiding à-trous wavelet filter (Dammertz et al.) guided by the albedo, normal and depth buffers.
+// Each pass blurs with a sparse 5x5 B3-spline kernel whose taps spread twice as far as the previous pass, and every
+// tap is down-weighted by how much its color and features differ from the center pixel
+pub struct Denoiser {
+    // Number of à-trous passes; the filter footprint grows to 4 * 2^(iterations - 1) pixels
+    pub iterations: u32,
+    // Tolerances for each guide. Smaller values preserve more edges, larger values smooth more.
+    // The color term only guards against smearing strong lighting edges the feature buffers can't see
+    pub sigma_color: f32,
+    pub sigma_albedo: f32,
+    pub sigma_normal: f32,
+    pub sigma_depth: f32,
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Denoiser {
+            iterations: 5,
+            sigma_color: 1.0,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.1,
+            sigma_depth: 0.1,
+        }
+    }
+}
+
+impl Denoiser {
+    const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+    pub fn new () -> Self {
+        Denoiser::default()
+    }
+
+    // Denoises the film's beauty pass using its feature buffers
+    pub fn denoise_film (&self, film: &Film) -> Image {
+        self.denoise(&film.beauty, &film.albedo, &film.normal, &film.depth)
+    }
+
+    pub fn denoise (&self, beauty: &Image, albedo: &Image, normal: &Image, depth: &Image) -> Image {
+        let width = beauty.width();
+        let height = beauty.height();
+
+        // Filter the illumination rather than the final color so texture detail carried by the albedo stays sharp
+        let mut current = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                current.set(x, y, Denoiser::demodulate(beauty.get(x, y), albedo.get(x, y)));
+            }
+        }
+
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            current = self.atrous_pass(&current, albedo, normal, depth, step);
+        }
+
+        let mut output = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                output.set(x, y, Denoiser::remodulate(current.get(x, y), albedo.get(x, y)));
+            }
+        }
+        output
+    }
+
+    fn atrous_pass (&self, input: &Image, albedo: &Image, normal: &Image, depth: &Image, step: usize) -> Image {
+        let width = input.width() as i64;
+        let height = input.height() as i64;
+        let mut output = Image::new(input.width(), input.height());
+
+        for y in 0..height {
+            for x in 0..width {
+                let (cx, cy) = (x as usize, y as usize);
+                let center_color = input.get(cx, cy);
+                let center_albedo = albedo.get(cx, cy);
+                let center_normal = normal.get(cx, cy);
+                let center_depth = depth.get(cx, cy).r();
+
+                let mut sum = Color::new(0.0, 0.0, 0.0);
+                let mut weight_sum = 0.0;
+                for (j, ky) in Denoiser::KERNEL.iter().enumerate() {
+                    for (i, kx) in Denoiser::KERNEL.iter().enumerate() {
+                        let sx = x + (i as i64 - 2) * step as i64;
+                        let sy = y + (j as i64 - 2) * step as i64;
+                        if sx < 0 || sy < 0 || sx >= width || sy >= height {
+                            continue;
+                        }
+                        let (sx, sy) = (sx as usize, sy as usize);
+
+                        let color = input.get(sx, sy);
+                        let w_color = (-(color - center_color).length_squared() / (self.sigma_color * self.sigma_color)).exp();
+                        let w_albedo = (-(albedo.get(sx, sy) - center_albedo).length_squared() / (self.sigma_albedo * self.sigma_albedo)).exp();
+                        let w_normal = (-(normal.get(sx, sy) - center_normal).length_squared() / (self.sigma_normal * self.sigma_normal)).exp();
+                        let depth_difference = depth.get(sx, sy).r() - center_depth;
+                        // Depth differences are relative so the tolerance works at any scene scale
+                        let relative_depth = depth_difference / center_depth.abs().max(1e-3);
+                        let w_depth = (-(relative_depth * relative_depth) / (self.sigma_depth * self.sigma_depth)).exp();
+
+                        let weight = kx * ky * w_color * w_albedo * w_normal * w_depth;
+                        sum = sum + weight * color;
+                        weight_sum += weight;
+                    }
+                }
+
+                output.set(cx, cy, if weight_sum > 0.0 { sum / weight_sum } else { center_color });
+            }
+        }
+        output
+    }
+
+    // Divides the albedo out of a color, leaving pixels with a near black albedo untouched
+    fn demodulate (color: Color, albedo: Color) -> Color {
+        let divide = |c: f32, a: f32| if a > 1e-3 { c / a } else { c };
+        Color::new(divide(color.r(), albedo.r()), divide(color.g(), albedo.g()), divide(color.b(), albedo.b()))
+    }
+
+    fn remodulate (color: Color, albedo: Color) -> Color {
+        let multiply = |c: f32, a: f32| if a > 1e-3 { c * a } else { c };
+        Color::new(multiply(color.r(), albedo.r()), multiply(color.g(), albedo.g()), multiply(color.b(), albedo.b()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::constants::random_generator;
+
+    fn flat (width: usize, height: usize, color: Color) -> Image {
+        let mut image = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                image.set(x, y, color);
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_denoise_reduces_noise(){
+        let (width, height) = (16, 16);
+        let mut noisy = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let v = 0.5 + (random_generator() - 0.5) * 0.4;
+                noisy.set(x, y, Color::new(v, v, v));
+            }
+        }
+        let albedo = flat(width, height, Color::new(0.5, 0.5, 0.5));
+        let normal = flat(width, height, Color::new(0.0, 1.0, 0.0));
+        let depth = flat(width, height, Color::new(2.0, 2.0, 2.0));
+
+        let error = |image: &Image| image.pixels().iter().map(|c| (c.r() - 0.5).powi(2)).sum::<f32>();
+        let denoised = Denoiser::new().denoise(&noisy, &albedo, &normal, &depth);
+        assert!(error(&denoised) < 0.25 * error(&noisy));
+    }
+
+    #[test]
+    fn test_denoise_keeps_normal_edges(){
+        // Left and right halves face different ways and are lit differently; the edge between them must survive
+        let (width, height) = (16, 8);
+        let mut beauty = Image::new(width, height);
+        let mut normal = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let left = x < width / 2;
+                beauty.set(x, y, if left { Color::new(0.1, 0.1, 0.1) } else { Color::new(0.9, 0.9, 0.9) });
+                normal.set(x, y, if left { Color::new(1.0, 0.0, 0.0) } else { Color::new(0.0, 1.0, 0.0) });
+            }
+        }
+        let albedo = flat(width, height, Color::new(1.0, 1.0, 1.0));
+        let depth = flat(width, height, Color::new(1.0, 1.0, 1.0));
+
+        let denoised = Denoiser::new().denoise(&beauty, &albedo, &normal, &depth);
+        assert!((denoised.get(width / 2 - 1, 4).r() - 0.1).abs() < 1e-3);
+        assert!((denoised.get(width / 2, 4).r() - 0.9).abs() < 1e-3);
+    }
+}
@@ -0,0 +1,123 @@
+use std::io::{self, Write};
+
+// One named channel of 32-bit float samples, row by row from the top
+pub struct ExrChannel {
+    name: String,
+    samples: Vec<f32>,
+}
+
+impl ExrChannel {
+    pub fn new (name: &str, samples: Vec<f32>) -> Self {
+        ExrChannel { name: name.to_string(), samples }
+    }
+}
+
+fn write_attribute (out: &mut Vec<u8>, name: &str, kind: &str, value: &[u8]) {
+    out.extend_from_slice(name.as_bytes());
+    out.push(0);
+    out.extend_from_slice(kind.as_bytes());
+    out.push(0);
+    out.extend_from_slice(&(value.len() as i32).to_le_bytes());
+    out.extend_from_slice(value);
+}
+
+// Writes a single-part, scanline, uncompressed OpenEXR file with FLOAT channels. `chromaticities` tags the RGB
+// primaries and white point of the data, as returned by ColorSpace::chromaticities
+pub fn write_exr (out: &mut dyn Write, width: usize, height: usize, mut channels: Vec<ExrChannel>, chromaticities: [f32; 8]) -> io::Result<()> {
+    // Readers expect the channel list, and therefore the pixel data, in alphabetical order
+    channels.sort_by(|a, b| a.name.cmp(&b.name));
+    for channel in &channels {
+        if channel.samples.len() != width * height {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, format!("channel {} has the wrong size", channel.name)));
+        }
+    }
+
+    let mut header: Vec<u8> = vec![];
+    // Magic number, then version 2 with no feature flags: single part, scanline, short names
+    header.extend_from_slice(&[0x76, 0x2f, 0x31, 0x01]);
+    header.extend_from_slice(&2_u32.to_le_bytes());
+
+    let mut chlist: Vec<u8> = vec![];
+    for channel in &channels {
+        chlist.extend_from_slice(channel.name.as_bytes());
+        chlist.push(0);
+        // Pixel type FLOAT, pLinear, three reserved bytes, then x and y sampling
+        chlist.extend_from_slice(&2_i32.to_le_bytes());
+        chlist.extend_from_slice(&[0, 0, 0, 0]);
+        chlist.extend_from_slice(&1_i32.to_le_bytes());
+        chlist.extend_from_slice(&1_i32.to_le_bytes());
+    }
+    chlist.push(0);
+    write_attribute(&mut header, "channels", "chlist", &chlist);
+    write_attribute(&mut header, "compression", "compression", &[0]);
+
+    let chromaticities: Vec<u8> = chromaticities.iter().flat_map(|value| value.to_le_bytes()).collect();
+    write_attribute(&mut header, "chromaticities", "chromaticities", &chromaticities);
+
+    let mut window: Vec<u8> = vec![];
+    for value in [0, 0, width as i32 - 1, height as i32 - 1] {
+        window.extend_from_slice(&value.to_le_bytes());
+    }
+    write_attribute(&mut header, "dataWindow", "box2i", &window);
+    write_attribute(&mut header, "displayWindow", "box2i", &window);
+    write_attribute(&mut header, "lineOrder", "lineOrder", &[0]);
+    write_attribute(&mut header, "pixelAspectRatio", "float", &1.0_f32.to_le_bytes());
+    write_attribute(&mut header, "screenWindowCenter", "v2f", &[0; 8]);
+    write_attribute(&mut header, "screenWindowWidth", "float", &1.0_f32.to_le_bytes());
+    header.push(0);
+
+    // Without compression every block is one scanline: y, byte count, then each channel's samples
+    let line_size = channels.len() * width * 4;
+    let block_size = 8 + line_size;
+    let first_block = header.len() + height * 8;
+
+    out.write_all(&header)?;
+    for y in 0..height {
+        out.write_all(&((first_block + y * block_size) as u64).to_le_bytes())?;
+    }
+    for y in 0..height {
+        out.write_all(&(y as i32).to_le_bytes())?;
+        out.write_all(&(line_size as i32).to_le_bytes())?;
+        for channel in &channels {
+            for sample in &channel.samples[y * width..(y + 1) * width] {
+                out.write_all(&sample.to_le_bytes())?;
+            }
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_write_exr_layout(){
+        let channels = vec![
+            ExrChannel::new("G", vec![2.0; 6]),
+            ExrChannel::new("B", vec![3.0; 6]),
+        ];
+
+        let mut out: Vec<u8> = vec![];
+        write_exr(&mut out, 3, 2, channels, [0.0; 8]).unwrap();
+
+        assert_eq!(&out[0..4], &[0x76, 0x2f, 0x31, 0x01]);
+
+        // The offset table points at the first scanline block
+        let line_size = 2 * 3 * 4;
+        let total = out.len();
+        let first_block = total - 2 * (8 + line_size);
+        let offset = u64::from_le_bytes(out[first_block - 16..first_block - 8].try_into().unwrap());
+        assert_eq!(offset as usize, first_block);
+
+        // Channels are sorted, so B comes before G in each block
+        let first_sample = f32::from_le_bytes(out[first_block + 8..first_block + 12].try_into().unwrap());
+        assert_eq!(first_sample, 3.0);
+    }
+
+    #[test]
+    fn test_write_exr_rejects_wrong_size(){
+        let mut out: Vec<u8> = vec![];
+        assert!(write_exr(&mut out, 2, 2, vec![ExrChannel::new("R", vec![0.0; 3])], [0.0; 8]).is_err());
+    }
+}
@@ -0,0 +1,181 @@
+use crate::color::Color;
+use crate::exr::{ExrChannel, write_exr};
+use crate::colorspace::ColorSpace;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+// A linear, floating point RGB image stored row by row from the top
+#[derive(Clone, Debug, PartialEq)]
+pub struct Image {
+    width: usize,
+    height: usize,
+    pixels: Vec<Color>,
+}
+
+impl Image {
+    pub fn new (width: usize, height: usize) -> Self {
+        Image { width, height, pixels: vec![Color::new(0.0, 0.0, 0.0); width * height] }
+    }
+
+    pub fn width (&self) -> usize {
+        self.width
+    }
+
+    pub fn height (&self) -> usize {
+        self.height
+    }
+
+    pub fn pixels (&self) -> &Vec<Color> {
+        &self.pixels
+    }
+
+    pub fn get (&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    pub fn set (&mut self, x: usize, y: usize, color: Color) {
+        self.pixels[y * self.width + x] = color;
+    }
+
+    // Plain-text PPM, gamma corrected and quantized to 8 bits through Color's Display
+    pub fn write_ppm (&self, out: &mut dyn Write) -> io::Result<()> {
+        writeln!(out, "P3\n{} {} \n255\n", self.width, self.height)?;
+        for pixel in &self.pixels {
+            writeln!(out, "{}", pixel)?;
+        }
+        Ok(())
+    }
+
+    // Portable float map: linear 32-bit RGB, little endian, rows stored bottom to top
+    pub fn write_pfm (&self, out: &mut dyn Write) -> io::Result<()> {
+        write!(out, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        for y in (0..self.height).rev() {
+            for x in 0..self.width {
+                let c = self.get(x, y);
+                for value in [c.r(), c.g(), c.b()] {
+                    out.write_all(&value.to_le_bytes())?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+// The beauty image plus the auxiliary buffers (AOVs) gathered during the same render
+pub struct Film {
+    pub beauty: Image,
+    // First-hit material albedo
+    pub albedo: Image,
+    // First-hit shading normal, in world space
+    pub normal: Image,
+    // Linear depth along the camera's forward axis, stored in every channel
+    pub depth: Image,
+    // Light reaching the camera after at most one surface interaction
+    pub direct: Image,
+    // Light reaching the camera after two or more surface interactions
+    pub indirect: Image,
+    // Contribution of each scene light, followed by the environment
+    pub lights: Vec<Image>,
+    // Working space all of the color buffers are expressed in
+    pub color_space: ColorSpace,
+}
+
+impl Film {
+    pub fn new (width: usize, height: usize, light_count: usize) -> Self {
+        Film {
+            beauty: Image::new(width, height),
+            albedo: Image::new(width, height),
+            normal: Image::new(width, height),
+            depth: Image::new(width, height),
+            direct: Image::new(width, height),
+            indirect: Image::new(width, height),
+            lights: (0..=light_count).map(|_| Image::new(width, height)).collect(),
+            color_space: ColorSpace::default(),
+        }
+    }
+
+    // Every buffer with the name it is written under
+    pub fn layers (&self) -> Vec<(String, &Image)> {
+        let mut layers = vec![
+            ("beauty".to_string(), &self.beauty),
+            ("albedo".to_string(), &self.albedo),
+            ("normal".to_string(), &self.normal),
+            ("depth".to_string(), &self.depth),
+            ("direct".to_string(), &self.direct),
+            ("indirect".to_string(), &self.indirect),
+        ];
+        let environment = self.lights.len() - 1;
+        for (index, image) in self.lights.iter().enumerate() {
+            let name = if index == environment { "light_environment".to_string() } else { format!("light{}", index) };
+            layers.push((name, image));
+        }
+        layers
+    }
+
+    // Writes each layer to `<dir>/<layer>.pfm`, in the film's color space
+    pub fn write_layers (&self, dir: &Path) -> io::Result<()> {
+        std::fs::create_dir_all(dir)?;
+        for (name, image) in self.layers() {
+            let mut out = BufWriter::new(File::create(dir.join(format!("{}.pfm", name)))?);
+            image.write_pfm(&mut out)?;
+        }
+        Ok(())
+    }
+
+    // Writes every layer into one multi-channel OpenEXR file. The beauty pass uses the default R, G, B channels
+    // and the others are prefixed with their layer name, e.g. albedo.R. Depth is written once as depth.Z.
+    // The file is tagged with the chromaticities of the film's color space
+    pub fn write_exr (&self, path: &Path) -> io::Result<()> {
+        let mut channels: Vec<ExrChannel> = vec![];
+        for (name, image) in self.layers() {
+            if name == "depth" {
+                channels.push(ExrChannel::new("depth.Z", image.pixels().iter().map(|c| c.r()).collect()));
+                continue;
+            }
+
+            let prefix = if name == "beauty" { String::new() } else { format!("{}.", name) };
+            channels.push(ExrChannel::new(&format!("{}R", prefix), image.pixels().iter().map(|c| c.r()).collect()));
+            channels.push(ExrChannel::new(&format!("{}G", prefix), image.pixels().iter().map(|c| c.g()).collect()));
+            channels.push(ExrChannel::new(&format!("{}B", prefix), image.pixels().iter().map(|c| c.b()).collect()));
+        }
+
+        let mut out = BufWriter::new(File::create(path)?);
+        write_exr(&mut out, self.beauty.width(), self.beauty.height(), channels, self.color_space.chromaticities())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_image_write_ppm(){
+        let mut image = Image::new(2, 1);
+        image.set(1, 0, Color::new(1.0, 1.0, 1.0));
+
+        let mut out: Vec<u8> = vec![];
+        image.write_ppm(&mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "P3\n2 1 \n255\n\n0 0 0\n255 255 255\n");
+    }
+
+    #[test]
+    fn test_image_write_pfm_bottom_up(){
+        let mut image = Image::new(1, 2);
+        image.set(0, 0, Color::new(1.0, 2.0, 3.0));
+
+        let mut out: Vec<u8> = vec![];
+        image.write_pfm(&mut out).unwrap();
+        let header = b"PF\n1 2\n-1.0\n".len();
+        assert_eq!(out.len(), header + 2 * 3 * 4);
+        // The top row comes last
+        assert_eq!(&out[header + 12..header + 16], &1.0_f32.to_le_bytes());
+    }
+
+    #[test]
+    fn test_film_layers(){
+        let film = Film::new(4, 4, 2);
+        let names: Vec<String> = film.layers().into_iter().map(|(name, _)| name).collect();
+        assert_eq!(names, vec!["beauty", "albedo", "normal", "depth", "direct", "indirect", "light0", "light1", "light_environment"]);
+    }
+}
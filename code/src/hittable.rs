@@ -1,20 +1,53 @@
-use crate::ray::Ray;
+use crate::ray::{Ray, RayDifferential};
+use crate::texture::Footprint;
 use crate::vec3::{Vec3, Point3};
 use std::sync::Arc;
-use crate::constants::{INFINITY, PI};
+use crate::constants::{INFINITY, PI, random_generator, random_generator_range};
 use crate::interval::{Interval};
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::transform::{Transform, AnimatedTransform, TransformChain};
+use crate::aabb::Aabb;
 
 
 // A hit record is a point on an object that is hit. The normal vector is captured, distance from the camera and whether or not it hit the front face
-#[derive(Default, Copy, Clone)]
+// The geometric normal comes straight from the surface, while the shading normal is the one materials light with
+// tangent and bitangent point along increasing u and v and, with the outward normal, form the frame normal maps live in
+// dpdu, dpdv, dndu and dndv are the partial derivatives of the outward surface point and normal with respect to u and v.
+// When the incoming ray carries differentials, dpdx, dpdy and the uv derivatives describe the area one pixel covers
+// u, v are surface coordinates, object_id is the index in the enclosing HittableList and primitive_id the part of the object that was hit
+#[derive(Default, Clone)]
 pub struct HitRecord {
     p: Point3,
     normal: Vec3,
+    shading_normal: Vec3,
+    tangent: Vec3,
+    bitangent: Vec3,
+    dpdu: Vec3,
+    dpdv: Vec3,
+    dndu: Vec3,
+    dndv: Vec3,
+    has_differentials: bool,
+    dpdx: Vec3,
+    dpdy: Vec3,
+    footprint: Footprint,
+    mat: Option<Arc<dyn Material>>,
     t: f32,
+    u: f32,
+    v: f32,
     front_face: bool,
+    object_id: usize,
+    primitive_id: usize,
 }
 
 impl HitRecord {
+    // A hit `t` along the ray at `p`. Surfaces go on to set their normal, uv and partials; a hit with no surface
+    // around it, such as a scattering event inside a volume, keeps an arbitrary normal
+    pub fn new (p: Point3, t: f32, mat: Arc<dyn Material>) -> Self {
+        let normal = Vec3::new(1.0, 0.0, 0.0);
+        HitRecord { p, normal, shading_normal: normal, mat: Some(mat), t, front_face: true, ..Default::default() }
+    }
+
     pub fn p(&self) -> Point3 {
         self.p
     }
@@ -23,47 +56,291 @@ impl HitRecord {
         self.normal
     }
 
+    pub fn shading_normal(&self)-> Vec3 {
+        self.shading_normal
+    }
+
+    pub fn tangent(&self)-> Vec3 {
+        self.tangent
+    }
+
+    pub fn bitangent(&self)-> Vec3 {
+        self.bitangent
+    }
+
+    pub fn dpdx(&self)-> Vec3 {
+        self.dpdx
+    }
+
+    pub fn dpdy(&self)-> Vec3 {
+        self.dpdy
+    }
+
+    // How far u and v change between neighbouring pixels; zero when the ray had no differentials
+    pub fn footprint(&self)-> Footprint {
+        self.footprint
+    }
+
+    pub fn mat(&self)-> Option<&Arc<dyn Material>> {
+        self.mat.as_ref()
+    }
+
     pub fn t(&self)-> f32 {
         self.t
     }
 
+    pub fn u(&self)-> f32 {
+        self.u
+    }
+
+    pub fn v(&self)-> f32 {
+        self.v
+    }
+
     pub fn front_face(&self)-> bool {
         self.front_face
     }
 
+    pub fn object_id(&self)-> usize {
+        self.object_id
+    }
+
+    pub fn primitive_id(&self)-> usize {
+        self.primitive_id
+    }
+
+    // Sets the geometric normal to face against the ray, and resets the shading normal to match it
     pub fn set_face_normal (&mut self, r: &Ray, outward_normal: Vec3){
         let truth = r.direction().dot(&outward_normal);
 
         self.front_face = truth < 0.0;
         self.normal = if self.front_face { outward_normal } else { -outward_normal };
+        self.shading_normal = self.normal;
+    }
+
+    pub fn set_uv (&mut self, u: f32, v: f32) {
+        self.u = u;
+        self.v = v;
+    }
+
+    pub fn set_primitive_id (&mut self, primitive_id: usize) {
+        self.primitive_id = primitive_id;
+    }
+
+    // Unit vectors along increasing u and v, expressed in the outward facing frame
+    pub fn set_tangents (&mut self, tangent: Vec3, bitangent: Vec3) {
+        self.tangent = tangent;
+        self.bitangent = bitangent;
+    }
+
+    pub fn set_partials (&mut self, dpdu: Vec3, dpdv: Vec3, dndu: Vec3, dndv: Vec3) {
+        self.dpdu = dpdu;
+        self.dpdv = dpdv;
+        self.dndu = dndu;
+        self.dndv = dndv;
+    }
+
+    // Intersects the ray's differentials with the tangent plane at p and expresses the offsets in uv, following pbrt
+    pub fn compute_differentials (&mut self, r: &Ray) {
+        self.has_differentials = false;
+        self.dpdx = Vec3::default();
+        self.dpdy = Vec3::default();
+        self.footprint = Footprint::default();
+
+        let d = match r.differentials() {
+            Some(d) => d,
+            None => return,
+        };
+        let n = self.normal;
+        let plane = n.dot(&self.p);
+        let denominator_x = n.dot(&d.rx_direction);
+        let denominator_y = n.dot(&d.ry_direction);
+        if denominator_x.abs() < 1e-12 || denominator_y.abs() < 1e-12 {
+            return;
+        }
+        let tx = (plane - n.dot(&d.rx_origin)) / denominator_x;
+        let ty = (plane - n.dot(&d.ry_origin)) / denominator_y;
+        self.dpdx = d.rx_origin + tx * d.rx_direction - self.p;
+        self.dpdy = d.ry_origin + ty * d.ry_direction - self.p;
+        self.has_differentials = true;
+
+        // Solve dp = du * dpdu + dv * dpdv in the two coordinates where the surface is least foreshortened
+        let (a0, a1) = if n.x().abs() > n.y().abs() && n.x().abs() > n.z().abs() {
+            (1, 2)
+        } else if n.y().abs() > n.z().abs() {
+            (0, 2)
+        } else {
+            (0, 1)
+        };
+        let det = self.dpdu[a0] * self.dpdv[a1] - self.dpdv[a0] * self.dpdu[a1];
+        if det.abs() < 1e-12 {
+            return;
+        }
+        let solve = |dp: Vec3| {
+            let du = (self.dpdv[a1] * dp[a0] - self.dpdv[a0] * dp[a1]) / det;
+            let dv = (self.dpdu[a0] * dp[a1] - self.dpdu[a1] * dp[a0]) / det;
+            (du, dv)
+        };
+        let (dudx, dvdx) = solve(self.dpdx);
+        let (dudy, dvdy) = solve(self.dpdy);
+        self.footprint = Footprint { dudx, dvdx, dudy, dvdy };
+    }
+
+    // Normal derivatives in x and y, turned to face the incoming ray like the shading normal
+    fn normal_differentials (&self) -> (Vec3, Vec3) {
+        let f = &self.footprint;
+        let dndx = self.dndu * f.dudx + self.dndv * f.dvdx;
+        let dndy = self.dndu * f.dudy + self.dndv * f.dvdy;
+        if self.front_face { (dndx, dndy) } else { (-dndx, -dndy) }
+    }
+
+    // Differentials of a perfect mirror reflection of `r_in` into `wi`, including the spread added by surface curvature
+    pub fn reflect_differentials (&self, r_in: &Ray, wi: Vec3) -> Option<RayDifferential> {
+        let d = r_in.differentials()?;
+        if !self.has_differentials {
+            return None;
+        }
+        let n = self.shading_normal;
+        let wo = -r_in.direction().unit_vector();
+        let wi = wi.unit_vector();
+        let (dndx, dndy) = self.normal_differentials();
+
+        let spread = |direction: Vec3, dndx: Vec3| {
+            let dwodx = -direction.unit_vector() - wo;
+            let d_dn_dx = dwodx.dot(&n) + wo.dot(&dndx);
+            wi - dwodx + 2.0 * (wo.dot(&n) * dndx + d_dn_dx * n)
+        };
+        Some(RayDifferential {
+            rx_origin: self.p + self.dpdx,
+            rx_direction: spread(d.rx_direction, dndx),
+            ry_origin: self.p + self.dpdy,
+            ry_direction: spread(d.ry_direction, dndy),
+        })
+    }
+
+    // Differentials of `r_in` refracted into `wi`, where `eta` is the ratio of indices across the surface
+    pub fn refract_differentials (&self, r_in: &Ray, wi: Vec3, eta: f32) -> Option<RayDifferential> {
+        let d = r_in.differentials()?;
+        if !self.has_differentials {
+            return None;
+        }
+        let n = self.shading_normal;
+        let wo = -r_in.direction().unit_vector();
+        let wi = wi.unit_vector();
+        let (dndx, dndy) = self.normal_differentials();
+
+        let mu = eta * wo.dot(&n) - wi.dot(&n).abs();
+        let spread = |direction: Vec3, dndx: Vec3| {
+            let dwodx = -direction.unit_vector() - wo;
+            let d_dn_dx = dwodx.dot(&n) + wo.dot(&dndx);
+            let dmudx = (eta - (eta * eta * wo.dot(&n)) / wi.dot(&n).abs().max(1e-6)) * d_dn_dx;
+            wi - eta * dwodx + (mu * dndx + dmudx * n)
+        };
+        Some(RayDifferential {
+            rx_origin: self.p + self.dpdx,
+            rx_direction: spread(d.rx_direction, dndx),
+            ry_origin: self.p + self.dpdy,
+            ry_direction: spread(d.ry_direction, dndy),
+        })
+    }
+
+    // Replaces the shading normal, flipping it onto the same side as the geometric normal. The geometric normal is
+    // left alone so rays still leave from the correct side of the surface
+    pub fn set_shading_normal (&mut self, n: Vec3) {
+        let n = n.unit_vector();
+        self.shading_normal = if n.dot(&self.normal) < 0.0 { -n } else { n };
     }
 }
 
 // Hittable is a trait implemented for all objects that can be hit by a ray
-pub trait Hittable {
+pub trait Hittable: Send + Sync {
     fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord>;
+
+    // Solid-angle density of sampling `direction` from `origin` with `random`. Objects that can't be sampled report zero
+    fn pdf_value (&self, _origin: Point3, _direction: Vec3) -> f32 {
+        0.0
+    }
+
+    // A direction from `origin` towards a random point on the object
+    fn random (&self, _origin: Point3) -> Vec3 {
+        Vec3::new(1.0, 0.0, 0.0)
+    }
+
+    // A box the object stays inside over the whole shutter interval. Objects that don't know their extent are unbounded
+    fn bounding_box (&self) -> Aabb {
+        Aabb::universe()
+    }
 } 
 
 // Sphere is an example of a hittable object
 pub struct Sphere {
-    center: Point3,
+    // The center moves along this ray, from its origin at time 0 to origin + direction at time 1
+    center: Ray,
     radius: f32,
+    mat: Arc<dyn Material>,
 }
 
 impl Sphere {
-    pub fn new (center: Point3, radius: f32) -> Self{
+    pub fn new (center: Point3, radius: f32, mat: Arc<dyn Material>) -> Self{
+        Self {
+            center: Ray::new(center, Vec3::default()),
+            radius,
+            mat,
+        }
+    }
+
+    // A sphere moving at constant speed from `center0` at time 0 to `center1` at time 1
+    pub fn moving (center0: Point3, center1: Point3, radius: f32, mat: Arc<dyn Material>) -> Self {
         Self {
-            center,
-            radius
+            center: Ray::new(center0, center1 - center0),
+            radius,
+            mat,
         }
     }
+
+    // Spherical coordinates of a point on the unit sphere: u is the angle around the Y axis from X = -1, v the angle from Y = -1 to Y = +1
+    fn get_sphere_uv (p: &Point3) -> (f32, f32) {
+        let theta = (-p.y()).acos();
+        let phi = (-p.z()).atan2(p.x()) + PI;
+
+        (phi / (2.0 * PI), theta / PI)
+    }
+
+    // Directions of increasing u and v at a point on the unit sphere. At the poles, where u is undefined, any
+    // perpendicular frame is used
+    fn get_sphere_tangents (p: &Point3) -> (Vec3, Vec3) {
+        let around = Vec3::new(p.z(), 0.0, -p.x());
+        let tangent = if around.length_squared() > 1e-12 { around.unit_vector() } else { Onb::new(p).u() };
+        (tangent, p.cross(&tangent))
+    }
+
+    // Derivatives of a point on the unit sphere with respect to u and v, which are also the derivatives of the normal
+    fn get_sphere_partials (p: &Point3) -> (Vec3, Vec3) {
+        let dpdu = 2.0 * PI * Vec3::new(p.z(), 0.0, -p.x());
+        let sin_theta = (p.x() * p.x() + p.z() * p.z()).sqrt().max(1e-6);
+        let dpdv = PI * Vec3::new(-p.y() * p.x() / sin_theta, sin_theta, -p.y() * p.z() / sin_theta);
+        (dpdu, dpdv)
+    }
+
+    // Uniform direction inside the cone subtended by a sphere of `radius` at squared distance `distance_squared`, about +z
+    fn random_to_sphere (radius: f32, distance_squared: f32) -> Vec3 {
+        let r1 = random_generator();
+        let r2 = random_generator();
+        let z = 1.0 + r2 * ((1.0 - radius * radius / distance_squared).sqrt() - 1.0);
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * (1.0 - z * z).sqrt();
+        let y = phi.sin() * (1.0 - z * z).sqrt();
+
+        Vec3::new(x, y, z)
+    }
 }
 
 impl Hittable for Sphere {
     fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
-        let oc = r.origin() - self.center;
+        let center = self.center.at(r.time());
+        let oc = r.origin() - center;
         let a = r.direction().length_squared();
-        let b = 2.0 * oc.dot(&r.direction());
         let h = r.direction().dot(&oc);
 
         let c = oc.length_squared() - self.radius * self.radius;
@@ -73,26 +350,158 @@ impl Hittable for Sphere {
 
         if discriminant > 0.0 {
             let root = discriminant.sqrt();
-            let temp = (-h - root) / a;
+            // Try the near root first, then the far one for rays that start inside the sphere
+            let mut temp = (-h - root) / a;
+            if !ray_t.surrounds(temp) {
+                temp = (-h + root) / a;
+            }
             if ray_t.surrounds(temp) {
                 let p = r.at(temp);
-                let normal = (p - self.center) / self.radius;
-                let front_face = r.direction().dot(&normal) < 0.0;
-                
+                let normal = (p - center) / self.radius;
+                let (u, v) = Sphere::get_sphere_uv(&normal);
 
                 let mut hit_record = HitRecord{
                     p,
-                    normal: normal,
+                    normal,
+                    mat: Some(self.mat.clone()),
                     t: temp,
-                    front_face,
+                    u,
+                    v,
+                    ..Default::default()
                 };
                 hit_record.set_face_normal(&r, normal);
+                let (tangent, bitangent) = Sphere::get_sphere_tangents(&normal);
+                hit_record.set_tangents(tangent, bitangent);
+                let (dndu, dndv) = Sphere::get_sphere_partials(&normal);
+                hit_record.set_partials(self.radius * dndu, self.radius * dndv, dndu, dndv);
                 *rec = hit_record.clone();
                 return Some(hit_record);
             }
         }
         None
     }
+
+    fn pdf_value (&self, origin: Point3, direction: Vec3) -> f32 {
+        let mut rec = HitRecord::default();
+        if self.hit(Ray::new(origin, direction), Interval::new(0.001, INFINITY), &mut rec).is_none() {
+            return 0.0;
+        }
+
+        // Lights are sampled where they are at time 0
+        let distance_squared = (self.center.origin() - origin).length_squared();
+        let cos_theta_max = (1.0 - self.radius * self.radius / distance_squared).sqrt();
+        let solid_angle = 2.0 * PI * (1.0 - cos_theta_max);
+
+        1.0 / solid_angle
+    }
+
+    fn random (&self, origin: Point3) -> Vec3 {
+        let direction = self.center.origin() - origin;
+        let uvw = Onb::new(&direction);
+        uvw.transform(&Sphere::random_to_sphere(self.radius, direction.length_squared()))
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        let extent = Vec3::new(self.radius, self.radius, self.radius);
+        let box_at = |center: Point3| Aabb::new(center - extent, center + extent);
+        box_at(self.center.at(0.0)).union(&box_at(self.center.at(1.0)))
+    }
+}
+
+// A hittable placed in the scene through a transform: rays are carried into the object's own space, hit there, and
+// the hit is carried back out. Instances share the object, so geometry can be repeated many times at the cost of
+// one transform each
+pub struct Instance {
+    object: Arc<dyn Hittable>,
+    // Placement at time 0, which is also where lights are sampled
+    to_world: Transform,
+    to_object: Transform,
+    // Set for instances that move during the shutter interval
+    motion: Option<TransformChain>,
+}
+
+impl Instance {
+    pub fn new (object: Arc<dyn Hittable>, transform: Transform) -> Self {
+        Instance { object, to_world: transform, to_object: transform.inverse(), motion: None }
+    }
+
+    // An instance whose placement follows `motion` through the shutter interval, e.g. a spinning wheel
+    pub fn animated (object: Arc<dyn Hittable>, motion: AnimatedTransform) -> Self {
+        Instance::chained(object, TransformChain::new().with_motion(motion))
+    }
+
+    // An instance placed by several transforms that move independently, such as the nodes of an animated scene graph
+    pub fn chained (object: Arc<dyn Hittable>, chain: TransformChain) -> Self {
+        let transform = chain.at(0.0);
+        Instance { object, to_world: transform, to_object: transform.inverse(), motion: Some(chain) }
+    }
+}
+
+impl Hittable for Instance {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        let (to_world, to_object) = match &self.motion {
+            Some(motion) => {
+                let transform = motion.at(r.time());
+                (transform, transform.inverse())
+            }
+            None => (self.to_world, self.to_object),
+        };
+        let mut object_rec = HitRecord::default();
+        let mut hit_record = self.object.hit(to_object.ray(&r), ray_t, &mut object_rec)?;
+
+        // t carries over unchanged since the ray direction wasn't renormalized. Front facing doesn't change either:
+        // transformed normals and directions keep the sign of their dot product
+        let to_world = &to_world;
+        let unit = |v: Vec3| if v.length_squared() > 0.0 { v.unit_vector() } else { v };
+        hit_record.p = to_world.point(&hit_record.p);
+        hit_record.normal = unit(to_world.normal(&hit_record.normal));
+        hit_record.shading_normal = unit(to_world.normal(&hit_record.shading_normal));
+        hit_record.tangent = unit(to_world.vector(&hit_record.tangent));
+        hit_record.bitangent = unit(to_world.vector(&hit_record.bitangent));
+        hit_record.dpdu = to_world.vector(&hit_record.dpdu);
+        hit_record.dpdv = to_world.vector(&hit_record.dpdv);
+        hit_record.dndu = to_world.normal(&hit_record.dndu);
+        hit_record.dndv = to_world.normal(&hit_record.dndv);
+        hit_record.compute_differentials(&r);
+
+        *rec = hit_record.clone();
+        Some(hit_record)
+    }
+
+    // Directions map to directions under the linear part L of the inverse transform, and the sphere of directions is
+    // stretched by |det L| / |L w|^3 at w, which converts the object's density into the world's
+    fn pdf_value (&self, origin: Point3, direction: Vec3) -> f32 {
+        let object_direction = self.to_object.vector(&direction.unit_vector());
+        let object_pdf = self.object.pdf_value(self.to_object.point(&origin), object_direction);
+        let stretch = self.to_object.matrix().linear_determinant().abs() / object_direction.length().powi(3);
+        object_pdf * stretch
+    }
+
+    fn random (&self, origin: Point3) -> Vec3 {
+        self.to_world.vector(&self.object.random(self.to_object.point(&origin)))
+    }
+
+    // The object's box carried into the world at both ends of the motion. Corners move in straight lines unless the
+    // instance rotates or several of its transforms move, and then the path in between is followed in small steps
+    fn bounding_box (&self) -> Aabb {
+        let object_box = self.object.bounding_box();
+        if !object_box.is_finite() {
+            return object_box;
+        }
+        let box_at = |transform: Transform| {
+            object_box.corners().iter().fold(Aabb::empty(), |bounds, corner| {
+                let p = transform.point(corner);
+                bounds.union(&Aabb::new(p, p))
+            })
+        };
+        match &self.motion {
+            None => box_at(self.to_world),
+            Some(motion) => {
+                let steps = if motion.moves_in_straight_lines() { 1 } else { 32 };
+                (0..=steps).fold(Aabb::empty(), |bounds, i| bounds.union(&box_at(motion.at(i as f32 / steps as f32))))
+            }
+        }
+    }
 }
 
 // A hittable list is a list of objects which implement the hittable trait
@@ -125,35 +534,57 @@ impl Hittable for HittableList {
         let mut hit_anything: bool = false;
         let mut closest_so_far = ray_t.max();
 
-        for object in &self.objects {
+        for (index, object) in self.objects.iter().enumerate() {
             let interval: Interval = Interval::new(ray_t.min(), closest_so_far);
             if let Some(hit) = object.hit(r, interval, &mut temp_rec) { 
                 hit_anything = true;
                 closest_so_far = hit.t();
                 
+                temp_rec.object_id = index;
                 *rec = temp_rec.clone();
             } 
         }
         
         if hit_anything {
-            Some(*rec) 
+            Some(rec.clone()) 
         } else {
             None
         }
     }
+
+    fn pdf_value (&self, origin: Point3, direction: Vec3) -> f32 {
+        if self.objects.is_empty() {
+            return 0.0;
+        }
+
+        let weight = 1.0 / self.objects.len() as f32;
+        self.objects.iter().map(|object| weight * object.pdf_value(origin, direction)).sum()
+    }
+
+    fn random (&self, origin: Point3) -> Vec3 {
+        let size = self.objects.len();
+        let index = (random_generator_range(0.0, size as f32) as usize).min(size - 1);
+        self.objects[index].random(origin)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        self.objects.iter().fold(Aabb::empty(), |bounds, object| bounds.union(&object.bounding_box()))
+    }
 }
 
 
 #[cfg(test)]
-
-mod tests {
+pub(crate) mod tests {
     use super::*;
 
+    use crate::color::Color;
+    use crate::material::Lambertian;
+
     #[test]
     fn test_hittable_sphere(){
         let center = Point3::new(0.0, 0.0, -1.0);
         let radius = 0.5;
-        let sphere = Sphere::new(center, radius);
+        let sphere = Sphere::new(center, radius, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
 
         let origin = Point3::new(0.0, 0.0, 0.0);
         let direction = Vec3::new(0.0, 0.0, -1.0);
@@ -163,8 +594,10 @@ mod tests {
         let mut rec = HitRecord {
             p: Point3::new(0.0, 0.0, 0.0),
             normal: Vec3::new(0.0, 0.0, 0.0),
+            mat: None,
             t: 0.0,
             front_face: false,
+            ..Default::default()
         };
 
         let hit = sphere.hit(ray, ray_t, &mut rec);
@@ -174,4 +607,224 @@ mod tests {
         assert!((hit_record.t - 0.5).abs() < 1e-6);
         assert!((hit_record.p - Point3::new(0.0, 0.0, -0.5)).length() < 1e-6);
     }
-}
\ No newline at end of file
+
+    fn gray () -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    // Integrates an object's density over a Fibonacci lattice of directions from `origin`, which covers the sphere
+    // evenly so small targets don't make the result noisy. Shared with the tests of other shapes
+    pub(crate) fn integrate_pdf (object: &dyn Hittable, origin: Point3) -> f32 {
+        let n = 200000;
+        let golden_angle = PI * (3.0 - 5f32.sqrt());
+        (0..n).map(|i| {
+            let z = 1.0 - (2 * i + 1) as f32 / n as f32;
+            let (sin_phi, cos_phi) = (i as f32 * golden_angle).sin_cos();
+            let radius = (1.0 - z * z).sqrt();
+            object.pdf_value(origin, Vec3::new(radius * cos_phi, radius * sin_phi, z))
+        }).sum::<f32>() * 4.0 * PI / n as f32
+    }
+
+    #[test]
+    fn test_instance_stretches_sphere_into_ellipsoid(){
+        let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::default(), 1.0, gray()));
+        let ellipsoid = Instance::new(sphere, Transform::translate(Vec3::new(0.0, 0.0, -5.0)) * Transform::scale(Vec3::new(2.0, 1.0, 1.0)));
+
+        let mut rec = HitRecord::default();
+        let side = Ray::new(Point3::new(5.0, 0.0, -5.0), Vec3::new(-1.0, 0.0, 0.0));
+        let hit = ellipsoid.hit(side, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        assert!((hit.t() - 3.0).abs() < 1e-4);
+        assert!((hit.normal() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-4);
+        assert!(hit.front_face());
+
+        // On x^2 / 4 + y^2 = 1 the normal follows the gradient (x / 4, y), not the direction from the center
+        let x = 2.0_f32.sqrt();
+        let down = Ray::new(Point3::new(x, 5.0, -5.0), Vec3::new(0.0, -2.0, 0.0));
+        let hit = ellipsoid.hit(down, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        let y = 0.5_f32.sqrt();
+        assert!((hit.p() - Point3::new(x, y, -5.0)).length() < 1e-4);
+        assert!((hit.t() - (5.0 - y) / 2.0).abs() < 1e-4);
+        assert!((hit.normal() - Vec3::new(x / 4.0, y, 0.0).unit_vector()).length() < 1e-4);
+        assert!(hit.tangent().dot(&hit.normal()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_instance_pdf_integrates_to_one(){
+        // A sphere light squashed and turned still has a density over directions that integrates to one
+        let sphere: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::default(), 1.0, gray()));
+        let transform = Transform::translate(Vec3::new(0.5, 0.0, -3.0)) * Transform::rotate_z(30.0) * Transform::scale(Vec3::new(2.0, 0.5, 1.0));
+        let light = Instance::new(sphere, transform);
+        let origin = Point3::default();
+
+        let integral = integrate_pdf(&light, origin);
+        assert!((integral - 1.0).abs() < 0.03, "integrated to {}", integral);
+
+        for _ in 0..100 {
+            let d = light.random(origin);
+            assert!(light.pdf_value(origin, d) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_moving_sphere_follows_ray_time(){
+        let sphere = Sphere::moving(Point3::new(0.0, 0.0, -3.0), Point3::new(2.0, 0.0, -3.0), 0.5, gray());
+        let r = Ray::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+        assert!(sphere.hit(r, Interval::new(0.0001, INFINITY), &mut rec).is_none());
+        let hit = sphere.hit(r.with_time(0.5), Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        assert!((hit.t() - 2.5).abs() < 1e-4);
+        assert!((hit.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-4);
+
+        let bounds = sphere.bounding_box();
+        assert_eq!(bounds, Aabb::new(Point3::new(-0.5, -0.5, -3.5), Point3::new(2.5, 0.5, -2.5)));
+    }
+
+    #[test]
+    fn test_animated_instance_moves_and_stays_in_bounds(){
+        use crate::transform::{Pose, Quaternion};
+
+        // A rod-like ellipsoid spinning a quarter turn while sliding along x
+        let rod: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::default(), 1.0, gray()));
+        let start = Pose::new(Vec3::new(0.0, 0.0, -5.0), Quaternion::identity(), Vec3::new(2.0, 0.2, 0.2));
+        let end = Pose::new(Vec3::new(1.0, 0.0, -5.0), Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0), Vec3::new(2.0, 0.2, 0.2));
+        let instance = Instance::animated(rod, AnimatedTransform::new(start, end));
+
+        // Straight up from below the rod's far end only finds it before it turns
+        let r = Ray::new(Point3::new(1.8, -5.0, -5.0), Vec3::new(0.0, 1.0, 0.0));
+        let mut rec = HitRecord::default();
+        assert!(instance.hit(r, Interval::new(0.0001, INFINITY), &mut rec).is_some());
+        assert!(instance.hit(r.with_time(1.0), Interval::new(0.0001, INFINITY), &mut rec).is_none());
+        let upright = Ray::new(Point3::new(1.0, -5.0, -5.0), Vec3::new(0.0, 1.0, 0.0)).with_time(1.0);
+        let hit = instance.hit(upright, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        assert!((hit.p() - Point3::new(1.0, -2.0, -5.0)).length() < 1e-3);
+
+        // Points found at any time lie within the bounds
+        let bounds = instance.bounding_box();
+        for i in 0..=50 {
+            let time = i as f32 / 50.0;
+            for x in [-2.0, -1.0, 0.0, 1.0, 2.0, 3.0] {
+                let r = Ray::new(Point3::new(x, -5.0, -5.0), Vec3::new(0.0, 1.0, 0.0)).with_time(time);
+                if let Some(hit) = instance.hit(r, Interval::new(0.0001, INFINITY), &mut rec) {
+                    assert!(bounds.contains(&hit.p()), "{} outside the bounds at time {}", hit.p(), time);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_sphere_pdf_value_matches_solid_angle(){
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, -2.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let origin = Point3::new(0.0, 0.0, 0.0);
+
+        let cos_theta_max = (1.0 - 1.0 / 4.0_f32).sqrt();
+        let expected = 1.0 / (2.0 * PI * (1.0 - cos_theta_max));
+        assert!((sphere.pdf_value(origin, Vec3::new(0.0, 0.0, -1.0)) - expected).abs() < 1e-4);
+        assert_eq!(sphere.pdf_value(origin, Vec3::new(0.0, 0.0, 1.0)), 0.0);
+
+        for _ in 0..100 {
+            let d = sphere.random(origin);
+            assert!(sphere.pdf_value(origin, d) > 0.0);
+        }
+    }
+
+    #[test]
+    fn test_hittable_sphere_uv(){
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let ray = Ray::new(Point3::new(-2.0, 0.0, 0.0), Vec3::new(1.0, 0.0, 0.0));
+        let mut rec = HitRecord::default();
+
+        let hit_record = sphere.hit(ray, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        assert!(hit_record.u().abs() < 1e-6 || (hit_record.u() - 1.0).abs() < 1e-6);
+        assert!((hit_record.v() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_hittable_list_object_id(){
+        let mat = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+        let mut world = HittableList::new();
+        world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -5.0), 1.0, mat.clone())));
+        world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -2.0), 0.5, mat)));
+
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+
+        let hit_record = world.hit(ray, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        assert_eq!(hit_record.object_id(), 1);
+    }
+
+    #[test]
+    fn test_hittable_sphere_from_inside(){
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let ray = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+
+        let hit_record = sphere.hit(ray, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        assert!((hit_record.t() - 1.0).abs() < 1e-6);
+        assert!(!hit_record.front_face());
+        assert!((hit_record.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-6);
+    }
+
+    #[test]
+    fn test_hittable_sphere_tangents_follow_uv(){
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let ray = Ray::new(Point3::new(0.3, 0.2, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut rec = HitRecord::default();
+
+        let hit_record = sphere.hit(ray, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        let n = hit_record.normal();
+        assert!(hit_record.tangent().dot(&n).abs() < 1e-5);
+        assert!(hit_record.bitangent().dot(&n).abs() < 1e-5);
+
+        // Stepping along the tangent increases u, stepping along the bitangent increases v
+        let (u0, v0) = Sphere::get_sphere_uv(&n);
+        let (u1, _) = Sphere::get_sphere_uv(&(n + 0.01 * hit_record.tangent()).unit_vector());
+        let (_, v1) = Sphere::get_sphere_uv(&(n + 0.01 * hit_record.bitangent()).unit_vector());
+        assert!(u1 > u0);
+        assert!(v1 > v0);
+    }
+
+    fn differential_ray (origin: Point3, direction: Vec3, spread: f32) -> Ray {
+        Ray::new(origin, direction).with_differentials(RayDifferential {
+            rx_origin: origin,
+            rx_direction: direction + Vec3::new(spread, 0.0, 0.0),
+            ry_origin: origin,
+            ry_direction: direction + Vec3::new(0.0, spread, 0.0),
+        })
+    }
+
+    #[test]
+    fn test_differentials_footprint_on_sphere(){
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let ray = differential_ray(Point3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.01);
+        let mut rec = HitRecord::default();
+        let mut hit_record = sphere.hit(ray, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        hit_record.compute_differentials(&ray);
+
+        // The offset rays land 0.02 away at distance 2; u runs once around a circle of length 2 pi
+        assert!((hit_record.dpdx() - Vec3::new(0.02, 0.0, 0.0)).length() < 1e-4);
+        let footprint = hit_record.footprint();
+        assert!((footprint.dudx - 0.02 / (2.0 * PI)).abs() < 1e-4);
+        assert!(footprint.dvdx.abs() < 1e-5);
+        assert!((footprint.dvdy - 0.02 / PI).abs() < 1e-4);
+
+        // Rays without differentials leave the footprint empty
+        hit_record.compute_differentials(&Ray::new(Point3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0)));
+        assert!(hit_record.footprint().is_zero());
+    }
+
+    #[test]
+    fn test_reflection_differentials_spread_on_convex_mirror(){
+        let sphere = Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5))));
+        let ray = differential_ray(Point3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0), 0.01);
+        let mut rec = HitRecord::default();
+        let mut hit_record = sphere.hit(ray, Interval::new(0.0001, INFINITY), &mut rec).unwrap();
+        hit_record.compute_differentials(&ray);
+
+        let wi = ray.direction().reflect(&hit_record.normal());
+        let d = hit_record.reflect_differentials(&ray, wi).unwrap();
+        // A flat mirror would mirror the 0.01 spread; the curved one spreads it much further
+        let spread = (d.rx_direction.unit_vector() - wi.unit_vector()).length();
+        assert!(spread > 0.03);
+        assert!(d.rx_direction.x() > 0.0);
+    }
+}
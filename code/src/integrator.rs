@@ -0,0 +1,584 @@
+use crate::vec3::{Vec3, Point3};
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::constants::{INFINITY};
+use crate::interval::{Interval};
+use crate::scene::Scene;
+use crate::sampler::Sampler;
+use crate::material::ScatterRecord;
+use crate::hittable::HitRecord;
+use crate::pdf::{Pdf, HittablePdf, MixturePdf};
+use crate::colorspace::ColorSpace;
+use crate::spectrum::{SampledSpectrum, SampledWavelengths};
+use std::str::FromStr;
+
+// Safety cap on the steps of random walks inside volumes along one path, which don't count against max_depth
+const MAX_WALK_STEPS: i32 = 4096;
+
+// Auxiliary values (AOVs) gathered while estimating the radiance of one camera ray. Geometric values describe the
+// first hit and stay zero when the ray escapes
+#[derive(Clone, Debug)]
+pub struct AovSample {
+    pub hit: bool,
+    pub position: Point3,
+    pub normal: Vec3,
+    pub albedo: Color,
+    // Light reaching the camera after at most one surface interaction
+    pub direct: Color,
+    // Light reaching the camera after two or more surface interactions
+    pub indirect: Color,
+    // Contribution of each of the scene's lights, with the environment last
+    pub lights: Vec<Color>,
+}
+
+impl AovSample {
+    pub fn new (light_count: usize) -> Self {
+        AovSample {
+            hit: false,
+            position: Point3::default(),
+            normal: Vec3::default(),
+            albedo: Color::new(0.0, 0.0, 0.0),
+            direct: Color::new(0.0, 0.0, 0.0),
+            indirect: Color::new(0.0, 0.0, 0.0),
+            lights: vec![Color::new(0.0, 0.0, 0.0); light_count + 1],
+        }
+    }
+}
+
+// An integrator estimates the radiance Li arriving at the origin of a camera ray
+pub trait Integrator: Send + Sync {
+    fn li (&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color;
+
+    // Like li, but also fills in whatever AOVs the integrator can provide
+    fn li_aov (&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, _aov: &mut AovSample) -> Color {
+        self.li(r, scene, sampler)
+    }
+}
+
+// Unidirectional path tracer. Diffuse bounces are importance sampled from a mixture of the material's PDF
+// and the lights, and weighted by scattering_pdf / pdf so the estimator stays unbiased
+pub struct PathIntegrator {
+    // Safety cap on the number of bounces; paths normally end through Russian roulette
+    pub max_depth: i32,
+    // Bounces traced unconditionally before Russian roulette kicks in
+    pub russian_roulette_depth: i32,
+}
+
+impl PathIntegrator {
+    pub fn new (max_depth: i32, russian_roulette_depth: i32) -> Self {
+        PathIntegrator { max_depth, russian_roulette_depth }
+    }
+}
+
+impl Default for PathIntegrator {
+    fn default() -> Self {
+        PathIntegrator::new(50, 3)
+    }
+}
+
+impl PathIntegrator {
+    // Draws the next direction from a mixture of the material's PDF and the lights, returning the ray and its density.
+    // None when the sampled direction has zero density
+    fn sample_direction (scene: &Scene, hit_record: &HitRecord, pdf: &dyn Pdf) -> Option<(Ray, f32)> {
+        let light_pdf = HittablePdf::new(&scene.lights, hit_record.p());
+        let mixture_pdf = MixturePdf::new(&light_pdf, pdf);
+        let sampling_pdf: &dyn Pdf = if scene.lights.objects().is_empty() { pdf } else { &mixture_pdf };
+
+        let scattered = Ray::new(hit_record.p(), sampling_pdf.generate());
+        let pdf_value = sampling_pdf.value(&scattered.direction());
+        if pdf_value <= 0.0 {
+            return None;
+        }
+        Some((scattered, pdf_value))
+    }
+
+    // Adds light found `depth` surface interactions into the path to the total and to the matching AOVs
+    fn record (aov: &mut AovSample, radiance: &mut Color, depth: i32, light: Option<usize>, contribution: Color) {
+        *radiance = *radiance + contribution;
+        if depth <= 1 {
+            aov.direct = aov.direct + contribution;
+        } else {
+            aov.indirect = aov.indirect + contribution;
+        }
+        if let Some(index) = light {
+            aov.lights[index] = aov.lights[index] + contribution;
+        }
+    }
+}
+
+impl Integrator for PathIntegrator {
+    fn li (&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let mut aov = AovSample::new(scene.lights.objects().len());
+        self.li_aov(r, scene, sampler, &mut aov)
+    }
+
+    // Paths are traced iteratively, carrying the throughput of every bounce so far
+    fn li_aov (&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, aov: &mut AovSample) -> Color {
+
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = Color::new(1.0, 1.0, 1.0);
+        let mut ray = r;
+        let environment = aov.lights.len() - 1;
+
+        let mut depth = 0;
+        let mut walk_steps = 0;
+        while depth < self.max_depth {
+            let interval = Interval::new(0.0001, INFINITY);
+            let mut hit_record = match scene.hit(ray, interval) {
+                Some(hit_record) => hit_record,
+                None => {
+                    PathIntegrator::record(aov, &mut radiance, depth, Some(environment), throughput * scene.background(&ray));
+                    break;
+                }
+            };
+
+            hit_record.compute_differentials(&ray);
+
+            let mat = match hit_record.mat() {
+                Some(mat) => mat,
+                None => break,
+            };
+            if depth == 0 && walk_steps == 0 {
+                aov.hit = true;
+                aov.position = hit_record.p();
+                aov.normal = hit_record.shading_normal();
+                aov.albedo = mat.albedo(&hit_record);
+            }
+            let light = scene.light_index(hit_record.object_id());
+            PathIntegrator::record(aov, &mut radiance, depth, light, throughput * mat.emitted(&ray, &hit_record));
+
+            let mut walking = false;
+            match mat.scatter(&ray, &hit_record) {
+                None => break,
+                Some(ScatterRecord::Specular { attenuation, ray: scattered }) => {
+                    throughput = throughput * attenuation;
+                    ray = scattered;
+                }
+                Some(ScatterRecord::Walk { attenuation, ray: scattered }) => {
+                    throughput = throughput * attenuation;
+                    ray = scattered;
+                    walking = true;
+                }
+                Some(ScatterRecord::Pdf { attenuation, pdf }) => {
+                    let (scattered, pdf_value) = match PathIntegrator::sample_direction(scene, &hit_record, pdf.as_ref()) {
+                        Some(sample) => sample,
+                        None => break,
+                    };
+                    let scattering_pdf = mat.scattering_pdf(&ray, &hit_record, &scattered);
+                    throughput = throughput * attenuation * (scattering_pdf / pdf_value);
+                    ray = scattered;
+                }
+                Some(ScatterRecord::Bsdf { pdf }) => {
+                    let (scattered, pdf_value) = match PathIntegrator::sample_direction(scene, &hit_record, pdf.as_ref()) {
+                        Some(sample) => sample,
+                        None => break,
+                    };
+                    throughput = throughput * mat.eval(&ray, &hit_record, &scattered) / pdf_value;
+                    ray = scattered;
+                }
+            }
+            // The whole path happens at the instant the camera ray sampled
+            ray = ray.with_time(r.time());
+
+            // Walk steps are rouletted on the throughput alone, without the clamp used for bounces, so walks through
+            // a medium that doesn't absorb are never cut short
+            if walking {
+                walk_steps += 1;
+                let survival = throughput.max_component().min(1.0);
+                if walk_steps >= MAX_WALK_STEPS || sampler.next_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+                continue;
+            }
+
+            // Russian roulette: continue with probability equal to the throughput (bounded away from 0 and 1) and
+            // reweight survivors, so terminating low-contribution paths early adds no bias
+            if depth >= self.russian_roulette_depth {
+                let survival = throughput.max_component().clamp(0.05, 0.95);
+                if sampler.next_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+            depth += 1;
+        }
+
+        radiance
+    }
+}
+
+// Path tracer that carries a handful of wavelengths per path instead of RGB (hero wavelength sampling).
+// Material and light colors are upsampled to spectra, and each contribution is projected back to the scene's
+// working space through the CIE matching functions. Dispersive materials collapse the path to its hero wavelength
+pub struct SpectralPathIntegrator {
+    pub max_depth: i32,
+    pub russian_roulette_depth: i32,
+}
+
+impl SpectralPathIntegrator {
+    pub fn new (max_depth: i32, russian_roulette_depth: i32) -> Self {
+        SpectralPathIntegrator { max_depth, russian_roulette_depth }
+    }
+
+    fn upsample (c: Color, scene: &Scene, lambda: &SampledWavelengths) -> SampledSpectrum {
+        SampledSpectrum::from_rgb(scene.color_space.convert(c, ColorSpace::LinearSrgb), lambda)
+    }
+
+    fn to_working_space (s: SampledSpectrum, scene: &Scene, lambda: &SampledWavelengths) -> Color {
+        ColorSpace::LinearSrgb.convert(s.to_rgb(lambda), scene.color_space)
+    }
+}
+
+impl Default for SpectralPathIntegrator {
+    fn default() -> Self {
+        SpectralPathIntegrator::new(50, 3)
+    }
+}
+
+impl Integrator for SpectralPathIntegrator {
+    fn li (&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler) -> Color {
+        let mut aov = AovSample::new(scene.lights.objects().len());
+        self.li_aov(r, scene, sampler, &mut aov)
+    }
+
+    fn li_aov (&self, r: Ray, scene: &Scene, sampler: &mut dyn Sampler, aov: &mut AovSample) -> Color {
+
+        let mut lambda = SampledWavelengths::sample_uniform(sampler.next_1d());
+        let mut radiance = Color::new(0.0, 0.0, 0.0);
+        let mut throughput = SampledSpectrum::new(1.0);
+        let mut ray = r;
+        let environment = aov.lights.len() - 1;
+
+        let mut depth = 0;
+        let mut walk_steps = 0;
+        while depth < self.max_depth {
+            let interval = Interval::new(0.0001, INFINITY);
+            let mut hit_record = match scene.hit(ray, interval) {
+                Some(hit_record) => hit_record,
+                None => {
+                    let background = throughput * SpectralPathIntegrator::upsample(scene.background(&ray), scene, &lambda);
+                    let contribution = SpectralPathIntegrator::to_working_space(background, scene, &lambda);
+                    PathIntegrator::record(aov, &mut radiance, depth, Some(environment), contribution);
+                    break;
+                }
+            };
+
+            hit_record.compute_differentials(&ray);
+
+            let mat = match hit_record.mat() {
+                Some(mat) => mat,
+                None => break,
+            };
+            if depth == 0 && walk_steps == 0 {
+                aov.hit = true;
+                aov.position = hit_record.p();
+                aov.normal = hit_record.shading_normal();
+                aov.albedo = mat.albedo(&hit_record);
+            }
+            let light = scene.light_index(hit_record.object_id());
+            let emitted = throughput * SpectralPathIntegrator::upsample(mat.emitted(&ray, &hit_record), scene, &lambda);
+            let contribution = SpectralPathIntegrator::to_working_space(emitted, scene, &lambda);
+            PathIntegrator::record(aov, &mut radiance, depth, light, contribution);
+
+            // From here on the path only makes sense for the hero wavelength
+            if mat.dispersive() && !lambda.secondary_terminated() {
+                lambda.terminate_secondary();
+                ray = ray.with_wavelength(lambda.hero());
+            }
+
+            let mut walking = false;
+            match mat.scatter(&ray, &hit_record) {
+                None => break,
+                Some(ScatterRecord::Specular { attenuation, ray: scattered }) => {
+                    throughput = throughput * SpectralPathIntegrator::upsample(attenuation, scene, &lambda);
+                    ray = scattered;
+                }
+                Some(ScatterRecord::Walk { attenuation, ray: scattered }) => {
+                    throughput = throughput * SpectralPathIntegrator::upsample(attenuation, scene, &lambda);
+                    ray = scattered;
+                    walking = true;
+                }
+                Some(ScatterRecord::Pdf { attenuation, pdf }) => {
+                    let (scattered, pdf_value) = match PathIntegrator::sample_direction(scene, &hit_record, pdf.as_ref()) {
+                        Some(sample) => sample,
+                        None => break,
+                    };
+                    let scattering_pdf = mat.scattering_pdf(&ray, &hit_record, &scattered);
+                    throughput = throughput * SpectralPathIntegrator::upsample(attenuation, scene, &lambda) * (scattering_pdf / pdf_value);
+                    ray = scattered;
+                }
+                Some(ScatterRecord::Bsdf { pdf }) => {
+                    let (scattered, pdf_value) = match PathIntegrator::sample_direction(scene, &hit_record, pdf.as_ref()) {
+                        Some(sample) => sample,
+                        None => break,
+                    };
+                    let f = mat.eval(&ray, &hit_record, &scattered);
+                    throughput = throughput * SpectralPathIntegrator::upsample(f, scene, &lambda) / pdf_value;
+                    ray = scattered;
+                }
+            }
+            if lambda.secondary_terminated() {
+                ray = ray.with_wavelength(lambda.hero());
+            }
+            ray = ray.with_time(r.time());
+
+            // Walk steps are rouletted on the throughput alone, without the clamp used for bounces, so walks through
+            // a medium that doesn't absorb are never cut short
+            if walking {
+                walk_steps += 1;
+                let survival = throughput.max_component().min(1.0);
+                if walk_steps >= MAX_WALK_STEPS || sampler.next_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+                continue;
+            }
+
+            if depth >= self.russian_roulette_depth {
+                let survival = throughput.max_component().clamp(0.05, 0.95);
+                if sampler.next_1d() >= survival {
+                    break;
+                }
+                throughput = throughput / survival;
+            }
+            depth += 1;
+        }
+
+        radiance
+    }
+}
+
+// What a DebugIntegrator shows for the first surface a camera ray hits
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum DebugMode {
+    ShadingNormal,
+    GeometricNormal,
+    Depth,
+    Albedo,
+    Uv,
+    ObjectId,
+    PrimitiveId,
+}
+
+impl FromStr for DebugMode {
+    type Err = String;
+
+    fn from_str (s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "normal" | "shading-normal" => Ok(DebugMode::ShadingNormal),
+            "geometric-normal" => Ok(DebugMode::GeometricNormal),
+            "depth" => Ok(DebugMode::Depth),
+            "albedo" => Ok(DebugMode::Albedo),
+            "uv" => Ok(DebugMode::Uv),
+            "object-id" => Ok(DebugMode::ObjectId),
+            "primitive-id" => Ok(DebugMode::PrimitiveId),
+            _ => Err(format!("unknown debug mode '{}'", s)),
+        }
+    }
+}
+
+// Renders a single property of the first hit instead of light transport, for diagnosing scene and geometry bugs.
+// Rays that miss everything are black
+pub struct DebugIntegrator {
+    pub mode: DebugMode,
+    // Camera viewing direction that depth is measured along
+    pub forward: Vec3,
+    // Depth that maps to white; set to 1.0 to get raw distances
+    pub far: f32,
+}
+
+impl DebugIntegrator {
+    pub fn new (mode: DebugMode) -> Self {
+        DebugIntegrator { mode, forward: Vec3::new(0.0, 0.0, -1.0), far: 1.0 }
+    }
+
+    pub fn with_depth_range (mut self, forward: Vec3, far: f32) -> Self {
+        self.forward = forward.unit_vector();
+        self.far = far;
+        self
+    }
+
+    // Maps a unit vector from [-1, 1] into displayable [0, 1]
+    fn vector_to_color (n: Vec3) -> Color {
+        Color::new(0.5 * n.x() + 0.5, 0.5 * n.y() + 0.5, 0.5 * n.z() + 0.5)
+    }
+
+    // A stable, well-spread color per id so neighbouring ids are easy to tell apart
+    fn id_to_color (id: usize) -> Color {
+        let mut h = (id as u32).wrapping_add(1).wrapping_mul(0x9e37_79b1);
+        h ^= h >> 16;
+        h = h.wrapping_mul(0x85eb_ca6b);
+        h ^= h >> 13;
+
+        Color::new(
+            (h & 0xff) as f32 / 255.0,
+            ((h >> 8) & 0xff) as f32 / 255.0,
+            ((h >> 16) & 0xff) as f32 / 255.0,
+        )
+    }
+}
+
+impl Integrator for DebugIntegrator {
+    fn li (&self, r: Ray, scene: &Scene, _sampler: &mut dyn Sampler) -> Color {
+        let hit_record = match scene.hit(r, Interval::new(0.0001, INFINITY)) {
+            Some(hit_record) => hit_record,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+
+        match self.mode {
+            DebugMode::ShadingNormal => DebugIntegrator::vector_to_color(hit_record.shading_normal()),
+            DebugMode::GeometricNormal => DebugIntegrator::vector_to_color(hit_record.normal()),
+            DebugMode::Depth => {
+                let depth = (hit_record.p() - r.origin()).dot(&self.forward) / self.far;
+                Color::new(depth, depth, depth)
+            }
+            DebugMode::Albedo => match hit_record.mat() {
+                Some(mat) => mat.albedo(&hit_record),
+                None => Color::new(0.0, 0.0, 0.0),
+            },
+            DebugMode::Uv => Color::new(hit_record.u(), hit_record.v(), 0.0),
+            DebugMode::ObjectId => DebugIntegrator::id_to_color(hit_record.object_id()),
+            DebugMode::PrimitiveId => DebugIntegrator::id_to_color(hit_record.primitive_id()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vec3::{Vec3, Point3};
+    use crate::hittable::{HitRecord, HittableList, Sphere};
+    use crate::material::{Material, Lambertian, DiffuseLight};
+    use crate::sampler::RandomSampler;
+    use std::sync::Arc;
+
+    // A diffuse surface that also glows, so an enclosing sphere of it forms a closed furnace
+    struct GlowingLambertian {
+        lambertian: Lambertian,
+        emit: Color,
+    }
+
+    impl Material for GlowingLambertian {
+        fn emitted (&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+            self.emit
+        }
+
+        fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+            self.lambertian.scatter(r_in, rec)
+        }
+
+        fn scattering_pdf (&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
+            self.lambertian.scattering_pdf(r_in, rec, scattered)
+        }
+    }
+
+    #[test]
+    fn test_path_integrator_furnace(){
+        // Inside a closed sphere with emission E and albedo a the radiance is E / (1 - a) everywhere
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(Point3::default(), 10.0, Arc::new(GlowingLambertian {
+            lambertian: Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            emit: Color::new(1.0, 1.0, 1.0),
+        }))));
+        let scene = Scene::new(world, HittableList::default());
+        let integrator = PathIntegrator::default();
+        let mut sampler = RandomSampler::new();
+
+        let n = 20000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let r = Ray::new(Point3::default(), Vec3::random_unit_vector());
+            sum += integrator.li(r, &scene, &mut sampler).r();
+        }
+
+        assert!((sum / n as f32 - 2.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_spectral_path_integrator_furnace(){
+        // Grey materials upsample to flat spectra, so the spectral furnace must agree with the RGB one
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(Point3::default(), 10.0, Arc::new(GlowingLambertian {
+            lambertian: Lambertian::new(Color::new(0.5, 0.5, 0.5)),
+            emit: Color::new(1.0, 1.0, 1.0),
+        }))));
+        let scene = Scene::new(world, HittableList::default());
+        let integrator = SpectralPathIntegrator::default();
+        let mut sampler = RandomSampler::new();
+
+        let n = 20000;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let r = Ray::new(Point3::default(), Vec3::random_unit_vector());
+            sum = sum + integrator.li(r, &scene, &mut sampler);
+        }
+
+        let average = sum / n as f32;
+        assert!((average - Color::new(2.0, 2.0, 2.0)).length() < 0.1);
+    }
+
+    #[test]
+    fn test_path_integrator_empty_scene_is_background(){
+        let scene = Scene::default();
+        let integrator = PathIntegrator::default();
+        let mut sampler = RandomSampler::new();
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 1.0, 0.0));
+
+        assert_eq!(integrator.li(r, &scene, &mut sampler), scene.background(&r));
+    }
+
+    #[test]
+    fn test_debug_mode_from_str(){
+        assert_eq!("normal".parse::<DebugMode>(), Ok(DebugMode::ShadingNormal));
+        assert_eq!("object-id".parse::<DebugMode>(), Ok(DebugMode::ObjectId));
+        assert!("nope".parse::<DebugMode>().is_err());
+    }
+
+    #[test]
+    fn test_debug_integrator_depth_and_normal(){
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, Arc::new(Lambertian::new(Color::new(0.2, 0.4, 0.6))))));
+        let scene = Scene::new(world, HittableList::default());
+        let mut sampler = RandomSampler::new();
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0));
+
+        let depth = DebugIntegrator::new(DebugMode::Depth).li(r, &scene, &mut sampler);
+        assert!((depth.r() - 2.0).abs() < 1e-5);
+
+        let normal = DebugIntegrator::new(DebugMode::GeometricNormal).li(r, &scene, &mut sampler);
+        assert!((normal - Color::new(0.5, 0.5, 1.0)).length() < 1e-5);
+
+        let albedo = DebugIntegrator::new(DebugMode::Albedo).li(r, &scene, &mut sampler);
+        assert_eq!(albedo, Color::new(0.2, 0.4, 0.6));
+
+        let miss = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, 1.0));
+        assert_eq!(DebugIntegrator::new(DebugMode::Uv).li(miss, &scene, &mut sampler), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_path_integrator_aov_split(){
+        // A light seen directly lands in the direct pass and in its own light pass
+        let light: Arc<dyn crate::hittable::Hittable> = Arc::new(Sphere::new(Point3::new(0.0, 0.0, -3.0), 1.0, Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)))));
+        let mut world = HittableList::default();
+        world.add(light.clone());
+        let mut lights = HittableList::default();
+        lights.add(light);
+        let scene = Scene::new(world, lights);
+
+        let integrator = PathIntegrator::default();
+        let mut sampler = RandomSampler::new();
+        let mut aov = AovSample::new(1);
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0));
+
+        let radiance = integrator.li_aov(r, &scene, &mut sampler, &mut aov);
+        assert_eq!(radiance, Color::new(4.0, 4.0, 4.0));
+        assert_eq!(aov.direct, radiance);
+        assert_eq!(aov.indirect, Color::new(0.0, 0.0, 0.0));
+        assert_eq!(aov.lights[0], radiance);
+        assert!(aov.hit);
+        assert!((aov.position - Point3::new(0.0, 0.0, -2.0)).length() < 1e-5);
+        assert!((aov.normal - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-5);
+    }
+}
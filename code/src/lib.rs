@@ -0,0 +1,34 @@
+pub mod vec3;
+pub mod transform;
+pub mod aabb;
+pub mod color;
+pub mod ray;
+pub mod camera;
+pub mod hittable;
+pub mod constants;
+pub mod interval;
+pub mod onb;
+pub mod pdf;
+pub mod material;
+pub mod sampler;
+pub mod scene;
+pub mod integrator;
+pub mod film;
+pub mod exr;
+pub mod denoise;
+pub mod tonemap;
+pub mod colorspace;
+pub mod spectrum;
+pub mod texture;
+pub mod perlin;
+pub mod normalmap;
+pub mod microfacet;
+pub mod principled;
+pub mod coating;
+pub mod phase;
+pub mod subsurface;
+pub mod voxel;
+pub mod medium;
+pub mod scenegraph;
+pub mod shapes;
+pub mod quadric;
@@ -1,21 +1,17 @@
-mod vec3;
-mod color;
-mod ray;
-mod camera;
-mod hittable;
-mod constants;
-mod interval;
-// mod hittable;
-
-use vec3::{Vec3, Point3};
-use color::Color;
-use ray::Ray;
-use camera::Camera;
-use hittable::{HitRecord, HittableList, Hittable, Sphere};
-use constants::{INFINITY, PI};
-use interval::{Interval};
-// use hittable::{Sphere, HitRecord};
+use raytracer::vec3::{Vec3, Point3};
+use raytracer::color::Color;
+use raytracer::camera::Camera;
+use raytracer::hittable::{HittableList, Sphere};
+use raytracer::shapes::Plane;
+use raytracer::material::Lambertian;
+use raytracer::scene::Scene;
+use raytracer::integrator::{Integrator, PathIntegrator, SpectralPathIntegrator, DebugIntegrator, DebugMode};
+use raytracer::denoise::Denoiser;
+use raytracer::tonemap::{ToneMapper, ToneMapOperator};
+use raytracer::colorspace::ColorSpace;
+use raytracer::medium::AtmosphericFog;
 
+use std::path::Path;
 use std::sync::{Arc};
 
 // let unit_direction = r.direction().unit_vector();
@@ -25,27 +21,76 @@ use std::sync::{Arc};
 
 fn main() {
 
-    let mut debug_count = 0;
-    
-    let aspect_ratio: f32 = 16.0/9.0;
-    let image_width: i32 = 400;
+    let mut world: HittableList = HittableList::default();
+    let material = Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)));
+    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5, material.clone())));
+    world.add(Arc::new(Plane::new(Point3::new(0.0, -0.5, 0.0), Vec3::new(0.0, 1.0, 0.0), material)));
+
+    // Objects to importance sample directly. Empty means the sky is the only light
+    let lights: HittableList = HittableList::default();
+
+    let args: Vec<String> = std::env::args().collect();
+    let mut scene = Scene::new(world, lights);
+    let mut cam: Camera = Camera::new(400);
 
-    
-    let mut image_height: i32 = ((image_width as f32) / aspect_ratio) as i32;
-    if image_height < 1 {
-        image_height = 1
+    // `--color-space <space>` picks the working space: srgb (default), acescg or rec2020
+    if let Some(name) = args.iter().position(|arg| arg == "--color-space").and_then(|index| args.get(index + 1)) {
+        scene.color_space = name.parse().unwrap_or_else(|err| panic!("{}", err));
     }
 
-    let mut world: HittableList = HittableList::default();
-    world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -1.0), 0.5)));
-    world.add(Arc::new(Sphere::new(Point3::new(0.0, -100.5, -1.0), 100.0)));
+    // `--fog <density>` fills the scene with ground fog thinning out above the floor
+    if let Some(density) = args.iter().position(|arg| arg == "--fog").and_then(|index| args.get(index + 1)) {
+        let density: f32 = density.parse().expect("--fog needs a number");
+        scene.fog = Some(AtmosphericFog::new(density, Color::new(0.9, 0.9, 0.9)).with_height_falloff(-0.5, 0.5));
+    }
 
-    
-    
-    let mut cam: Camera = Camera::new(400);
-    cam.render(&mut world);
+    // `--spp <n>` overrides the samples per pixel, e.g. for quick denoised previews
+    if let Some(spp) = args.iter().position(|arg| arg == "--spp").and_then(|index| args.get(index + 1)) {
+        cam.samples_per_pixel = spp.parse().expect("--spp needs a number");
+        cam.pixel_sample_scale = 1.0 / cam.samples_per_pixel;
+    }
 
-    // println!("{}", debug_count);
+    // `--debug <mode>` swaps the path tracer for a debug view, e.g. `--debug normal` or `--debug depth`
+    let integrator: Box<dyn Integrator> = match args.iter().position(|arg| arg == "--debug") {
+        Some(index) => {
+            let mode: DebugMode = match args.get(index + 1).map(|name| name.parse()) {
+                Some(Ok(mode)) => mode,
+                Some(Err(err)) => panic!("{}", err),
+                None => panic!("--debug needs a mode"),
+            };
+            Box::new(DebugIntegrator::new(mode).with_depth_range(cam.forward(), 5.0))
+        }
+        // `--spectral` traces sampled wavelengths instead of RGB, needed for dispersion
+        None if args.iter().any(|arg| arg == "--spectral") => Box::new(SpectralPathIntegrator::default()),
+        None => Box::new(PathIntegrator::default()),
+    };
 
-}
+    let mut film = cam.render(&scene, integrator.as_ref());
+
+    // `--denoise` filters the beauty pass with the albedo, normal and depth buffers as guides
+    if args.iter().any(|arg| arg == "--denoise") {
+        film.beauty = Denoiser::new().denoise_film(&film);
+    }
+
+    // `--tonemap <operator>` and `--exposure <ev>` shape the linear image before it is quantized to 8 bits
+    let operator: ToneMapOperator = match args.iter().position(|arg| arg == "--tonemap").and_then(|index| args.get(index + 1)) {
+        Some(name) => name.parse().unwrap_or_else(|err| panic!("{}", err)),
+        None => ToneMapOperator::Clamp,
+    };
+    let exposure: f32 = match args.iter().position(|arg| arg == "--exposure").and_then(|index| args.get(index + 1)) {
+        Some(ev) => ev.parse().expect("--exposure needs a number"),
+        None => 0.0,
+    };
+    let tone_mapper = ToneMapper::new(operator, exposure);
+    let display = film.color_space.convert_image(&film.beauty, ColorSpace::LinearSrgb);
+    tone_mapper.apply(&display).write_ppm(&mut std::io::stdout().lock()).expect("failed to write image");
 
+    // `--aov <dir>` writes every buffer as a separate PFM file, `--exr <path>` writes them all as layers of one EXR
+    if let Some(dir) = args.iter().position(|arg| arg == "--aov").and_then(|index| args.get(index + 1)) {
+        film.write_layers(Path::new(dir)).expect("failed to write AOVs");
+    }
+    if let Some(path) = args.iter().position(|arg| arg == "--exr").and_then(|index| args.get(index + 1)) {
+        film.write_exr(Path::new(path)).expect("failed to write EXR");
+    }
+
+}
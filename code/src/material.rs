@@ -0,0 +1,441 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::hittable::HitRecord;
+use crate::pdf::{Pdf, CosinePdf};
+use crate::microfacet::{TrowbridgeReitz, MicrofacetPdf, fresnel_schlick};
+use crate::onb::Onb;
+use crate::constants::{PI, random_generator};
+use crate::spectrum::LAMBDA_D_LINE;
+use crate::texture::{Texture, SolidColor};
+use std::sync::Arc;
+
+// How a material continues a path. Diffuse-like surfaces hand back a PDF to sample from, while
+// perfectly specular ones (mirrors, glass) produce a single deterministic ray that skips the PDF.
+// Materials whose color depends on the outgoing direction return Bsdf and are weighted by `Material::eval`.
+// Walk is a step of a random walk inside a volume; it is traced like Specular but doesn't count as a bounce
+pub enum ScatterRecord {
+    Pdf { attenuation: Color, pdf: Box<dyn Pdf> },
+    Specular { attenuation: Color, ray: Ray },
+    Walk { attenuation: Color, ray: Ray },
+    Bsdf { pdf: Box<dyn Pdf> },
+}
+
+// Material is a trait implemented by every surface response. Defaults describe a black, non-emitting absorber
+pub trait Material: Send + Sync {
+    fn emitted (&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    fn scatter (&self, _r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        None
+    }
+
+    // Density of the material's own scattering in the direction of `scattered`
+    fn scattering_pdf (&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> f32 {
+        0.0
+    }
+
+    // BSDF times the cosine of `scattered` with the shading normal, for materials that scatter with ScatterRecord::Bsdf
+    fn eval (&self, _r_in: &Ray, _rec: &HitRecord, _scattered: &Ray) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Base reflectance at the hit point, independent of lighting
+    fn albedo (&self, _rec: &HitRecord) -> Color {
+        Color::new(0.0, 0.0, 0.0)
+    }
+
+    // Whether scattering depends on the ray's wavelength, so spectral paths must collapse to a single wavelength
+    fn dispersive (&self) -> bool {
+        false
+    }
+}
+
+pub struct Lambertian {
+    texture: Arc<dyn Texture>,
+}
+
+impl Lambertian {
+    pub fn new (albedo: Color) -> Self {
+        Lambertian::from_texture(Arc::new(SolidColor::new(albedo)))
+    }
+
+    pub fn from_texture (texture: Arc<dyn Texture>) -> Self {
+        Lambertian { texture }
+    }
+}
+
+impl Material for Lambertian {
+    fn scatter (&self, _r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf {
+            attenuation: self.albedo(rec),
+            pdf: Box::new(CosinePdf::new(&rec.shading_normal())),
+        })
+    }
+
+    fn scattering_pdf (&self, _r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
+        let cos_theta = rec.shading_normal().dot(&scattered.direction().unit_vector());
+        (cos_theta / PI).max(0.0)
+    }
+
+    fn albedo (&self, rec: &HitRecord) -> Color {
+        self.texture.evaluate(rec)
+    }
+}
+
+pub struct Metal {
+    albedo: Color,
+    fuzz: f32,
+}
+
+impl Metal {
+    pub fn new (albedo: Color, fuzz: f32) -> Self {
+        Metal { albedo, fuzz: fuzz.min(1.0) }
+    }
+}
+
+impl Material for Metal {
+    fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let reflected = r_in.direction().reflect(&rec.shading_normal()).unit_vector() + self.fuzz * Vec3::random_unit_vector();
+        // A perturbed shading normal can reflect below the real surface; such paths are absorbed
+        if reflected.dot(&rec.normal()) <= 0.0 {
+            return None;
+        }
+
+        let mut ray = Ray::new(rec.p(), reflected);
+        if let Some(differentials) = rec.reflect_differentials(r_in, reflected) {
+            ray = ray.with_differentials(differentials);
+        }
+
+        Some(ScatterRecord::Specular {
+            attenuation: self.albedo,
+            ray,
+        })
+    }
+
+    fn albedo (&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+// Refractive index as a function of wavelength, in nanometres
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum Ior {
+    Constant(f32),
+    // n = a + b / λ², with λ in micrometres
+    Cauchy { a: f32, b: f32 },
+    // n² = 1 + Σ b_i λ² / (λ² - c_i), with λ in micrometres and c_i in µm²
+    Sellmeier { b: [f32; 3], c: [f32; 3] },
+}
+
+impl Ior {
+    // Schott N-BK7 crown glass
+    pub const BK7: Ior = Ior::Sellmeier {
+        b: [1.039612, 0.231792, 1.010469],
+        c: [0.006000699, 0.02001791, 103.5607],
+    };
+
+    pub fn eval (&self, lambda: f32) -> f32 {
+        let micrometres = lambda / 1000.0;
+        let l2 = micrometres * micrometres;
+        match *self {
+            Ior::Constant(n) => n,
+            Ior::Cauchy { a, b } => a + b / l2,
+            Ior::Sellmeier { b, c } => (1.0 + (0..3).map(|i| b[i] * l2 / (l2 - c[i])).sum::<f32>()).sqrt(),
+        }
+    }
+}
+
+pub struct Dielectric {
+    // Refractive index in vacuum or air, or the ratio of the material's index over the enclosing media
+    ior: Ior,
+}
+
+impl Dielectric {
+    pub fn new (refraction_index: f32) -> Self {
+        Dielectric { ior: Ior::Constant(refraction_index) }
+    }
+
+    // A dispersive dielectric. Rays without a wavelength see the index at the d line
+    pub fn with_ior (ior: Ior) -> Self {
+        Dielectric { ior }
+    }
+
+    // Schlick's approximation for reflectance
+    fn reflectance (cosine: f32, refraction_index: f32) -> f32 {
+        let r0 = (1.0 - refraction_index) / (1.0 + refraction_index);
+        let r0 = r0 * r0;
+        r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+    }
+}
+
+impl Material for Dielectric {
+    fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let refraction_index = self.ior.eval(r_in.wavelength().unwrap_or(LAMBDA_D_LINE));
+        let ri = if rec.front_face() { 1.0 / refraction_index } else { refraction_index };
+
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = (-unit_direction).dot(&rec.shading_normal()).min(1.0);
+        let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+
+        let cannot_refract = ri * sin_theta > 1.0;
+        let (direction, differentials) = if cannot_refract || Dielectric::reflectance(cos_theta, ri) > random_generator() {
+            let direction = unit_direction.reflect(&rec.shading_normal());
+            (direction, rec.reflect_differentials(r_in, direction))
+        } else {
+            let direction = unit_direction.refract(&rec.shading_normal(), ri);
+            (direction, rec.refract_differentials(r_in, direction, ri))
+        };
+
+        let mut ray = Ray::new(rec.p(), direction);
+        if let Some(differentials) = differentials {
+            ray = ray.with_differentials(differentials);
+        }
+
+        Some(ScatterRecord::Specular {
+            attenuation: Color::new(1.0, 1.0, 1.0),
+            ray,
+        })
+    }
+
+    fn albedo (&self, _rec: &HitRecord) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn dispersive (&self) -> bool {
+        !matches!(self.ior, Ior::Constant(_))
+    }
+}
+
+// The glTF metallic-roughness model: a GGX specular lobe with Smith masking-shadowing and Schlick Fresnel over a
+// Lambertian base. Metals tint their reflection with the base color and have no diffuse part; dielectrics reflect 4%
+// at normal incidence. Metallic is read from the blue channel and roughness from the green one, as glTF packs them
+pub struct MetallicRoughness {
+    base_color: Arc<dyn Texture>,
+    metallic: Arc<dyn Texture>,
+    roughness: Arc<dyn Texture>,
+}
+
+// Per-hit parameters of a MetallicRoughness material
+struct MetallicRoughnessSample {
+    base_color: Color,
+    metallic: f32,
+    distribution: TrowbridgeReitz,
+}
+
+impl MetallicRoughness {
+    // Reflectance of non-metals at normal incidence
+    const DIELECTRIC_F0: f32 = 0.04;
+
+    pub fn new (base_color: Color, metallic: f32, roughness: f32) -> Self {
+        MetallicRoughness::from_textures(
+            Arc::new(SolidColor::new(base_color)),
+            Arc::new(SolidColor::from_rgb(metallic, metallic, metallic)),
+            Arc::new(SolidColor::from_rgb(roughness, roughness, roughness)),
+        )
+    }
+
+    pub fn from_textures (base_color: Arc<dyn Texture>, metallic: Arc<dyn Texture>, roughness: Arc<dyn Texture>) -> Self {
+        MetallicRoughness { base_color, metallic, roughness }
+    }
+
+    fn sample (&self, rec: &HitRecord) -> MetallicRoughnessSample {
+        MetallicRoughnessSample {
+            base_color: self.base_color.evaluate(rec),
+            metallic: self.metallic.evaluate(rec).b().clamp(0.0, 1.0),
+            distribution: TrowbridgeReitz::from_roughness(self.roughness.evaluate(rec).g()),
+        }
+    }
+
+    fn f0 (sample: &MetallicRoughnessSample) -> Color {
+        let dielectric = Color::new(1.0, 1.0, 1.0) * MetallicRoughness::DIELECTRIC_F0;
+        (1.0 - sample.metallic) * dielectric + sample.metallic * sample.base_color
+    }
+}
+
+impl Material for MetallicRoughness {
+    fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let sample = self.sample(rec);
+        let wo = -r_in.direction().unit_vector();
+        let cos_o = wo.dot(&rec.shading_normal()).max(0.0);
+
+        // Pick the specular lobe in proportion to how much it reflects at this angle
+        let specular = fresnel_schlick(MetallicRoughness::f0(&sample), cos_o).luminance();
+        let diffuse = (1.0 - sample.metallic) * sample.base_color.luminance() * (1.0 - specular);
+        let specular_probability = if specular + diffuse > 0.0 { specular / (specular + diffuse) } else { 1.0 };
+
+        Some(ScatterRecord::Bsdf {
+            pdf: Box::new(MicrofacetPdf::new(&rec.shading_normal(), &wo, sample.distribution, specular_probability)),
+        })
+    }
+
+    fn eval (&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let uvw = Onb::new(&rec.shading_normal());
+        let wo = uvw.to_local(&-r_in.direction().unit_vector());
+        let wi = uvw.to_local(&scattered.direction().unit_vector());
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        if cos_o <= 0.0 || cos_i <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+
+        let sample = self.sample(rec);
+        let h = (wo + wi).unit_vector();
+        let fresnel = fresnel_schlick(MetallicRoughness::f0(&sample), wi.dot(&h));
+
+        let d = sample.distribution.d(&h);
+        let g = sample.distribution.g(&wo, &wi);
+        let specular = fresnel * (d * g / (4.0 * cos_o * cos_i));
+
+        let diffuse = (1.0 - sample.metallic) / PI * (Color::new(1.0, 1.0, 1.0) - fresnel) * sample.base_color;
+        (specular + diffuse) * cos_i
+    }
+
+    fn albedo (&self, rec: &HitRecord) -> Color {
+        self.base_color.evaluate(rec)
+    }
+}
+
+pub struct DiffuseLight {
+    texture: Arc<dyn Texture>,
+}
+
+impl DiffuseLight {
+    pub fn new (emit: Color) -> Self {
+        DiffuseLight::from_texture(Arc::new(SolidColor::new(emit)))
+    }
+
+    pub fn from_texture (texture: Arc<dyn Texture>) -> Self {
+        DiffuseLight { texture }
+    }
+}
+
+impl Material for DiffuseLight {
+    fn emitted (&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        if !rec.front_face() {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        self.texture.evaluate(rec)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::texture::CheckerTexture;
+
+    #[test]
+    fn test_lambertian_scattering_pdf_matches_cosine_pdf(){
+        let mut rec = HitRecord::default();
+        let r_in = Ray::new(Vec3::new(0.0, 1.0, 0.0), Vec3::new(0.0, -1.0, 0.0));
+        rec.set_face_normal(&r_in, Vec3::new(0.0, 1.0, 0.0));
+
+        let material = Lambertian::new(Color::new(0.5, 0.5, 0.5));
+        let scattered = Ray::new(rec.p(), Vec3::new(0.3, 0.8, 0.1));
+
+        match material.scatter(&r_in, &rec) {
+            Some(ScatterRecord::Pdf { pdf, .. }) => {
+                let a = pdf.value(&scattered.direction());
+                let b = material.scattering_pdf(&r_in, &rec, &scattered);
+                assert!((a - b).abs() < 1e-6);
+            }
+            _ => panic!("lambertian should scatter with a pdf"),
+        }
+    }
+
+    #[test]
+    fn test_diffuse_light_back_face_is_dark(){
+        let mut rec = HitRecord::default();
+        let r_in = Ray::new(Vec3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        rec.set_face_normal(&r_in, Vec3::new(0.0, 1.0, 0.0));
+
+        let light = DiffuseLight::new(Color::new(4.0, 4.0, 4.0));
+        assert_eq!(light.emitted(&r_in, &rec), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_ior_dispersion(){
+        // BK7 is about 1.5168 at the d line and bends blue light more than red
+        assert!((Ior::BK7.eval(LAMBDA_D_LINE) - 1.5168).abs() < 1e-3);
+        assert!(Ior::BK7.eval(450.0) > Ior::BK7.eval(650.0));
+
+        let cauchy = Ior::Cauchy { a: 1.5, b: 0.004 };
+        assert!((cauchy.eval(500.0) - 1.516).abs() < 1e-5);
+        assert_eq!(Ior::Constant(1.33).eval(400.0), 1.33);
+
+        assert!(Dielectric::with_ior(Ior::BK7).dispersive());
+        assert!(!Dielectric::new(1.5).dispersive());
+    }
+
+    #[test]
+    fn test_lambertian_texture_albedo(){
+        let mut rec = HitRecord::default();
+        let r_in = Ray::new(Vec3::new(0.5, 1.0, 0.5), Vec3::new(0.0, -1.0, 0.0));
+        rec.set_face_normal(&r_in, Vec3::new(0.0, 1.0, 0.0));
+
+        let checker = CheckerTexture::from_colors(1.0, Color::new(0.9, 0.9, 0.9), Color::new(0.1, 0.1, 0.1));
+        let material = Lambertian::from_texture(Arc::new(checker));
+        match material.scatter(&r_in, &rec) {
+            Some(ScatterRecord::Pdf { attenuation, .. }) => assert_eq!(attenuation, Color::new(0.9, 0.9, 0.9)),
+            _ => panic!("lambertian should scatter with a pdf"),
+        }
+    }
+
+    // Average of eval / pdf over directions drawn from the material's own PDF: the fraction of light it reflects
+    fn reflected_energy (material: &dyn Material, wo: Vec3, n: usize) -> Color {
+        let mut rec = HitRecord::default();
+        let r_in = Ray::new(wo, -wo);
+        rec.set_face_normal(&r_in, Vec3::new(0.0, 0.0, 1.0));
+
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let pdf = match material.scatter(&r_in, &rec) {
+                Some(ScatterRecord::Bsdf { pdf }) => pdf,
+                _ => panic!("expected a bsdf"),
+            };
+            let scattered = Ray::new(rec.p(), pdf.generate());
+            let pdf_value = pdf.value(&scattered.direction());
+            if pdf_value > 0.0 {
+                sum = sum + material.eval(&r_in, &rec, &scattered) / pdf_value;
+            }
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn test_metallic_roughness_conserves_energy(){
+        let wo = Vec3::new(0.4, 0.0, 0.9).unit_vector();
+
+        // A white metal never creates energy. Single-scattering GGX loses some to masking, a lot at high roughness
+        for roughness in [0.1, 0.5, 1.0] {
+            let metal = MetallicRoughness::new(Color::new(1.0, 1.0, 1.0), 1.0, roughness);
+            let energy = reflected_energy(&metal, wo, 20000).r();
+            assert!(energy <= 1.02, "roughness {} reflected {}", roughness, energy);
+            assert!(roughness > 0.5 || energy >= 0.85, "roughness {} reflected {}", roughness, energy);
+        }
+
+        let plastic = MetallicRoughness::new(Color::new(1.0, 1.0, 1.0), 0.0, 0.5);
+        let energy = reflected_energy(&plastic, wo, 20000).r();
+        assert!((0.8..=1.02).contains(&energy), "plastic reflected {}", energy);
+    }
+
+    #[test]
+    fn test_metallic_roughness_is_reciprocal(){
+        let material = MetallicRoughness::new(Color::new(0.8, 0.4, 0.2), 0.3, 0.4);
+        let mut rec = HitRecord::default();
+        let n = Vec3::new(0.0, 0.0, 1.0);
+        let a = Vec3::new(0.3, 0.2, 0.9).unit_vector();
+        let b = Vec3::new(-0.5, 0.1, 0.7).unit_vector();
+
+        let ray_a = Ray::new(a, -a);
+        rec.set_face_normal(&ray_a, n);
+        let f_ab = material.eval(&ray_a, &rec, &Ray::new(rec.p(), b)) / b.z();
+        let ray_b = Ray::new(b, -b);
+        rec.set_face_normal(&ray_b, n);
+        let f_ba = material.eval(&ray_b, &rec, &Ray::new(rec.p(), a)) / a.z();
+        assert!((f_ab - f_ba).length() < 1e-4);
+
+        // Nothing is transmitted
+        assert_eq!(material.eval(&ray_b, &rec, &Ray::new(rec.p(), -a)), Color::new(0.0, 0.0, 0.0));
+    }
+}
@@ -0,0 +1,601 @@
+use crate::vec3::{Vec3, Point3};
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::interval::Interval;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::{Material, ScatterRecord};
+use crate::phase::{PhaseFunction, PhasePdf, Isotropic};
+use crate::voxel::VoxelGrid;
+use crate::aabb::Aabb;
+use crate::constants::{INFINITY, random_generator};
+use std::sync::Arc;
+
+// What a hit inside a medium scatters with: the phase function picks the new direction and `albedo` is the fraction
+// of light that survives the interaction
+pub struct PhaseMaterial {
+    albedo: Color,
+    phase: Arc<dyn PhaseFunction>,
+}
+
+impl PhaseMaterial {
+    pub fn new (albedo: Color, phase: Arc<dyn PhaseFunction>) -> Self {
+        PhaseMaterial { albedo, phase }
+    }
+}
+
+impl Material for PhaseMaterial {
+    fn scatter (&self, r_in: &Ray, _rec: &HitRecord) -> Option<ScatterRecord> {
+        Some(ScatterRecord::Pdf {
+            attenuation: self.albedo,
+            pdf: Box::new(PhasePdf::new(&r_in.direction(), self.phase.clone())),
+        })
+    }
+
+    fn scattering_pdf (&self, r_in: &Ray, _rec: &HitRecord, scattered: &Ray) -> f32 {
+        let cos_theta = r_in.direction().unit_vector().dot(&scattered.direction().unit_vector());
+        self.phase.p(cos_theta)
+    }
+
+    fn albedo (&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+// A homogeneous volume such as smoke, fog or murky water, filling a closed boundary. Rays crossing it sample how far
+// they travel before an interaction from the density; those that interact report a hit at that point, which the
+// integrator then scatters like any surface hit. The rest pass through as if the volume weren't there
+pub struct ConstantMedium {
+    boundary: Arc<dyn Hittable>,
+    // -1 / density, so the free-flight distance is this times the log of a uniform number
+    neg_inv_density: f32,
+    phase_material: Arc<PhaseMaterial>,
+}
+
+impl ConstantMedium {
+    // `density` is the chance of an interaction per unit distance. Scatters isotropically
+    pub fn new (boundary: Arc<dyn Hittable>, density: f32, albedo: Color) -> Self {
+        ConstantMedium {
+            boundary,
+            neg_inv_density: -1.0 / density.max(1e-12),
+            phase_material: Arc::new(PhaseMaterial::new(albedo, Arc::new(Isotropic))),
+        }
+    }
+
+    // Scatters with another phase function, e.g. forward scattering HenyeyGreenstein for haze
+    pub fn with_phase (mut self, phase: Arc<dyn PhaseFunction>) -> Self {
+        self.phase_material = Arc::new(PhaseMaterial::new(self.phase_material.albedo, phase));
+        self
+    }
+}
+
+impl Hittable for ConstantMedium {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        // Where the ray's line enters and leaves the boundary, so rays starting inside still see the volume
+        let mut entry = HitRecord::default();
+        let entry_t = self.boundary.hit(r, Interval::universe(), &mut entry)?.t();
+        let mut exit = HitRecord::default();
+        let exit_t = self.boundary.hit(r, Interval::new(entry_t + 0.0001, INFINITY), &mut exit)?.t();
+
+        let t_min = entry_t.max(ray_t.min()).max(0.0);
+        let t_max = exit_t.min(ray_t.max());
+        if t_min >= t_max {
+            return None;
+        }
+
+        let speed = r.direction().length();
+        let distance_inside = (t_max - t_min) * speed;
+        let hit_distance = self.neg_inv_density * (1.0 - random_generator()).ln();
+        if hit_distance > distance_inside {
+            return None;
+        }
+
+        let t = t_min + hit_distance / speed;
+        let hit_record = HitRecord::new(r.at(t), t, self.phase_material.clone());
+        *rec = hit_record.clone();
+        Some(hit_record)
+    }
+
+    fn pdf_value (&self, origin: Point3, direction: Vec3) -> f32 {
+        self.boundary.pdf_value(origin, direction)
+    }
+
+    fn random (&self, origin: Point3) -> Vec3 {
+        self.boundary.random(origin)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        self.boundary.bounding_box()
+    }
+}
+
+// The axis-aligned box a voxel grid is stretched over
+#[derive(Copy, Clone, Debug)]
+struct GridBounds {
+    min: Point3,
+    max: Point3,
+}
+
+impl GridBounds {
+    // Position within the box as unit cube coordinates
+    fn unit_coordinates (&self, p: &Point3) -> Point3 {
+        let extent = self.max - self.min;
+        Point3::new((p.x() - self.min.x()) / extent.x(), (p.y() - self.min.y()) / extent.y(), (p.z() - self.min.z()) / extent.z())
+    }
+
+    // The part of `ray_t` where the ray is inside the box, by the slab method. A ray parallel to a slab is inside it
+    // everywhere or nowhere, depending only on its origin
+    fn clip (&self, r: &Ray, ray_t: Interval) -> Option<(f32, f32)> {
+        let (mut t0, mut t1) = (ray_t.min(), ray_t.max());
+        for axis in 0..3 {
+            if r.direction()[axis] == 0.0 {
+                if r.origin()[axis] < self.min[axis] || r.origin()[axis] > self.max[axis] {
+                    return None;
+                }
+                continue;
+            }
+            let inverse = 1.0 / r.direction()[axis];
+            let mut near = (self.min[axis] - r.origin()[axis]) * inverse;
+            let mut far = (self.max[axis] - r.origin()[axis]) * inverse;
+            if near > far {
+                std::mem::swap(&mut near, &mut far);
+            }
+            t0 = t0.max(near);
+            t1 = t1.min(far);
+            if t0 >= t1 {
+                return None;
+            }
+        }
+        Some((t0, t1))
+    }
+}
+
+// Scattering at a collision inside a HeterogeneousMedium, plus the light its absorbing part emits there
+struct VolumeMaterial {
+    scattering: PhaseMaterial,
+    bounds: GridBounds,
+    emission: Option<(Arc<VoxelGrid>, Color)>,
+}
+
+impl Material for VolumeMaterial {
+    // Collision estimator: of each collision a fraction 1 - albedo is absorption, and emission is proportional to it
+    fn emitted (&self, _r_in: &Ray, rec: &HitRecord) -> Color {
+        match &self.emission {
+            Some((grid, color)) => {
+                let absorbed = Color::new(1.0, 1.0, 1.0) - self.scattering.albedo;
+                absorbed * *color * grid.lookup(&self.bounds.unit_coordinates(&rec.p()))
+            }
+            None => Color::new(0.0, 0.0, 0.0),
+        }
+    }
+
+    fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        self.scattering.scatter(r_in, rec)
+    }
+
+    fn scattering_pdf (&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> f32 {
+        self.scattering.scattering_pdf(r_in, rec, scattered)
+    }
+
+    fn albedo (&self, rec: &HitRecord) -> Color {
+        self.scattering.albedo(rec)
+    }
+}
+
+// A medium whose density varies through space, such as smoke or fire exported from a simulation, given by a voxel
+// grid stretched over an axis-aligned box. Collisions are found by delta tracking: tentative collisions are sampled
+// at the rate of a majorant bounding the density everywhere, and each is real with probability density / majorant,
+// which samples free-flight distances exactly without integrating the density. Real collisions become hits
+pub struct HeterogeneousMedium {
+    density: VoxelGrid,
+    bounds: GridBounds,
+    density_scale: f32,
+    majorant: f32,
+    material: Arc<VolumeMaterial>,
+}
+
+impl HeterogeneousMedium {
+    // Grid values times `density_scale` are the chance of an interaction per unit distance. Scatters isotropically
+    pub fn new (density: VoxelGrid, min: Point3, max: Point3, density_scale: f32, albedo: Color) -> Self {
+        let bounds = GridBounds { min, max };
+        let majorant = density.max_value() * density_scale;
+        HeterogeneousMedium {
+            density,
+            bounds,
+            density_scale,
+            majorant,
+            material: Arc::new(VolumeMaterial {
+                scattering: PhaseMaterial::new(albedo, Arc::new(Isotropic)),
+                bounds,
+                emission: None,
+            }),
+        }
+    }
+
+    pub fn with_phase (mut self, phase: Arc<dyn PhaseFunction>) -> Self {
+        self.material = Arc::new(VolumeMaterial {
+            scattering: PhaseMaterial::new(self.material.scattering.albedo, phase),
+            bounds: self.bounds,
+            emission: self.material.emission.clone(),
+        });
+        self
+    }
+
+    // Makes the medium glow like fire: radiance `color` times the grid value, emitted by the absorbing part of the
+    // medium, so emission only shows where there is density to carry it and the albedo is below one
+    pub fn with_emission (mut self, emission: VoxelGrid, color: Color) -> Self {
+        self.material = Arc::new(VolumeMaterial {
+            scattering: PhaseMaterial::new(self.material.scattering.albedo, self.material.scattering.phase.clone()),
+            bounds: self.bounds,
+            emission: Some((Arc::new(emission), color)),
+        });
+        self
+    }
+
+    // Extinction coefficient at a world space point
+    pub fn density_at (&self, p: &Point3) -> f32 {
+        self.density.lookup(&self.bounds.unit_coordinates(p)) * self.density_scale
+    }
+
+    // Distance travelled along `r` to the next tentative collision
+    fn majorant_step (&self, r: &Ray) -> f32 {
+        -(1.0 - random_generator()).ln() / (self.majorant * r.direction().length())
+    }
+
+    // Unbiased estimate of the fraction of light that crosses the medium along `r` within `ray_t`, by ratio
+    // tracking: instead of stopping at the first real collision, every tentative one scales the estimate by the chance
+    // it was a null collision. Less noisy than counting how often delta tracking gets through
+    pub fn transmittance (&self, r: &Ray, ray_t: Interval) -> f32 {
+        let (mut t, t_max) = match self.bounds.clip(r, ray_t) {
+            Some(range) => range,
+            None => return 1.0,
+        };
+        if self.majorant <= 0.0 {
+            return 1.0;
+        }
+
+        let mut transmittance = 1.0;
+        loop {
+            t += self.majorant_step(r);
+            if t >= t_max {
+                return transmittance;
+            }
+            transmittance *= 1.0 - self.density_at(&r.at(t)) / self.majorant;
+        }
+    }
+}
+
+impl Hittable for HeterogeneousMedium {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        let (mut t, t_max) = self.bounds.clip(&r, ray_t)?;
+        if self.majorant <= 0.0 {
+            return None;
+        }
+
+        loop {
+            t += self.majorant_step(&r);
+            if t >= t_max {
+                return None;
+            }
+            let p = r.at(t);
+            if random_generator() * self.majorant < self.density_at(&p) {
+                let hit_record = HitRecord::new(p, t, self.material.clone());
+                *rec = hit_record.clone();
+                return Some(hit_record);
+            }
+        }
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        Aabb::new(self.bounds.min, self.bounds.max)
+    }
+}
+
+// Fog filling the whole scene, for aerial perspective: distant objects fade into the color of the light scattered
+// towards the camera along the way. The extinction coefficient is `density` at `base_height` and falls off
+// exponentially above it, thickening below, so valleys fill with haze and the sky stays clear overhead. Having no
+// boundary, it is attached to the scene rather than the world, and every ray, camera rays included, travels through
+// it. Optical depth along a ray has a closed form, so free-flight distances are sampled exactly
+pub struct AtmosphericFog {
+    density: f32,
+    base_height: f32,
+    // 1 / scale height; 0 makes the fog homogeneous
+    falloff: f32,
+    phase_material: Arc<PhaseMaterial>,
+}
+
+impl AtmosphericFog {
+    // Homogeneous fog: `density` is the chance of an interaction per unit distance everywhere. Scatters isotropically
+    pub fn new (density: f32, albedo: Color) -> Self {
+        AtmosphericFog {
+            density: density.max(0.0),
+            base_height: 0.0,
+            falloff: 0.0,
+            phase_material: Arc::new(PhaseMaterial::new(albedo, Arc::new(Isotropic))),
+        }
+    }
+
+    // Density is divided by e for every `scale_height` climbed above `base_height`
+    pub fn with_height_falloff (mut self, base_height: f32, scale_height: f32) -> Self {
+        self.base_height = base_height;
+        self.falloff = 1.0 / scale_height.max(1e-6);
+        self
+    }
+
+    // Scatters with another phase function; haze scatters strongly forwards, which gives glows around lights
+    pub fn with_phase (mut self, phase: Arc<dyn PhaseFunction>) -> Self {
+        self.phase_material = Arc::new(PhaseMaterial::new(self.phase_material.albedo, phase));
+        self
+    }
+
+    // Extinction coefficient at a point
+    pub fn density_at (&self, p: &Point3) -> f32 {
+        self.density * (-self.falloff * (p.y() - self.base_height)).exp()
+    }
+
+    // Along `r`, density is a * exp(-b * s) at distance s from the origin
+    fn profile (&self, r: &Ray) -> (f32, f32) {
+        (self.density_at(&r.origin()), self.falloff * r.direction().unit_vector().y())
+    }
+
+    // Optical depth from the origin of `r` out to distance `s`
+    fn optical_depth (a: f32, b: f32, s: f32) -> f32 {
+        if (b * s).abs() < 1e-4 {
+            a * s * (1.0 - 0.5 * b * s)
+        } else {
+            a * (1.0 - (-b * s).exp()) / b
+        }
+    }
+
+    // Fraction of light that crosses the fog along `r` within `ray_t`
+    pub fn transmittance (&self, r: &Ray, ray_t: Interval) -> f32 {
+        let (a, b) = self.profile(r);
+        let speed = r.direction().length();
+        let t_min = ray_t.min().max(0.0);
+        if t_min >= ray_t.max() {
+            return 1.0;
+        }
+        let depth = AtmosphericFog::optical_depth(a, b, ray_t.max() * speed) - AtmosphericFog::optical_depth(a, b, t_min * speed);
+        (-depth).exp()
+    }
+
+    // Samples where along `r` light first interacts with the fog, and reports it as a hit if that happens within
+    // `ray_t`, i.e. before the surface the ray would otherwise reach
+    pub fn hit (&self, r: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let (a, b) = self.profile(&r);
+        if a <= 0.0 {
+            return None;
+        }
+        let speed = r.direction().length();
+        let t_min = ray_t.min().max(0.0);
+
+        // Invert the optical depth: the sampled depth is counted on from wherever the interval starts
+        let depth = -(1.0 - random_generator()).ln() + AtmosphericFog::optical_depth(a, b, t_min * speed);
+        let s = if (b * depth / a).abs() < 1e-4 {
+            depth / a * (1.0 + 0.5 * b * depth / a)
+        } else {
+            // Rays climbing out of the fog only ever see a finite optical depth of a / b
+            let remaining = 1.0 - b * depth / a;
+            if remaining <= 0.0 {
+                return None;
+            }
+            -remaining.ln() / b
+        };
+
+        let t = s / speed;
+        if t >= ray_t.max() {
+            return None;
+        }
+        Some(HitRecord::new(r.at(t), t, self.phase_material.clone()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::hittable::{HittableList, Sphere};
+    use crate::phase::HenyeyGreenstein;
+    use crate::integrator::{Integrator, PathIntegrator};
+    use crate::sampler::RandomSampler;
+    use crate::scene::Scene;
+
+    fn unit_sphere () -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::default(), 1.0, Arc::new(PhaseMaterial::new(Color::new(1.0, 1.0, 1.0), Arc::new(Isotropic)))))
+    }
+
+    // Fraction of rays that cross the medium without interacting
+    fn transmittance (medium: &ConstantMedium, r: Ray, n: usize) -> f32 {
+        let misses = (0..n).filter(|_| {
+            let mut rec = HitRecord::default();
+            medium.hit(r, Interval::new(0.0001, INFINITY), &mut rec).is_none()
+        }).count();
+        misses as f32 / n as f32
+    }
+
+    #[test]
+    fn test_constant_medium_transmittance(){
+        let medium = ConstantMedium::new(unit_sphere(), 0.5, Color::new(0.8, 0.8, 0.8));
+        let n = 40000;
+
+        // Through the center the ray crosses two units of medium, from the center just one
+        let through = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -2.0));
+        assert!((transmittance(&medium, through, n) - (-1.0f32).exp()).abs() < 0.01);
+        let from_inside = Ray::new(Point3::default(), Vec3::new(0.0, 1.0, 0.0));
+        assert!((transmittance(&medium, from_inside, n) - (-0.5f32).exp()).abs() < 0.01);
+
+        // Interactions land inside the boundary
+        let mut rec = HitRecord::default();
+        while medium.hit(through, Interval::new(0.0001, INFINITY), &mut rec).is_none() {}
+        assert!(rec.p().length() <= 1.0 + 1e-4);
+        assert!((rec.t() - (5.0 - rec.p().z()) / 2.0).abs() < 1e-4);
+    }
+
+    // A ramp of density rising along z through the cube [-1, 1]^3
+    fn ramp_medium (albedo: Color) -> HeterogeneousMedium {
+        let grid = VoxelGrid::from_fn([4, 4, 64], |p| p.z());
+        HeterogeneousMedium::new(grid, Point3::new(-1.0, -1.0, -1.0), Point3::new(1.0, 1.0, 1.0), 1.0, albedo)
+    }
+
+    #[test]
+    fn test_heterogeneous_transmittance(){
+        // The density averages 0.5 over two units, an optical depth of one
+        let medium = ramp_medium(Color::new(0.5, 0.5, 0.5));
+        let r = Ray::new(Point3::new(0.2, 0.1, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let interval = || Interval::new(0.0001, INFINITY);
+        let expected = (-1.0f32).exp();
+
+        let n = 20000;
+        let ratio = (0..n).map(|_| medium.transmittance(&r, interval())).sum::<f32>() / n as f32;
+        assert!((ratio - expected).abs() < 0.01, "ratio tracking gave {}", ratio);
+
+        let misses = (0..n).filter(|_| medium.hit(r, interval(), &mut HitRecord::default()).is_none()).count();
+        assert!((misses as f32 / n as f32 - expected).abs() < 0.01);
+
+        // Collisions favor the dense end
+        let mut sum = 0.0;
+        let mut hits = 0;
+        for _ in 0..n {
+            if let Some(rec) = medium.hit(r, interval(), &mut HitRecord::default()) {
+                sum += rec.p().z();
+                hits += 1;
+            }
+        }
+        assert!(sum / hits as f32 > 0.0);
+
+        // Rays missing the box pass untouched
+        let outside = Ray::new(Point3::new(3.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(medium.transmittance(&outside, interval()), 1.0);
+    }
+
+    #[test]
+    fn test_grid_bounds_clip_axis_aligned_rays(){
+        let bounds = GridBounds { min: Point3::new(-1.0, -1.0, -1.0), max: Point3::new(1.0, 1.0, 1.0) };
+        let interval = || Interval::new(0.0, INFINITY);
+
+        // Parallel to two pairs of slabs, inside them, on one of their faces, and outside them
+        let (t0, t1) = bounds.clip(&Ray::new(Point3::new(0.5, 0.0, 3.0), Vec3::new(0.0, 0.0, -2.0)), interval()).unwrap();
+        assert_eq!((t0, t1), (1.0, 2.0));
+        let (t0, t1) = bounds.clip(&Ray::new(Point3::new(-1.0, 1.0, 3.0), Vec3::new(0.0, 0.0, -1.0)), interval()).unwrap();
+        assert_eq!((t0, t1), (2.0, 4.0));
+        assert!(bounds.clip(&Ray::new(Point3::new(1.5, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0)), interval()).is_none());
+
+        // A zero direction is inside for all of the interval or none of it
+        assert!(bounds.clip(&Ray::new(Point3::default(), Vec3::default()), Interval::new(0.0, 5.0)).is_some());
+        assert!(bounds.clip(&Ray::new(Point3::new(0.0, 2.0, 0.0), Vec3::default()), Interval::new(0.0, 5.0)).is_none());
+    }
+
+    #[test]
+    fn test_heterogeneous_emission(){
+        // A glowing absorber seen against black shows its emission times the fraction of light it absorbs
+        let glow = VoxelGrid::from_fn([2, 2, 2], |_| 1.0);
+        let medium = ramp_medium(Color::new(0.0, 0.0, 0.0)).with_emission(glow, Color::new(2.0, 1.0, 0.5));
+        let mut world = HittableList::default();
+        world.add(Arc::new(medium));
+        world.add(Arc::new(Sphere::new(Point3::default(), 10.0, Arc::new(PhaseMaterial::new(Color::new(0.0, 0.0, 0.0), Arc::new(Isotropic))))));
+        let scene = Scene::new(world, HittableList::default());
+        let integrator = PathIntegrator::default();
+        let mut sampler = RandomSampler::new();
+
+        let r = Ray::new(Point3::new(0.2, 0.1, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        let n = 20000;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            sum = sum + integrator.li(r, &scene, &mut sampler);
+        }
+        let expected = Color::new(2.0, 1.0, 0.5) * (1.0 - (-1.0f32).exp());
+        assert!((sum / n as f32 - expected).length() < 0.03, "radiance {:?}", sum / n as f32);
+    }
+
+    #[test]
+    fn test_phase_material_pdf_matches_scattering_pdf(){
+        let material = PhaseMaterial::new(Color::new(0.5, 0.5, 0.5), Arc::new(HenyeyGreenstein::new(0.6)));
+        let r_in = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -3.0));
+        let rec = HitRecord::new(Point3::default(), 1.0, Arc::new(PhaseMaterial::new(Color::new(0.5, 0.5, 0.5), Arc::new(Isotropic))));
+        let scattered = Ray::new(Point3::default(), Vec3::new(0.2, 0.1, -1.0));
+
+        match material.scatter(&r_in, &rec) {
+            Some(ScatterRecord::Pdf { attenuation, pdf }) => {
+                assert_eq!(attenuation, Color::new(0.5, 0.5, 0.5));
+                let a = pdf.value(&scattered.direction());
+                let b = material.scattering_pdf(&r_in, &rec, &scattered);
+                assert!((a - b).abs() < 1e-6);
+                // Forward scattering favors directions close to the ray's
+                assert!(pdf.value(&Vec3::new(0.0, 0.0, -1.0)) > pdf.value(&Vec3::new(0.0, 0.0, 1.0)));
+            }
+            _ => panic!("media should scatter with a pdf"),
+        }
+    }
+
+    #[test]
+    fn test_fog_transmittance_matches_height_profile(){
+        let fog = AtmosphericFog::new(0.4, Color::new(0.9, 0.9, 0.9)).with_height_falloff(1.0, 2.0);
+        assert!((fog.density_at(&Point3::new(5.0, 3.0, 0.0)) - 0.4 * (-1.0f32).exp()).abs() < 1e-6);
+
+        // Climbing, descending and level rays, with non-unit directions
+        let rays = [
+            Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(1.0, 0.5, 0.0)),
+            Ray::new(Point3::new(0.0, 4.0, 0.0), Vec3::new(0.0, -2.0, 2.0)),
+            Ray::new(Point3::new(0.0, 0.5, 0.0), Vec3::new(0.0, 0.0, -3.0)),
+        ];
+        for r in rays {
+            // Optical depth integrated numerically over t in [0, 2]
+            let steps = 10000;
+            let dt = 2.0 / steps as f32;
+            let depth: f32 = (0..steps).map(|i| fog.density_at(&r.at((i as f32 + 0.5) * dt)) * dt * r.direction().length()).sum();
+            let expected = (-depth).exp();
+            let analytic = fog.transmittance(&r, Interval::new(0.0, 2.0));
+            assert!((analytic - expected).abs() < 1e-3, "analytic {} expected {}", analytic, expected);
+
+            // Sampled interactions happen before t = 2 as often as light fails to get through
+            let n = 40000;
+            let misses = (0..n).filter(|_| fog.hit(r, Interval::new(0.0, 2.0)).is_none()).count();
+            assert!((misses as f32 / n as f32 - expected).abs() < 0.01, "{} of rays got through, expected {}", misses as f32 / n as f32, expected);
+        }
+
+        // High enough, rays escape upwards with finite optical depth
+        let up = Ray::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!((fog.transmittance(&up, Interval::new(0.0, INFINITY)) - (-0.8f32).exp()).abs() < 1e-4);
+    }
+
+    #[test]
+    fn test_fog_hides_surfaces_in_the_scene(){
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(Point3::new(0.0, 0.0, -4.0), 1.0, Arc::new(PhaseMaterial::new(Color::new(0.5, 0.5, 0.5), Arc::new(Isotropic))))));
+        let mut scene = Scene::new(world, HittableList::default());
+        let r = Ray::new(Point3::default(), Vec3::new(0.0, 0.0, -1.0));
+        let surface_t = scene.hit(r, Interval::new(0.0001, INFINITY)).unwrap().t();
+        assert!((surface_t - 3.0).abs() < 1e-4);
+
+        // Fog in front of the sphere catches rays with the chance of an interaction over three units
+        scene.fog = Some(AtmosphericFog::new(0.25, Color::new(1.0, 1.0, 1.0)));
+        let n = 20000;
+        let surface_hits = (0..n).filter(|_| (scene.hit(r, Interval::new(0.0001, INFINITY)).unwrap().t() - surface_t).abs() < 1e-4).count();
+        assert!((surface_hits as f32 / n as f32 - (-0.75f32).exp()).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_white_fog_conserves_energy(){
+        // Inside a uniformly glowing enclosure, fog that scatters without absorbing leaves the radiance unchanged
+        struct Glow;
+        impl Material for Glow {
+            fn emitted (&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+                Color::new(1.0, 1.0, 1.0)
+            }
+        }
+
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(Point3::default(), 10.0, Arc::new(Glow))));
+        let mut scene = Scene::new(world, HittableList::default());
+        scene.fog = Some(AtmosphericFog::new(0.2, Color::new(1.0, 1.0, 1.0)).with_height_falloff(0.0, 5.0)
+            .with_phase(Arc::new(HenyeyGreenstein::new(0.5))));
+        let integrator = PathIntegrator::default();
+        let mut sampler = RandomSampler::new();
+
+        let r = Ray::new(Point3::default(), Vec3::new(1.0, -0.2, 0.0));
+        let n = 4000;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            sum = sum + integrator.li(r, &scene, &mut sampler);
+        }
+        assert!((sum / n as f32 - Color::new(1.0, 1.0, 1.0)).length() < 0.05, "radiance {:?}", sum / n as f32);
+    }
+}
@@ -0,0 +1,262 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::onb::Onb;
+use crate::pdf::Pdf;
+use crate::constants::{PI, random_generator};
+
+// Fresnel reflectance by Schlick's approximation, for a surface with reflectance `f0` at normal incidence
+pub fn fresnel_schlick (f0: Color, cos_theta: f32) -> Color {
+    let weight = (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5);
+    f0 + weight * (Color::new(1.0, 1.0, 1.0) - f0)
+}
+
+// Exact Fresnel reflectance of an unpolarized ray meeting a dielectric interface at `cos_theta_i` from the incident
+// side, where `eta` is the transmitted side's index over the incident side's. Total internal reflection gives 1
+pub fn fresnel_dielectric (cos_theta_i: f32, eta: f32) -> f32 {
+    let cos_theta_i = cos_theta_i.clamp(0.0, 1.0);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return 1.0;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    let parallel = (eta * cos_theta_i - cos_theta_t) / (eta * cos_theta_i + cos_theta_t);
+    let perpendicular = (cos_theta_i - eta * cos_theta_t) / (cos_theta_i + eta * cos_theta_t);
+    0.5 * (parallel * parallel + perpendicular * perpendicular)
+}
+
+// GGX (Trowbridge-Reitz) microfacet distribution, optionally anisotropic with roughness alpha_x along the tangent and
+// alpha_y along the bitangent. All directions are unit vectors in the local shading frame, where the macro surface
+// normal is +z and the tangent is +x
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct TrowbridgeReitz {
+    alpha_x: f32,
+    alpha_y: f32,
+}
+
+impl TrowbridgeReitz {
+    // Smallest alpha used; smoother surfaces are numerically a mirror but the lobe must stay finite
+    const MIN_ALPHA: f32 = 1e-3;
+
+    pub fn new (alpha: f32) -> Self {
+        TrowbridgeReitz::anisotropic(alpha, alpha)
+    }
+
+    pub fn anisotropic (alpha_x: f32, alpha_y: f32) -> Self {
+        TrowbridgeReitz {
+            alpha_x: alpha_x.max(TrowbridgeReitz::MIN_ALPHA),
+            alpha_y: alpha_y.max(TrowbridgeReitz::MIN_ALPHA),
+        }
+    }
+
+    // Artist-friendly perceptual roughness in [0, 1], remapped to alpha = roughness^2
+    pub fn from_roughness (roughness: f32) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        TrowbridgeReitz::new(roughness * roughness)
+    }
+
+    // Disney's remapping: `anisotropic` in [0, 1] stretches the highlight along the tangent at constant total roughness
+    pub fn from_roughness_anisotropic (roughness: f32, anisotropic: f32) -> Self {
+        let roughness = roughness.clamp(0.0, 1.0);
+        let aspect = (1.0 - 0.9 * anisotropic.clamp(0.0, 1.0)).sqrt();
+        TrowbridgeReitz::anisotropic(roughness * roughness / aspect, roughness * roughness * aspect)
+    }
+
+    pub fn alpha_x (&self) -> f32 {
+        self.alpha_x
+    }
+
+    pub fn alpha_y (&self) -> f32 {
+        self.alpha_y
+    }
+
+    // Density of microfacet normals `h`, projected so it integrates to one over the hemisphere against cos(theta_h)
+    pub fn d (&self, h: &Vec3) -> f32 {
+        if h.z() <= 0.0 {
+            return 0.0;
+        }
+        // Summing the stretched tangential components keeps precision for tiny alphas, unlike going through 1 - cos^2
+        let x = h.x() / self.alpha_x;
+        let y = h.y() / self.alpha_y;
+        let denominator = x * x + y * y + h.z() * h.z();
+        1.0 / (PI * self.alpha_x * self.alpha_y * denominator * denominator)
+    }
+
+    // Smith's auxiliary function for a direction
+    fn lambda (&self, w: &Vec3) -> f32 {
+        let cos2 = w.z() * w.z();
+        if cos2 <= 0.0 {
+            return 0.0;
+        }
+        let x = self.alpha_x * w.x();
+        let y = self.alpha_y * w.y();
+        let alpha2_tan2 = (x * x + y * y) / cos2;
+        0.5 * ((1.0 + alpha2_tan2).sqrt() - 1.0)
+    }
+
+    // Fraction of microfacets visible from `w`
+    pub fn g1 (&self, w: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(w))
+    }
+
+    // Height-correlated Smith masking-shadowing for the pair of directions
+    pub fn g (&self, wo: &Vec3, wi: &Vec3) -> f32 {
+        1.0 / (1.0 + self.lambda(wo) + self.lambda(wi))
+    }
+
+    // Distribution of normals visible from `wo`, which is what sample_visible_normal draws from
+    pub fn visible_d (&self, wo: &Vec3, h: &Vec3) -> f32 {
+        if wo.z() <= 0.0 {
+            return 0.0;
+        }
+        self.g1(wo) * wo.dot(h).max(0.0) * self.d(h) / wo.z()
+    }
+
+    // Samples a microfacet normal visible from `wo` (Heitz 2018), so reflected directions rarely fall below the surface
+    pub fn sample_visible_normal (&self, wo: &Vec3, u1: f32, u2: f32) -> Vec3 {
+        // Stretch into the configuration where the distribution is a hemisphere
+        let vh = Vec3::new(self.alpha_x * wo.x(), self.alpha_y * wo.y(), wo.z()).unit_vector();
+
+        let lensq = vh.x() * vh.x() + vh.y() * vh.y();
+        let t1 = if lensq > 0.0 { Vec3::new(-vh.y(), vh.x(), 0.0) / lensq.sqrt() } else { Vec3::new(1.0, 0.0, 0.0) };
+        let t2 = vh.cross(&t1);
+
+        // Uniform point on a disk, warped towards the part of the hemisphere that faces wo
+        let r = u1.sqrt();
+        let phi = 2.0 * PI * u2;
+        let p1 = r * phi.cos();
+        let s = 0.5 * (1.0 + vh.z());
+        let p2 = (1.0 - s) * (1.0 - p1 * p1).sqrt() + s * r * phi.sin();
+
+        let nh = p1 * t1 + p2 * t2 + (1.0 - p1 * p1 - p2 * p2).max(0.0).sqrt() * vh;
+        Vec3::new(self.alpha_x * nh.x(), self.alpha_y * nh.y(), nh.z().max(1e-6)).unit_vector()
+    }
+}
+
+// Mirror of `wo` about the microfacet normal `h`
+pub fn reflect_about (wo: &Vec3, h: &Vec3) -> Vec3 {
+    2.0 * wo.dot(h) * *h - *wo
+}
+
+// Sampling density for a diffuse base under a GGX specular lobe: the specular lobe is chosen with probability
+// `specular_probability` and sampled through its visible normals, otherwise a cosine-weighted direction is drawn
+pub struct MicrofacetPdf {
+    uvw: Onb,
+    wo: Vec3,
+    distribution: TrowbridgeReitz,
+    specular_probability: f32,
+}
+
+impl MicrofacetPdf {
+    // `wo` points away from the surface, towards where the light leaves
+    pub fn new (normal: &Vec3, wo: &Vec3, distribution: TrowbridgeReitz, specular_probability: f32) -> Self {
+        let uvw = Onb::new(normal);
+        let wo = uvw.to_local(&wo.unit_vector());
+        MicrofacetPdf { uvw, wo, distribution, specular_probability: specular_probability.clamp(0.0, 1.0) }
+    }
+
+    // Density of reflecting into local `wi` through a visible normal
+    fn specular_value (&self, wi: &Vec3) -> f32 {
+        let h = *wi + self.wo;
+        if h.length_squared() == 0.0 {
+            return 0.0;
+        }
+        let h = h.unit_vector();
+        let wo_dot_h = self.wo.dot(&h);
+        if wo_dot_h <= 0.0 {
+            return 0.0;
+        }
+        self.distribution.visible_d(&self.wo, &h) / (4.0 * wo_dot_h)
+    }
+}
+
+impl Pdf for MicrofacetPdf {
+    fn value (&self, direction: &Vec3) -> f32 {
+        let wi = self.uvw.to_local(&direction.unit_vector());
+        let diffuse = (wi.z() / PI).max(0.0);
+        self.specular_probability * self.specular_value(&wi) + (1.0 - self.specular_probability) * diffuse
+    }
+
+    fn generate (&self) -> Vec3 {
+        if random_generator() < self.specular_probability {
+            let h = self.distribution.sample_visible_normal(&self.wo, random_generator(), random_generator());
+            self.uvw.transform(&reflect_about(&self.wo, &h))
+        } else {
+            self.uvw.transform(&Vec3::random_cosine_direction())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ggx_normalized(){
+        // The projected normal distribution integrates to one over the hemisphere
+        let distribution = TrowbridgeReitz::from_roughness(0.6);
+        let n = 200000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let h = Vec3::random_cosine_direction();
+            // cosine sampling has density cos / pi
+            sum += distribution.d(&h) * h.z() / (h.z() / PI);
+        }
+        assert!((sum / n as f32 - 1.0).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_visible_normals_face_the_viewer(){
+        let distribution = TrowbridgeReitz::from_roughness(0.8);
+        let wo = Vec3::new(0.6, 0.0, 0.8);
+        for _ in 0..1000 {
+            let h = distribution.sample_visible_normal(&wo, random_generator(), random_generator());
+            assert!(h.z() > 0.0);
+            assert!(wo.dot(&h) >= -1e-5);
+            assert!((h.length() - 1.0).abs() < 1e-4);
+        }
+    }
+
+    #[test]
+    fn test_microfacet_pdf_integrates_to_one(){
+        let normal = Vec3::new(0.0, 1.0, 0.0);
+        let wo = Vec3::new(0.3, 0.8, 0.1);
+        let pdf = MicrofacetPdf::new(&normal, &wo, TrowbridgeReitz::from_roughness(0.5), 0.5);
+
+        // Uniform sphere sampling has density 1 / 4 pi
+        let n = 200000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            sum += pdf.value(&Vec3::random_unit_vector()) * 4.0 * PI;
+        }
+        assert!((sum / n as f32 - 1.0).abs() < 0.05);
+    }
+
+    #[test]
+    fn test_anisotropic_ggx_normalized(){
+        let distribution = TrowbridgeReitz::from_roughness_anisotropic(0.5, 0.8);
+        assert!(distribution.alpha_x() > distribution.alpha_y());
+
+        let n = 200000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            let h = Vec3::random_cosine_direction();
+            sum += distribution.d(&h) * PI;
+        }
+        assert!((sum / n as f32 - 1.0).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_fresnel_dielectric(){
+        // About 4% for glass at normal incidence, total internal reflection past the critical angle from inside
+        assert!((fresnel_dielectric(1.0, 1.5) - 0.04).abs() < 1e-4);
+        assert_eq!(fresnel_dielectric(0.3, 1.0 / 1.5), 1.0);
+        assert!(fresnel_dielectric(0.01, 1.5) > 0.9);
+    }
+
+    #[test]
+    fn test_fresnel_schlick(){
+        let f0 = Color::new(0.04, 0.04, 0.04);
+        assert_eq!(fresnel_schlick(f0, 1.0), f0);
+        assert!((fresnel_schlick(f0, 0.0).r() - 1.0).abs() < 1e-6);
+    }
+}
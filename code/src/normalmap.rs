@@ -0,0 +1,189 @@
+use crate::vec3::{Vec3, Point3};
+use crate::ray::Ray;
+use crate::interval::Interval;
+use crate::hittable::{HitRecord, Hittable};
+use crate::texture::Texture;
+use crate::aabb::Aabb;
+use std::sync::Arc;
+
+// Something that bends the shading normal at a hit to add surface detail the geometry doesn't have
+pub trait NormalPerturbation: Send + Sync {
+    // The new outward facing shading normal for `rec`
+    fn perturb (&self, rec: &HitRecord) -> Vec3;
+}
+
+// The hit's tangent frame oriented with the outward normal, Gram-Schmidt orthogonalized
+fn outward_frame (rec: &HitRecord) -> (Vec3, Vec3, Vec3) {
+    let n = if rec.front_face() { rec.normal() } else { -rec.normal() };
+    let t = rec.tangent() - rec.tangent().dot(&n) * n;
+    if t.length_squared() < 1e-12 {
+        return (Vec3::default(), Vec3::default(), n);
+    }
+    let t = t.unit_vector();
+    let b = rec.bitangent() - rec.bitangent().dot(&n) * n - rec.bitangent().dot(&t) * t;
+    let b = if b.length_squared() < 1e-12 { n.cross(&t) } else { b.unit_vector() };
+    (t, b, n)
+}
+
+// Tangent-space normal map: each texel stores a normal as RGB = (n + 1) / 2, with blue pointing out of the surface.
+// Load image maps with ImageTexture::load_data so the values aren't linearized
+pub struct NormalMap {
+    texture: Arc<dyn Texture>,
+    // Scales the tangential part of the stored normals: 0 flattens the map, 1 uses it as authored
+    strength: f32,
+}
+
+impl NormalMap {
+    pub fn new (texture: Arc<dyn Texture>, strength: f32) -> Self {
+        NormalMap { texture, strength }
+    }
+}
+
+impl NormalPerturbation for NormalMap {
+    fn perturb (&self, rec: &HitRecord) -> Vec3 {
+        let (t, b, n) = outward_frame(rec);
+        let c = self.texture.evaluate(rec);
+        let x = (2.0 * c.r() - 1.0) * self.strength;
+        let y = (2.0 * c.g() - 1.0) * self.strength;
+        let z = (2.0 * c.b() - 1.0).max(1e-3);
+        (x * t + y * b + z * n).unit_vector()
+    }
+}
+
+// Bump map: a scalar height field (the texture's luminance) whose slope tilts the normal. The slope is found by
+// finite differences, stepping `delta` in u and v and the same distance along the tangents for 3D textures
+pub struct BumpMap {
+    height: Arc<dyn Texture>,
+    // Height of a texture value of 1, relative to one unit of u or v
+    scale: f32,
+    delta: f32,
+}
+
+impl BumpMap {
+    pub fn new (height: Arc<dyn Texture>, scale: f32) -> Self {
+        BumpMap { height, scale, delta: 1e-3 }
+    }
+
+    fn height_at (&self, u: f32, v: f32, p: &Point3) -> f32 {
+        self.height.value(u, v, p).luminance()
+    }
+}
+
+impl NormalPerturbation for BumpMap {
+    fn perturb (&self, rec: &HitRecord) -> Vec3 {
+        let (t, b, n) = outward_frame(rec);
+        let (u, v, p) = (rec.u(), rec.v(), rec.p());
+        let h = self.height_at(u, v, &p);
+        let dh_du = (self.height_at(u + self.delta, v, &(p + self.delta * t)) - h) / self.delta;
+        let dh_dv = (self.height_at(u, v + self.delta, &(p + self.delta * b)) - h) / self.delta;
+        (n - self.scale * (dh_du * t + dh_dv * b)).unit_vector()
+    }
+}
+
+// Wraps an object so every hit on it gets its shading normal perturbed. The geometric normal, and with it the side
+// rays leave from, is unchanged
+pub struct NormalMapped {
+    object: Arc<dyn Hittable>,
+    perturbation: Arc<dyn NormalPerturbation>,
+}
+
+impl NormalMapped {
+    pub fn new (object: Arc<dyn Hittable>, perturbation: Arc<dyn NormalPerturbation>) -> Self {
+        NormalMapped { object, perturbation }
+    }
+}
+
+impl Hittable for NormalMapped {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        self.object.hit(r, ray_t, rec)?;
+
+        // Image maps need the footprint to pick a mip level
+        rec.compute_differentials(&r);
+        let outward = self.perturbation.perturb(rec);
+        rec.set_shading_normal(if rec.front_face() { outward } else { -outward });
+        Some(rec.clone())
+    }
+
+    fn pdf_value (&self, origin: Point3, direction: Vec3) -> f32 {
+        self.object.pdf_value(origin, direction)
+    }
+
+    fn random (&self, origin: Point3) -> Vec3 {
+        self.object.random(origin)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        self.object.bounding_box()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::constants::INFINITY;
+    use crate::hittable::Sphere;
+    use crate::material::Lambertian;
+    use crate::texture::SolidColor;
+
+    fn sphere () -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 1.0, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))))
+    }
+
+    fn hit (object: &dyn Hittable, origin: Point3, direction: Vec3) -> HitRecord {
+        let mut rec = HitRecord::default();
+        object.hit(Ray::new(origin, direction), Interval::new(0.0001, INFINITY), &mut rec).unwrap()
+    }
+
+    #[test]
+    fn test_flat_normal_map_keeps_normal(){
+        let flat = Arc::new(SolidColor::from_rgb(0.5, 0.5, 1.0));
+        let mapped = NormalMapped::new(sphere(), Arc::new(NormalMap::new(flat, 1.0)));
+        let rec = hit(&mapped, Point3::new(0.2, 0.1, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((rec.shading_normal() - rec.normal()).length() < 1e-5);
+        assert_eq!(mapped.bounding_box(), sphere().bounding_box());
+    }
+
+    #[test]
+    fn test_normal_map_tilts_towards_tangent(){
+        // Red encodes a normal leaning along +u
+        let tilted = Arc::new(SolidColor::from_rgb(1.0, 0.5, 0.5));
+        let mapped = NormalMapped::new(sphere(), Arc::new(NormalMap::new(tilted, 1.0)));
+        let rec = hit(&mapped, Point3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+
+        assert!(rec.shading_normal().dot(&rec.tangent()) > 0.5);
+        assert!((rec.shading_normal().length() - 1.0).abs() < 1e-5);
+        // The geometric normal is untouched
+        assert!((rec.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_normal_map_from_inside_stays_on_ray_side(){
+        let tilted = Arc::new(SolidColor::from_rgb(0.8, 0.5, 0.9));
+        let mapped = NormalMapped::new(sphere(), Arc::new(NormalMap::new(tilted, 1.0)));
+        let rec = hit(&mapped, Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!(!rec.front_face());
+        assert!(rec.shading_normal().dot(&rec.normal()) > 0.0);
+    }
+
+    // Height rising linearly with u
+    struct Ramp;
+
+    impl Texture for Ramp {
+        fn value (&self, u: f32, _v: f32, _p: &Point3) -> Color {
+            Color::new(u, u, u)
+        }
+    }
+
+    #[test]
+    fn test_bump_map_tilts_against_slope(){
+        let constant = NormalMapped::new(sphere(), Arc::new(BumpMap::new(Arc::new(SolidColor::from_rgb(0.3, 0.3, 0.3)), 1.0)));
+        let rec = hit(&constant, Point3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        assert!((rec.shading_normal() - rec.normal()).length() < 1e-4);
+
+        let ramp = NormalMapped::new(sphere(), Arc::new(BumpMap::new(Arc::new(Ramp), 1.0)));
+        let rec = hit(&ramp, Point3::new(0.0, 0.0, 3.0), Vec3::new(0.0, 0.0, -1.0));
+        // Height increases along the tangent, so the surface faces back against it
+        assert!(rec.shading_normal().dot(&rec.tangent()) < -0.5);
+    }
+}
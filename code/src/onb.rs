@@ -0,0 +1,86 @@
+use crate::vec3::Vec3;
+
+// An orthonormal basis built around a single vector, used to take directions sampled around +z into world space
+#[derive(Copy, Clone, Debug)]
+pub struct Onb {
+    axis: [Vec3; 3],
+}
+
+impl Onb {
+    pub fn new (n: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let a = if w.x().abs() > 0.9 { Vec3::new(0.0, 1.0, 0.0) } else { Vec3::new(1.0, 0.0, 0.0) };
+        let v = w.cross(&a).unit_vector();
+        let u = w.cross(&v);
+
+        Onb { axis: [u, v, w] }
+    }
+
+    // A basis around `n` whose first axis follows `tangent`, for anisotropic shading. Falls back to an arbitrary
+    // orientation when the tangent is missing or parallel to the normal
+    pub fn with_tangent (n: &Vec3, tangent: &Vec3) -> Self {
+        let w = n.unit_vector();
+        let u = *tangent - tangent.dot(&w) * w;
+        if u.length_squared() < 1e-12 {
+            return Onb::new(n);
+        }
+        let u = u.unit_vector();
+        Onb { axis: [u, w.cross(&u), w] }
+    }
+
+    pub fn u (&self) -> Vec3 {
+        self.axis[0]
+    }
+
+    pub fn v (&self) -> Vec3 {
+        self.axis[1]
+    }
+
+    pub fn w (&self) -> Vec3 {
+        self.axis[2]
+    }
+
+    // Transform from basis coordinates to local space
+    pub fn transform (&self, v: &Vec3) -> Vec3 {
+        v[0] * self.axis[0] + v[1] * self.axis[1] + v[2] * self.axis[2]
+    }
+
+    // Inverse of transform: the coordinates of `v` along each axis
+    pub fn to_local (&self, v: &Vec3) -> Vec3 {
+        Vec3::new(v.dot(&self.axis[0]), v.dot(&self.axis[1]), v.dot(&self.axis[2]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_onb_orthonormal(){
+        let uvw = Onb::new(&Vec3::new(1.0, 2.0, 3.0));
+
+        assert!((uvw.u().length() - 1.0).abs() < 1e-5);
+        assert!((uvw.v().length() - 1.0).abs() < 1e-5);
+        assert!((uvw.w().length() - 1.0).abs() < 1e-5);
+        assert!(uvw.u().dot(&uvw.v()).abs() < 1e-5);
+        assert!(uvw.v().dot(&uvw.w()).abs() < 1e-5);
+        assert!(uvw.w().dot(&uvw.u()).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_onb_transform_z(){
+        let n = Vec3::new(0.0, 3.0, 4.0);
+        let uvw = Onb::new(&n);
+        let t = uvw.transform(&Vec3::new(0.0, 0.0, 1.0));
+
+        assert!((t - n.unit_vector()).length() < 1e-5);
+    }
+
+    #[test]
+    fn test_onb_with_tangent(){
+        let uvw = Onb::with_tangent(&Vec3::new(0.0, 0.0, 2.0), &Vec3::new(1.0, 0.0, 0.5));
+        assert!((uvw.u() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-5);
+        assert!((uvw.v() - Vec3::new(0.0, 1.0, 0.0)).length() < 1e-5);
+        assert!((uvw.w() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-5);
+    }
+}
@@ -0,0 +1,133 @@
+use crate::vec3::{Vec3, Point3};
+use crate::onb::Onb;
+use crate::hittable::Hittable;
+use crate::constants::{PI, random_generator};
+
+// A probability density function over directions. `generate` draws a direction and `value` returns its density
+pub trait Pdf {
+    fn value (&self, direction: &Vec3) -> f32;
+    fn generate (&self) -> Vec3;
+}
+
+// Uniform density over the whole sphere of directions
+#[derive(Default)]
+pub struct SpherePdf;
+
+impl SpherePdf {
+    pub fn new () -> Self {
+        SpherePdf
+    }
+}
+
+impl Pdf for SpherePdf {
+    fn value (&self, _direction: &Vec3) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn generate (&self) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+// Cosine-weighted density around a surface normal, matching a Lambertian surface
+pub struct CosinePdf {
+    uvw: Onb,
+}
+
+impl CosinePdf {
+    pub fn new (w: &Vec3) -> Self {
+        CosinePdf { uvw: Onb::new(w) }
+    }
+}
+
+impl Pdf for CosinePdf {
+    fn value (&self, direction: &Vec3) -> f32 {
+        let cosine_theta = direction.unit_vector().dot(&self.uvw.w());
+        (cosine_theta / PI).max(0.0)
+    }
+
+    fn generate (&self) -> Vec3 {
+        self.uvw.transform(&Vec3::random_cosine_direction())
+    }
+}
+
+// Density of directions from `origin` towards a hittable, used to sample lights directly
+pub struct HittablePdf<'a> {
+    objects: &'a dyn Hittable,
+    origin: Point3,
+}
+
+impl<'a> HittablePdf<'a> {
+    pub fn new (objects: &'a dyn Hittable, origin: Point3) -> Self {
+        HittablePdf { objects, origin }
+    }
+}
+
+impl Pdf for HittablePdf<'_> {
+    fn value (&self, direction: &Vec3) -> f32 {
+        self.objects.pdf_value(self.origin, *direction)
+    }
+
+    fn generate (&self) -> Vec3 {
+        self.objects.random(self.origin)
+    }
+}
+
+// An equal-weight blend of two densities
+pub struct MixturePdf<'a> {
+    p: [&'a dyn Pdf; 2],
+}
+
+impl<'a> MixturePdf<'a> {
+    pub fn new (p0: &'a dyn Pdf, p1: &'a dyn Pdf) -> Self {
+        MixturePdf { p: [p0, p1] }
+    }
+}
+
+impl Pdf for MixturePdf<'_> {
+    fn value (&self, direction: &Vec3) -> f32 {
+        0.5 * self.p[0].value(direction) + 0.5 * self.p[1].value(direction)
+    }
+
+    fn generate (&self) -> Vec3 {
+        if random_generator() < 0.5 {
+            self.p[0].generate()
+        } else {
+            self.p[1].generate()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cosine_pdf_value(){
+        let pdf = CosinePdf::new(&Vec3::new(0.0, 1.0, 0.0));
+
+        assert!((pdf.value(&Vec3::new(0.0, 1.0, 0.0)) - 1.0 / PI).abs() < 1e-6);
+        assert_eq!(pdf.value(&Vec3::new(0.0, -1.0, 0.0)), 0.0);
+    }
+
+    #[test]
+    fn test_cosine_pdf_generate_in_hemisphere(){
+        let n = Vec3::new(1.0, 1.0, 0.0);
+        let pdf = CosinePdf::new(&n);
+
+        for _ in 0..100 {
+            assert!(pdf.generate().dot(&n) >= 0.0);
+        }
+    }
+
+    #[test]
+    fn test_mixture_pdf_value(){
+        let cosine = CosinePdf::new(&Vec3::new(0.0, 0.0, 1.0));
+        let sphere = SpherePdf::new();
+        let mixture = MixturePdf::new(&cosine, &sphere);
+        let d = Vec3::new(0.0, 0.0, 1.0);
+
+        let expected = 0.5 / PI + 0.5 / (4.0 * PI);
+        assert!((mixture.value(&d) - expected).abs() < 1e-6);
+    }
+}
@@ -0,0 +1,130 @@
+use crate::vec3::{Vec3, Point3};
+use crate::constants::seeded_generator;
+use rand::Rng;
+use rand::seq::SliceRandom;
+
+// Ken Perlin's gradient noise: random unit gradients on an integer lattice, blended with a smooth Hermite curve.
+// The lattice is built from a seed so the same seed always gives the same pattern
+pub struct Perlin {
+    gradients: Vec<Vec3>,
+    perm_x: Vec<usize>,
+    perm_y: Vec<usize>,
+    perm_z: Vec<usize>,
+}
+
+impl Perlin {
+    const POINT_COUNT: usize = 256;
+
+    pub fn new (seed: u64) -> Self {
+        let mut rng = seeded_generator(seed);
+
+        let mut gradients = Vec::with_capacity(Perlin::POINT_COUNT);
+        while gradients.len() < Perlin::POINT_COUNT {
+            let p = Vec3::new(rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0), rng.gen_range(-1.0..1.0));
+            let lensq = p.length_squared();
+            if (1e-8..=1.0).contains(&lensq) {
+                gradients.push(p / lensq.sqrt());
+            }
+        }
+
+        let permutation = |rng: &mut rand::rngs::StdRng| {
+            let mut p: Vec<usize> = (0..Perlin::POINT_COUNT).collect();
+            p.shuffle(rng);
+            p
+        };
+        let perm_x = permutation(&mut rng);
+        let perm_y = permutation(&mut rng);
+        let perm_z = permutation(&mut rng);
+
+        Perlin { gradients, perm_x, perm_y, perm_z }
+    }
+
+    // Smooth noise in roughly [-1, 1] that is zero at every lattice point
+    pub fn noise (&self, p: &Point3) -> f32 {
+        let (fx, fy, fz) = (p.x().floor(), p.y().floor(), p.z().floor());
+        let (u, v, w) = (p.x() - fx, p.y() - fy, p.z() - fz);
+        let (i, j, k) = (fx as i64, fy as i64, fz as i64);
+
+        let mask = Perlin::POINT_COUNT as i64 - 1;
+        let mut corners = [[[Vec3::default(); 2]; 2]; 2];
+        for (di, plane) in corners.iter_mut().enumerate() {
+            for (dj, row) in plane.iter_mut().enumerate() {
+                for (dk, corner) in row.iter_mut().enumerate() {
+                    let index = self.perm_x[((i + di as i64) & mask) as usize]
+                        ^ self.perm_y[((j + dj as i64) & mask) as usize]
+                        ^ self.perm_z[((k + dk as i64) & mask) as usize];
+                    *corner = self.gradients[index];
+                }
+            }
+        }
+
+        Perlin::interpolate(&corners, u, v, w)
+    }
+
+    fn interpolate (corners: &[[[Vec3; 2]; 2]; 2], u: f32, v: f32, w: f32) -> f32 {
+        let uu = u * u * (3.0 - 2.0 * u);
+        let vv = v * v * (3.0 - 2.0 * v);
+        let ww = w * w * (3.0 - 2.0 * w);
+
+        let mut accum = 0.0;
+        for (i, plane) in corners.iter().enumerate() {
+            for (j, row) in plane.iter().enumerate() {
+                for (k, corner) in row.iter().enumerate() {
+                    let (fi, fj, fk) = (i as f32, j as f32, k as f32);
+                    let weight = Vec3::new(u - fi, v - fj, w - fk);
+                    accum += (fi * uu + (1.0 - fi) * (1.0 - uu))
+                        * (fj * vv + (1.0 - fj) * (1.0 - vv))
+                        * (fk * ww + (1.0 - fk) * (1.0 - ww))
+                        * corner.dot(&weight);
+                }
+            }
+        }
+        accum
+    }
+
+    // Fractal sum of `octaves` noise layers; each layer has `lacunarity` times the frequency and `gain` times the
+    // amplitude of the one before
+    pub fn fbm (&self, p: &Point3, octaves: u32, lacunarity: f32, gain: f32) -> f32 {
+        let mut accum = 0.0;
+        let mut temp = *p;
+        let mut weight = 1.0;
+        for _ in 0..octaves {
+            accum += weight * self.noise(&temp);
+            weight *= gain;
+            temp *= lacunarity;
+        }
+        accum
+    }
+
+    // Absolute value of a standard fractal sum (doubling frequency, halving amplitude), giving billowy turbulence
+    pub fn turbulence (&self, p: &Point3, depth: u32) -> f32 {
+        self.fbm(p, depth, 2.0, 0.5).abs()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_perlin_is_deterministic(){
+        let a = Perlin::new(42);
+        let b = Perlin::new(42);
+        let c = Perlin::new(43);
+        let p = Point3::new(1.3, -2.7, 0.4);
+        assert_eq!(a.noise(&p), b.noise(&p));
+        assert_ne!(a.noise(&p), c.noise(&p));
+    }
+
+    #[test]
+    fn test_perlin_range_and_lattice(){
+        let perlin = Perlin::new(1);
+        assert_eq!(perlin.noise(&Point3::new(3.0, -1.0, 7.0)), 0.0);
+        for i in 0..1000 {
+            let t = i as f32 * 0.173;
+            let n = perlin.noise(&Point3::new(t, t * 0.7, -t * 1.3));
+            assert!((-1.1..=1.1).contains(&n));
+        }
+        assert!(perlin.turbulence(&Point3::new(0.3, 0.6, 0.9), 7) >= 0.0);
+    }
+}
@@ -0,0 +1,122 @@
+use crate::vec3::Vec3;
+use crate::onb::Onb;
+use crate::pdf::Pdf;
+use crate::constants::{PI, random_generator};
+use std::sync::Arc;
+
+// The angular distribution of light scattered inside a medium. Angles are measured from the direction of travel
+pub trait PhaseFunction: Send + Sync {
+    // Density over the sphere of scattering by an angle with cosine `cos_theta` from the direction of travel
+    fn p (&self, cos_theta: f32) -> f32;
+
+    // Draws a new direction of travel for light moving along `direction`
+    fn sample (&self, direction: &Vec3) -> Vec3;
+}
+
+// Scatters equally in every direction
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Isotropic;
+
+impl PhaseFunction for Isotropic {
+    fn p (&self, _cos_theta: f32) -> f32 {
+        1.0 / (4.0 * PI)
+    }
+
+    fn sample (&self, _direction: &Vec3) -> Vec3 {
+        Vec3::random_unit_vector()
+    }
+}
+
+// Henyey-Greenstein phase function: the angular distribution of light scattered inside a medium, controlled by the
+// mean cosine `g` between the incoming and scattered directions. g > 0 scatters forwards, g < 0 backwards, 0 evenly
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct HenyeyGreenstein {
+    g: f32,
+}
+
+impl HenyeyGreenstein {
+    pub fn new (g: f32) -> Self {
+        HenyeyGreenstein { g: g.clamp(-0.99, 0.99) }
+    }
+
+    pub fn g (&self) -> f32 {
+        self.g
+    }
+}
+
+impl PhaseFunction for HenyeyGreenstein {
+    fn p (&self, cos_theta: f32) -> f32 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * PI * denominator * denominator.sqrt())
+    }
+
+    fn sample (&self, direction: &Vec3) -> Vec3 {
+        let g = self.g;
+        let u = random_generator();
+        let cos_theta = if g.abs() < 1e-3 {
+            1.0 - 2.0 * u
+        } else {
+            let ratio = (1.0 - g * g) / (1.0 - g + 2.0 * g * u);
+            (1.0 + g * g - ratio * ratio) / (2.0 * g)
+        }.clamp(-1.0, 1.0);
+
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let phi = 2.0 * PI * random_generator();
+        Onb::new(direction).transform(&Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta))
+    }
+}
+
+// Sampling density of a phase function for light travelling along `direction`
+pub struct PhasePdf {
+    direction: Vec3,
+    phase: Arc<dyn PhaseFunction>,
+}
+
+impl PhasePdf {
+    pub fn new (direction: &Vec3, phase: Arc<dyn PhaseFunction>) -> Self {
+        PhasePdf { direction: direction.unit_vector(), phase }
+    }
+}
+
+impl Pdf for PhasePdf {
+    fn value (&self, direction: &Vec3) -> f32 {
+        self.phase.p(self.direction.dot(&direction.unit_vector()))
+    }
+
+    fn generate (&self) -> Vec3 {
+        self.phase.sample(&self.direction)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_phase_functions_normalized(){
+        let phases: [Box<dyn PhaseFunction>; 4] = [
+            Box::new(Isotropic),
+            Box::new(HenyeyGreenstein::new(-0.5)),
+            Box::new(HenyeyGreenstein::new(0.0)),
+            Box::new(HenyeyGreenstein::new(0.7)),
+        ];
+        for phase in phases {
+            // Integrate over cos_theta with the azimuth contributing 2 pi
+            let n = 10000;
+            let sum: f32 = (0..n).map(|i| phase.p(-1.0 + 2.0 * (i as f32 + 0.5) / n as f32) * 2.0 / n as f32).sum();
+            assert!((sum * 2.0 * PI - 1.0).abs() < 1e-2, "integrated to {}", sum * 2.0 * PI);
+        }
+    }
+
+    #[test]
+    fn test_henyey_greenstein_mean_cosine(){
+        let direction = Vec3::new(0.0, 1.0, 0.0);
+        for g in [-0.3, 0.0, 0.8] {
+            let phase = HenyeyGreenstein::new(g);
+            let n = 50000;
+            let mean: f32 = (0..n).map(|_| phase.sample(&direction).dot(&direction)).sum::<f32>() / n as f32;
+            assert!((mean - g).abs() < 0.02, "g {} sampled mean cosine {}", g, mean);
+        }
+    }
+}
@@ -0,0 +1,489 @@
+use crate::vec3::Vec3;
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::microfacet::{TrowbridgeReitz, fresnel_schlick, fresnel_dielectric, reflect_about};
+use crate::onb::Onb;
+use crate::pdf::Pdf;
+use crate::texture::{Texture, SolidColor};
+use crate::constants::{PI, random_generator};
+use std::sync::Arc;
+
+// Disney's principled BSDF (Burley 2012, with the 2015 specular transmission extension): one material whose
+// artist-facing parameters, all in [0, 1], blend a retro-reflective diffuse base with sheen, an anisotropic GGX
+// specular lobe, a metallic lobe tinted by the base color, a clearcoat layer and rough dielectric transmission.
+// Each lobe is importance sampled, picked in proportion to its share of the reflected energy
+pub struct PrincipledBsdf {
+    base_color: Arc<dyn Texture>,
+    metallic: f32,
+    roughness: f32,
+    // Dielectric reflectance at normal incidence, scaled so 0.5 is the usual 4%
+    specular: f32,
+    // How much the dielectric highlight takes on the base color's hue
+    specular_tint: f32,
+    // Stretches the highlight along the hit's tangent
+    anisotropic: f32,
+    // Extra grazing reflection for cloth
+    sheen: f32,
+    sheen_tint: f32,
+    // Strength and glossiness of a second, clear specular layer on top
+    clearcoat: f32,
+    clearcoat_gloss: f32,
+    // Fraction of the non-metallic part that is glass rather than diffuse
+    specular_transmission: f32,
+    // Refractive index of the transmissive part
+    ior: f32,
+}
+
+impl PrincipledBsdf {
+    pub fn new (base_color: Color) -> Self {
+        PrincipledBsdf::from_texture(Arc::new(SolidColor::new(base_color)))
+    }
+
+    // Defaults describe a plain dielectric of medium roughness
+    pub fn from_texture (base_color: Arc<dyn Texture>) -> Self {
+        PrincipledBsdf {
+            base_color,
+            metallic: 0.0,
+            roughness: 0.5,
+            specular: 0.5,
+            specular_tint: 0.0,
+            anisotropic: 0.0,
+            sheen: 0.0,
+            sheen_tint: 0.5,
+            clearcoat: 0.0,
+            clearcoat_gloss: 1.0,
+            specular_transmission: 0.0,
+            ior: 1.5,
+        }
+    }
+
+    pub fn with_metallic (mut self, metallic: f32) -> Self {
+        self.metallic = metallic.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_roughness (mut self, roughness: f32) -> Self {
+        self.roughness = roughness.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_specular (mut self, specular: f32, tint: f32) -> Self {
+        self.specular = specular.clamp(0.0, 1.0);
+        self.specular_tint = tint.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_anisotropic (mut self, anisotropic: f32) -> Self {
+        self.anisotropic = anisotropic.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_sheen (mut self, sheen: f32, tint: f32) -> Self {
+        self.sheen = sheen.clamp(0.0, 1.0);
+        self.sheen_tint = tint.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_clearcoat (mut self, clearcoat: f32, gloss: f32) -> Self {
+        self.clearcoat = clearcoat.clamp(0.0, 1.0);
+        self.clearcoat_gloss = gloss.clamp(0.0, 1.0);
+        self
+    }
+
+    pub fn with_transmission (mut self, specular_transmission: f32, ior: f32) -> Self {
+        self.specular_transmission = specular_transmission.clamp(0.0, 1.0);
+        self.ior = ior.max(1.0);
+        self
+    }
+
+    // The BSDF at `rec` seen from the direction `r_in` arrives from
+    fn lobes (&self, r_in: &Ray, rec: &HitRecord) -> PrincipledLobes {
+        let uvw = Onb::with_tangent(&rec.shading_normal(), &rec.tangent());
+        let wo = uvw.to_local(&-r_in.direction().unit_vector());
+        let base_color = self.base_color.evaluate(rec);
+        let luminance = base_color.luminance();
+        let tint = if luminance > 0.0 { base_color / luminance } else { Color::new(1.0, 1.0, 1.0) };
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        let entering = rec.front_face();
+        let (diffuse_weight, glass_weight) = if entering {
+            let dielectric = 1.0 - self.metallic;
+            (dielectric * (1.0 - self.specular_transmission), dielectric * self.specular_transmission)
+        } else {
+            // Only paths that refracted in reach the back face, and they leave through a bare dielectric interface
+            (0.0, 1.0)
+        };
+
+        let mut lobes = PrincipledLobes {
+            uvw,
+            wo,
+            base_color,
+            roughness: self.roughness,
+            metallic: if entering { self.metallic } else { 0.0 },
+            specular_f0: 0.08 * self.specular * ((1.0 - self.specular_tint) * white + self.specular_tint * tint),
+            sheen_color: self.sheen * ((1.0 - self.sheen_tint) * white + self.sheen_tint * tint),
+            clearcoat: if entering { self.clearcoat } else { 0.0 },
+            clearcoat_alpha: 0.1 + (0.001 - 0.1) * self.clearcoat_gloss,
+            distribution: TrowbridgeReitz::from_roughness_anisotropic(self.roughness, self.anisotropic),
+            eta: if entering { self.ior } else { 1.0 / self.ior },
+            entering,
+            diffuse_weight,
+            glass_weight,
+            probabilities: [0.0; 4],
+        };
+        lobes.probabilities = lobes.lobe_probabilities();
+        lobes
+    }
+}
+
+impl Material for PrincipledBsdf {
+    fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let lobes = self.lobes(r_in, rec);
+        // A perturbed shading normal can face away from the viewer; such paths are absorbed
+        if lobes.wo.z() <= 0.0 {
+            return None;
+        }
+        Some(ScatterRecord::Bsdf { pdf: Box::new(lobes) })
+    }
+
+    fn eval (&self, r_in: &Ray, rec: &HitRecord, scattered: &Ray) -> Color {
+        let lobes = self.lobes(r_in, rec);
+        let wi = lobes.uvw.to_local(&scattered.direction().unit_vector());
+        lobes.f(&wi) * wi.z().abs()
+    }
+
+    fn albedo (&self, rec: &HitRecord) -> Color {
+        self.base_color.evaluate(rec)
+    }
+}
+
+// Schlick's Fresnel weight (1 - cos)^5
+fn schlick_weight (cos_theta: f32) -> f32 {
+    (1.0 - cos_theta.clamp(0.0, 1.0)).powi(5)
+}
+
+// Berry's distribution (GTR1), whose long tail gives the clearcoat its haze
+fn gtr1 (cos_theta_h: f32, alpha: f32) -> f32 {
+    let a2 = alpha * alpha;
+    (a2 - 1.0) / (PI * a2.ln() * (1.0 + (a2 - 1.0) * cos_theta_h * cos_theta_h))
+}
+
+// Refracts local `wo` through a microfacet normal `h` it lies above, or None under total internal reflection
+fn refract_about (wo: &Vec3, h: &Vec3, eta: f32) -> Option<Vec3> {
+    let cos_theta_i = wo.dot(h);
+    let sin2_theta_t = (1.0 - cos_theta_i * cos_theta_i).max(0.0) / (eta * eta);
+    if sin2_theta_t >= 1.0 {
+        return None;
+    }
+    let cos_theta_t = (1.0 - sin2_theta_t).sqrt();
+    Some(-*wo / eta + (cos_theta_i / eta - cos_theta_t) * *h)
+}
+
+// The BSDF evaluated for one hit and viewing direction, in a local frame with the shading normal along +z and the
+// tangent along +x. It is also the scattering PDF, so it gets handed to the integrator as is
+struct PrincipledLobes {
+    uvw: Onb,
+    wo: Vec3,
+    base_color: Color,
+    roughness: f32,
+    metallic: f32,
+    specular_f0: Color,
+    sheen_color: Color,
+    clearcoat: f32,
+    clearcoat_alpha: f32,
+    distribution: TrowbridgeReitz,
+    // Index on the far side of the surface over the index on the viewer's side
+    eta: f32,
+    entering: bool,
+    diffuse_weight: f32,
+    glass_weight: f32,
+    // Chance of sampling the diffuse, specular, clearcoat and glass lobes
+    probabilities: [f32; 4],
+}
+
+impl PrincipledLobes {
+    const DIFFUSE: usize = 0;
+    const SPECULAR: usize = 1;
+    const CLEARCOAT: usize = 2;
+    const GLASS: usize = 3;
+
+    // Roughly how much each lobe reflects towards the viewer, normalized
+    fn lobe_probabilities (&self) -> [f32; 4] {
+        let cos_o = self.wo.z();
+        let diffuse = self.diffuse_weight * (self.base_color.luminance() + self.sheen_color.luminance());
+        let specular = self.specular_color(cos_o).luminance();
+        let clearcoat = 0.25 * self.clearcoat * (0.04 + 0.96 * schlick_weight(cos_o));
+        let glass = self.glass_weight;
+
+        let total = diffuse + specular + clearcoat + glass;
+        if total <= 0.0 {
+            return [0.0, 1.0, 0.0, 0.0];
+        }
+        [diffuse / total, specular / total, clearcoat / total, glass / total]
+    }
+
+    // Fresnel reflectance of the opaque specular lobe: dielectric under the diffuse base and metal
+    fn specular_color (&self, cos_theta: f32) -> Color {
+        let dielectric = self.diffuse_weight * fresnel_schlick(self.specular_f0, cos_theta);
+        dielectric + self.metallic * fresnel_schlick(self.base_color, cos_theta)
+    }
+
+    // The BSDF for local `wi`, without the cosine factor
+    fn f (&self, wi: &Vec3) -> Color {
+        let wo = self.wo;
+        let (cos_o, cos_i) = (wo.z(), wi.z());
+        let black = Color::new(0.0, 0.0, 0.0);
+        if cos_o <= 0.0 || cos_i == 0.0 {
+            return black;
+        }
+
+        if cos_i < 0.0 {
+            return self.glass_weight * self.transmission(wi);
+        }
+
+        let h = (wo + *wi).unit_vector();
+        let cos_d = wi.dot(&h);
+        let mut f = black;
+
+        if self.diffuse_weight > 0.0 {
+            // Burley's diffuse darkens at grazing angles on smooth surfaces and turns retro-reflective on rough ones
+            let (fl, fv) = (schlick_weight(cos_i), schlick_weight(cos_o));
+            let rr = 2.0 * self.roughness * cos_d * cos_d;
+            let lambert = (1.0 - 0.5 * fl) * (1.0 - 0.5 * fv);
+            let retro = rr * (fl + fv + fl * fv * (rr - 1.0));
+            let sheen = schlick_weight(cos_d) * self.sheen_color;
+            f = f + self.diffuse_weight * ((lambert + retro) / PI * self.base_color + sheen);
+        }
+
+        let microfacet = self.distribution.d(&h) * self.distribution.g(&wo, wi) / (4.0 * cos_o * cos_i);
+        f = f + self.specular_color(cos_d) * microfacet;
+
+        if self.clearcoat > 0.0 {
+            let coat = TrowbridgeReitz::new(0.25);
+            let fresnel = 0.04 + 0.96 * schlick_weight(cos_d);
+            let d = gtr1(h.z(), self.clearcoat_alpha);
+            let value = 0.25 * self.clearcoat * d * fresnel * coat.g1(&wo) * coat.g1(wi) / (4.0 * cos_o * cos_i);
+            f = f + Color::new(1.0, 1.0, 1.0) * value;
+        }
+
+        if self.glass_weight > 0.0 {
+            let fresnel = fresnel_dielectric(wo.dot(&h), self.eta);
+            f = f + Color::new(1.0, 1.0, 1.0) * (self.glass_weight * fresnel * microfacet);
+        }
+
+        f
+    }
+
+    // Rough dielectric BTDF (Walter et al. 2007). Radiance is compressed by eta^2 on the way into the denser side.
+    // The base color tints light entering the material
+    fn transmission (&self, wi: &Vec3) -> Color {
+        let wo = self.wo;
+        let h = match self.transmission_normal(wi) {
+            Some(h) => h,
+            None => return Color::new(0.0, 0.0, 0.0),
+        };
+        let (o_h, i_h) = (wo.dot(&h), wi.dot(&h));
+        let fresnel = fresnel_dielectric(o_h, self.eta);
+        let denominator = i_h + o_h / self.eta;
+        let value = self.distribution.d(&h) * self.distribution.g(&wo, wi) * (1.0 - fresnel) * (i_h * o_h).abs()
+            / ((wi.z() * wo.z()).abs() * denominator * denominator * self.eta * self.eta);
+
+        let tint = if self.entering { self.base_color } else { Color::new(1.0, 1.0, 1.0) };
+        tint * value
+    }
+
+    // The microfacet normal that refracts wo into wi, if wi is reachable by refraction at all
+    fn transmission_normal (&self, wi: &Vec3) -> Option<Vec3> {
+        let h = self.wo + self.eta * *wi;
+        if h.length_squared() == 0.0 {
+            return None;
+        }
+        let h = h.unit_vector();
+        let h = if h.z() < 0.0 { -h } else { h };
+        if self.wo.dot(&h) <= 0.0 || wi.dot(&h) >= 0.0 {
+            return None;
+        }
+        Some(h)
+    }
+
+    // Density of each lobe's own sampling strategy for local `wi`
+    fn lobe_pdfs (&self, wi: &Vec3) -> [f32; 4] {
+        let wo = self.wo;
+        let mut pdfs = [0.0; 4];
+        if wi.z() > 0.0 {
+            let h = (wo + *wi).unit_vector();
+            let o_h = wo.dot(&h);
+            if o_h <= 0.0 {
+                return pdfs;
+            }
+            let reflection = self.distribution.visible_d(&wo, &h) / (4.0 * o_h);
+            pdfs[PrincipledLobes::DIFFUSE] = wi.z() / PI;
+            pdfs[PrincipledLobes::SPECULAR] = reflection;
+            pdfs[PrincipledLobes::CLEARCOAT] = gtr1(h.z(), self.clearcoat_alpha) * h.z() / (4.0 * o_h);
+            pdfs[PrincipledLobes::GLASS] = reflection * fresnel_dielectric(o_h, self.eta);
+        } else if let Some(h) = self.transmission_normal(wi) {
+            let (o_h, i_h) = (wo.dot(&h), wi.dot(&h));
+            let denominator = i_h + o_h / self.eta;
+            let jacobian = i_h.abs() / (denominator * denominator);
+            pdfs[PrincipledLobes::GLASS] =
+                self.distribution.visible_d(&wo, &h) * (1.0 - fresnel_dielectric(o_h, self.eta)) * jacobian;
+        }
+        pdfs
+    }
+
+    // Draws a local direction from one lobe's sampling strategy
+    fn sample_lobe (&self, lobe: usize) -> Vec3 {
+        let wo = self.wo;
+        match lobe {
+            PrincipledLobes::DIFFUSE => Vec3::random_cosine_direction(),
+            PrincipledLobes::SPECULAR => {
+                let h = self.distribution.sample_visible_normal(&wo, random_generator(), random_generator());
+                reflect_about(&wo, &h)
+            }
+            PrincipledLobes::CLEARCOAT => {
+                let a2 = self.clearcoat_alpha * self.clearcoat_alpha;
+                let cos_theta = ((1.0 - a2.powf(1.0 - random_generator())) / (1.0 - a2)).clamp(0.0, 1.0).sqrt();
+                let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
+                let phi = 2.0 * PI * random_generator();
+                let h = Vec3::new(sin_theta * phi.cos(), sin_theta * phi.sin(), cos_theta);
+                reflect_about(&wo, &h)
+            }
+            _ => {
+                // Reflect or refract through a visible normal in proportion to the Fresnel reflectance
+                let h = self.distribution.sample_visible_normal(&wo, random_generator(), random_generator());
+                if random_generator() < fresnel_dielectric(wo.dot(&h), self.eta) {
+                    return reflect_about(&wo, &h);
+                }
+                refract_about(&wo, &h, self.eta).unwrap_or_else(|| reflect_about(&wo, &h))
+            }
+        }
+    }
+}
+
+impl Pdf for PrincipledLobes {
+    fn value (&self, direction: &Vec3) -> f32 {
+        let wi = self.uvw.to_local(&direction.unit_vector());
+        let pdfs = self.lobe_pdfs(&wi);
+        (0..4).map(|lobe| self.probabilities[lobe] * pdfs[lobe]).sum()
+    }
+
+    fn generate (&self) -> Vec3 {
+        let u = random_generator();
+        let mut cumulative = 0.0;
+        let mut lobe = PrincipledLobes::GLASS;
+        for (index, probability) in self.probabilities.iter().enumerate() {
+            cumulative += probability;
+            if u < cumulative {
+                lobe = index;
+                break;
+            }
+        }
+        self.uvw.transform(&self.sample_lobe(lobe))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hit_from (wo: Vec3) -> (Ray, HitRecord) {
+        let mut rec = HitRecord::default();
+        let r_in = Ray::new(wo, -wo);
+        rec.set_face_normal(&r_in, Vec3::new(0.0, 0.0, 1.0));
+        (r_in, rec)
+    }
+
+    // Average of eval / pdf over directions drawn from the material's own PDF: the fraction of light it scatters
+    fn scattered_energy (material: &PrincipledBsdf, wo: Vec3, n: usize) -> Color {
+        let (r_in, rec) = hit_from(wo);
+        let pdf = match material.scatter(&r_in, &rec) {
+            Some(ScatterRecord::Bsdf { pdf }) => pdf,
+            _ => panic!("expected a bsdf"),
+        };
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            let scattered = Ray::new(rec.p(), pdf.generate());
+            let pdf_value = pdf.value(&scattered.direction());
+            if pdf_value > 0.0 {
+                sum = sum + material.eval(&r_in, &rec, &scattered) / pdf_value;
+            }
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn test_principled_pdf_integrates_to_one(){
+        let material = PrincipledBsdf::new(Color::new(0.8, 0.5, 0.3))
+            .with_roughness(0.4)
+            .with_metallic(0.3)
+            .with_sheen(0.5, 0.5)
+            .with_clearcoat(1.0, 0.5)
+            .with_transmission(0.5, 1.5);
+        let (r_in, rec) = hit_from(Vec3::new(0.3, 0.1, 0.8).unit_vector());
+        let pdf = match material.scatter(&r_in, &rec) {
+            Some(ScatterRecord::Bsdf { pdf }) => pdf,
+            _ => panic!("expected a bsdf"),
+        };
+
+        // Uniform sphere sampling has density 1 / 4 pi
+        let n = 400000;
+        let mut sum = 0.0;
+        for _ in 0..n {
+            sum += pdf.value(&Vec3::random_unit_vector()) * 4.0 * PI;
+        }
+        assert!((sum / n as f32 - 1.0).abs() < 0.05, "pdf integrated to {}", sum / n as f32);
+    }
+
+    #[test]
+    fn test_principled_sampling_matches_uniform_estimate(){
+        // Importance sampling and uniform sampling estimate the same scattered energy
+        let material = PrincipledBsdf::new(Color::new(0.9, 0.9, 0.9))
+            .with_roughness(0.6)
+            .with_anisotropic(0.5)
+            .with_clearcoat(0.5, 0.3)
+            .with_transmission(0.5, 1.5);
+        let wo = Vec3::new(0.5, 0.2, 0.7).unit_vector();
+        let sampled = scattered_energy(&material, wo, 40000).r();
+
+        let (r_in, rec) = hit_from(wo);
+        let n = 400000;
+        let mut uniform = 0.0;
+        for _ in 0..n {
+            let scattered = Ray::new(rec.p(), Vec3::random_unit_vector());
+            uniform += material.eval(&r_in, &rec, &scattered).r() * 4.0 * PI;
+        }
+        let uniform = uniform / n as f32;
+        assert!((sampled - uniform).abs() < 0.03, "sampled {} uniform {}", sampled, uniform);
+    }
+
+    #[test]
+    fn test_principled_energy(){
+        let wo = Vec3::new(0.4, 0.0, 0.9).unit_vector();
+        let white = Color::new(1.0, 1.0, 1.0);
+
+        let plastic = scattered_energy(&PrincipledBsdf::new(white), wo, 20000).r();
+        assert!((0.85..=1.05).contains(&plastic), "plastic scattered {}", plastic);
+
+        let metal = scattered_energy(&PrincipledBsdf::new(white).with_metallic(1.0).with_roughness(0.3), wo, 20000).r();
+        assert!((0.9..=1.02).contains(&metal), "metal scattered {}", metal);
+
+        // Smooth glass at normal incidence reflects 4% and transmits the rest, compressed by eta^2
+        let glass = PrincipledBsdf::new(white).with_roughness(0.0).with_transmission(1.0, 1.5);
+        let energy = scattered_energy(&glass, Vec3::new(0.0, 0.0, 1.0), 20000).r();
+        assert!((energy - (0.04 + 0.96 / 2.25)).abs() < 0.02, "glass scattered {}", energy);
+    }
+
+    #[test]
+    fn test_principled_transmits_only_with_transmission(){
+        let wo = Vec3::new(0.2, 0.0, 0.9).unit_vector();
+        let (r_in, rec) = hit_from(wo);
+        let below = Ray::new(rec.p(), Vec3::new(-0.1, 0.0, -1.0));
+
+        let opaque = PrincipledBsdf::new(Color::new(0.8, 0.8, 0.8)).with_roughness(0.3);
+        assert_eq!(opaque.eval(&r_in, &rec, &below), Color::new(0.0, 0.0, 0.0));
+        let glass = PrincipledBsdf::new(Color::new(0.8, 0.8, 0.8)).with_roughness(0.3).with_transmission(1.0, 1.5);
+        assert!(glass.eval(&r_in, &rec, &below).r() > 0.0);
+    }
+}
@@ -0,0 +1,806 @@
+use crate::vec3::{Vec3, Point3};
+use crate::ray::Ray;
+use crate::interval::Interval;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::aabb::Aabb;
+use crate::constants::{PI, degrees_to_radians};
+use std::sync::Arc;
+
+// Analytic surfaces of revolution about the y axis: cylinders, cones, paraboloids, hyperboloids and tori. Each is
+// built in its own frame and placed in the scene with an Instance. u runs around the axis, counterclockwise seen from
+// above starting at +x, up to the sweep angle, so parts of a turn can be cut away. Hits on caps report primitive id
+// 1 for the bottom and 2 for the top, and 0 on the curved surface
+
+// The surface's profile curve at a hit: distance from the axis and height as functions of v, with their derivatives
+struct Profile {
+    v: f32,
+    rho: f32,
+    drho: f32,
+    ddrho: f32,
+    dy: f32,
+    ddy: f32,
+}
+
+// A flat disk closing one end of a shape, facing down at the bottom or up at the top
+struct Cap {
+    y: f32,
+    radius: f32,
+    upward: bool,
+}
+
+// What sets one surface of revolution apart from another
+trait Revolution {
+    // Ray parameters where `r` meets the complete surface, in increasing order, written into `roots`. Returns how many
+    fn intersect (&self, r: &Ray, roots: &mut [f32; 4]) -> usize;
+
+    // The profile at a point on the complete surface, or None if that point is cut away
+    fn profile (&self, p: &Point3) -> Option<Profile>;
+
+    // Largest distance from the axis and the range of heights
+    fn extent (&self) -> (f32, f32, f32);
+
+    fn caps (&self) -> &[Cap];
+
+    fn phi_max (&self) -> f32;
+
+    fn mat (&self) -> &Arc<dyn Material>;
+}
+
+// Angle around the y axis, counterclockwise from +x seen from above, in [0, 2 pi)
+fn phi_of (p: &Point3) -> f32 {
+    let phi = (-p.z()).atan2(p.x());
+    if phi < 0.0 { phi + 2.0 * PI } else { phi }
+}
+
+// Sweep angle in radians from degrees, kept within one turn
+fn sweep (degrees: f32) -> f32 {
+    degrees_to_radians(degrees).clamp(1e-4, 2.0 * PI)
+}
+
+fn unit (v: Vec3) -> Vec3 {
+    if v.length_squared() > 0.0 { v.unit_vector() } else { v }
+}
+
+// Origin and direction in double precision, where the polynomial coefficients are formed
+fn ray64 (r: &Ray) -> ([f64; 3], [f64; 3]) {
+    let (o, d) = (r.origin(), r.direction());
+    ([o.x() as f64, o.y() as f64, o.z() as f64], [d.x() as f64, d.y() as f64, d.z() as f64])
+}
+
+// Real roots of a t^2 + b t + c, in increasing order
+fn solve_quadratic (a: f64, b: f64, c: f64, roots: &mut [f32; 4]) -> usize {
+    if a.abs() < 1e-12 {
+        if b.abs() < 1e-12 {
+            return 0;
+        }
+        roots[0] = (-c / b) as f32;
+        return 1;
+    }
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return 0;
+    }
+    // Avoids cancellation between b and the square root
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let (t0, t1) = if q == 0.0 { (0.0, 0.0) } else { (q / a, c / q) };
+    roots[0] = t0.min(t1) as f32;
+    roots[1] = t0.max(t1) as f32;
+    2
+}
+
+// Largest real root of x^3 + b x^2 + c x + d
+fn largest_cubic_root (b: f64, c: f64, d: f64) -> f64 {
+    let p = c - b * b / 3.0;
+    let q = 2.0 * b * b * b / 27.0 - b * c / 3.0 + d;
+    let discriminant = q * q / 4.0 + p * p * p / 27.0;
+    let z = if discriminant > 0.0 {
+        let root = discriminant.sqrt();
+        (-q / 2.0 + root).cbrt() + (-q / 2.0 - root).cbrt()
+    } else {
+        let rho = (-p / 3.0).sqrt();
+        let theta = if rho == 0.0 { 0.0 } else { (-q / (2.0 * rho * rho * rho)).clamp(-1.0, 1.0).acos() };
+        2.0 * rho * (theta / 3.0).cos()
+    };
+    let mut x = z - b / 3.0;
+    for _ in 0..2 {
+        let f = ((x + b) * x + c) * x + d;
+        let df = (3.0 * x + 2.0 * b) * x + c;
+        if df.abs() > 1e-12 {
+            x -= f / df;
+        }
+    }
+    x
+}
+
+// Real roots of x^4 + a x^3 + b x^2 + c x + d by Ferrari's method, polished with Newton steps. Unsorted
+fn solve_quartic (a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    // Depressed quartic y^4 + p y^2 + q y + r with x = y - a / 4
+    let shift = a / 4.0;
+    let p = b - 6.0 * shift * shift;
+    let q = c - 2.0 * b * shift + 8.0 * shift * shift * shift;
+    let r = d - c * shift + b * shift * shift - 3.0 * shift * shift * shift * shift;
+
+    let mut ys = Vec::with_capacity(4);
+    let mut push_quadratic = |b: f64, c: f64| {
+        let discriminant = b * b - 4.0 * c;
+        if discriminant >= 0.0 {
+            let root = discriminant.sqrt();
+            ys.push((-b - root) / 2.0);
+            ys.push((-b + root) / 2.0);
+        }
+    };
+    if q.abs() < 1e-12 {
+        // Biquadratic: a quadratic in y^2
+        let discriminant = p * p - 4.0 * r;
+        if discriminant >= 0.0 {
+            for y2 in [(-p - discriminant.sqrt()) / 2.0, (-p + discriminant.sqrt()) / 2.0] {
+                if y2 >= 0.0 {
+                    push_quadratic(0.0, -y2);
+                }
+            }
+        }
+    } else {
+        // Completing the square with the positive root m of the resolvent cubic splits it into two quadratics
+        let m = largest_cubic_root(p, p * p / 4.0 - r, -q * q / 8.0);
+        if m <= 0.0 {
+            return Vec::new();
+        }
+        let s = (2.0 * m).sqrt();
+        push_quadratic(-s, p / 2.0 + m + s * q / (4.0 * m));
+        push_quadratic(s, p / 2.0 + m - s * q / (4.0 * m));
+    }
+
+    ys.iter().map(|y| {
+        let mut x = y - shift;
+        for _ in 0..3 {
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if df.abs() > 1e-12 {
+                x -= f / df;
+            }
+        }
+        x
+    }).collect()
+}
+
+// Derivatives of the unit normal from the first and second derivatives of the surface, by the Weingarten equations
+fn weingarten (n: &Vec3, dpdu: &Vec3, dpdv: &Vec3, d2pduu: &Vec3, d2pduv: &Vec3, d2pdvv: &Vec3) -> (Vec3, Vec3) {
+    let (e1, f1, g1) = (dpdu.dot(dpdu), dpdu.dot(dpdv), dpdv.dot(dpdv));
+    let (e2, f2, g2) = (n.dot(d2pduu), n.dot(d2pduv), n.dot(d2pdvv));
+    let determinant = e1 * g1 - f1 * f1;
+    if determinant.abs() < 1e-12 {
+        return (Vec3::default(), Vec3::default());
+    }
+    let inverse = 1.0 / determinant;
+    let dndu = ((f2 * f1 - e2 * g1) * inverse) * *dpdu + ((e2 * f1 - f2 * e1) * inverse) * *dpdv;
+    let dndv = ((g2 * f1 - f2 * g1) * inverse) * *dpdu + ((f2 * f1 - g2 * e1) * inverse) * *dpdv;
+    (dndu, dndv)
+}
+
+// Shared intersection for all surfaces of revolution: the nearest of the curved surface within its sweep and the
+// caps, with normals, uvs and partial derivatives filled in
+fn hit_revolution (shape: &impl Revolution, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+    let phi_max = shape.phi_max();
+    let in_sweep = |p: &Point3| phi_of(p) <= phi_max;
+
+    // The nearest hit so far, and which cap it is on if any
+    let mut nearest: Option<(f32, Point3, Option<usize>)> = None;
+    let mut roots = [0.0; 4];
+    let count = shape.intersect(&r, &mut roots);
+    for &t in &roots[..count] {
+        if ray_t.surrounds(t) {
+            let p = r.at(t);
+            if in_sweep(&p) && shape.profile(&p).is_some() {
+                nearest = Some((t, p, None));
+                break;
+            }
+        }
+    }
+    for (index, cap) in shape.caps().iter().enumerate() {
+        if r.direction().y().abs() < 1e-12 {
+            continue;
+        }
+        let t = (cap.y - r.origin().y()) / r.direction().y();
+        let t_max = nearest.map_or(ray_t.max(), |(t, _, _)| t);
+        if !Interval::new(ray_t.min(), t_max).surrounds(t) {
+            continue;
+        }
+        let p = r.at(t);
+        if p.x() * p.x() + p.z() * p.z() <= cap.radius * cap.radius && in_sweep(&p) {
+            nearest = Some((t, p, Some(index)));
+        }
+    }
+
+    let (t, p, cap) = nearest?;
+    let (sin_phi, cos_phi) = phi_of(&p).sin_cos();
+    let around = Vec3::new(-sin_phi, 0.0, -cos_phi);
+    let radial = Vec3::new(cos_phi, 0.0, -sin_phi);
+    let mut hit_record = HitRecord::new(p, t, shape.mat().clone());
+
+    match cap {
+        None => {
+            let profile = shape.profile(&p)?;
+            let up = Vec3::new(0.0, 1.0, 0.0);
+            let dpdu = phi_max * profile.rho * around;
+            let dpdv = profile.drho * radial + profile.dy * up;
+            let d2pduu = -phi_max * phi_max * profile.rho * radial;
+            let d2pduv = phi_max * profile.drho * around;
+            let d2pdvv = profile.ddrho * radial + profile.ddy * up;
+            // The profile's tangent turned a quarter turn away from the axis
+            let normal = unit(profile.dy * radial - profile.drho * up);
+            let (dndu, dndv) = weingarten(&normal, &dpdu, &dpdv, &d2pduu, &d2pduv, &d2pdvv);
+
+            hit_record.set_face_normal(&r, normal);
+            hit_record.set_uv(phi_of(&p) / phi_max, profile.v);
+            hit_record.set_tangents(unit(around), unit(dpdv));
+            hit_record.set_partials(dpdu, dpdv, dndu, dndv);
+        }
+        Some(index) => {
+            // v runs from the center out to the rim
+            let cap = &shape.caps()[index];
+            let normal = Vec3::new(0.0, if cap.upward { 1.0 } else { -1.0 }, 0.0);
+            let distance = (p.x() * p.x() + p.z() * p.z()).sqrt();
+
+            hit_record.set_face_normal(&r, normal);
+            hit_record.set_uv(phi_of(&p) / phi_max, distance / cap.radius);
+            hit_record.set_tangents(around, radial);
+            hit_record.set_partials(phi_max * distance * around, cap.radius * radial, Vec3::default(), Vec3::default());
+            hit_record.set_primitive_id(if cap.upward { 2 } else { 1 });
+        }
+    }
+    *rec = hit_record.clone();
+    Some(hit_record)
+}
+
+fn revolution_box (shape: &impl Revolution) -> Aabb {
+    let (radius, y_min, y_max) = shape.extent();
+    Aabb::new(Point3::new(-radius, y_min, -radius), Point3::new(radius, y_max, radius))
+}
+
+// Cylinder of `radius` around the y axis from y = 0 up to `height`, open at both ends unless capped
+pub struct Cylinder {
+    radius: f32,
+    height: f32,
+    phi_max: f32,
+    caps: Vec<Cap>,
+    mat: Arc<dyn Material>,
+}
+
+impl Cylinder {
+    pub fn new (radius: f32, height: f32, mat: Arc<dyn Material>) -> Self {
+        Cylinder { radius, height, phi_max: 2.0 * PI, caps: Vec::new(), mat }
+    }
+
+    // Closes both ends with disks
+    pub fn with_caps (mut self) -> Self {
+        self.caps = vec![Cap { y: 0.0, radius: self.radius, upward: false }, Cap { y: self.height, radius: self.radius, upward: true }];
+        self
+    }
+
+    // Keeps only `degrees` of the turn around the axis
+    pub fn with_sweep (mut self, degrees: f32) -> Self {
+        self.phi_max = sweep(degrees);
+        self
+    }
+}
+
+impl Revolution for Cylinder {
+    fn intersect (&self, r: &Ray, roots: &mut [f32; 4]) -> usize {
+        let (o, d) = ray64(r);
+        let a = d[0] * d[0] + d[2] * d[2];
+        let b = 2.0 * (d[0] * o[0] + d[2] * o[2]);
+        let c = o[0] * o[0] + o[2] * o[2] - (self.radius as f64).powi(2);
+        solve_quadratic(a, b, c, roots)
+    }
+
+    fn profile (&self, p: &Point3) -> Option<Profile> {
+        if !(0.0..=self.height).contains(&p.y()) {
+            return None;
+        }
+        Some(Profile { v: p.y() / self.height, rho: self.radius, drho: 0.0, ddrho: 0.0, dy: self.height, ddy: 0.0 })
+    }
+
+    fn extent (&self) -> (f32, f32, f32) {
+        (self.radius, 0.0, self.height)
+    }
+
+    fn caps (&self) -> &[Cap] {
+        &self.caps
+    }
+
+    fn phi_max (&self) -> f32 {
+        self.phi_max
+    }
+
+    fn mat (&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+}
+
+// Cone with a base of `radius` at y = 0 narrowing to its apex at y = `height`, open at the base unless capped
+pub struct Cone {
+    radius: f32,
+    height: f32,
+    phi_max: f32,
+    caps: Vec<Cap>,
+    mat: Arc<dyn Material>,
+}
+
+impl Cone {
+    pub fn new (radius: f32, height: f32, mat: Arc<dyn Material>) -> Self {
+        Cone { radius, height, phi_max: 2.0 * PI, caps: Vec::new(), mat }
+    }
+
+    // Closes the base with a disk
+    pub fn with_caps (mut self) -> Self {
+        self.caps = vec![Cap { y: 0.0, radius: self.radius, upward: false }];
+        self
+    }
+
+    pub fn with_sweep (mut self, degrees: f32) -> Self {
+        self.phi_max = sweep(degrees);
+        self
+    }
+}
+
+impl Revolution for Cone {
+    // x^2 + z^2 = k (h - y)^2 with k = (radius / height)^2
+    fn intersect (&self, r: &Ray, roots: &mut [f32; 4]) -> usize {
+        let (o, d) = ray64(r);
+        let h = self.height as f64;
+        let k = (self.radius as f64 / h).powi(2);
+        let a = d[0] * d[0] + d[2] * d[2] - k * d[1] * d[1];
+        let b = 2.0 * (d[0] * o[0] + d[2] * o[2] + k * d[1] * (h - o[1]));
+        let c = o[0] * o[0] + o[2] * o[2] - k * (h - o[1]) * (h - o[1]);
+        solve_quadratic(a, b, c, roots)
+    }
+
+    fn profile (&self, p: &Point3) -> Option<Profile> {
+        if !(0.0..=self.height).contains(&p.y()) {
+            return None;
+        }
+        let v = p.y() / self.height;
+        Some(Profile { v, rho: self.radius * (1.0 - v), drho: -self.radius, ddrho: 0.0, dy: self.height, ddy: 0.0 })
+    }
+
+    fn extent (&self) -> (f32, f32, f32) {
+        (self.radius, 0.0, self.height)
+    }
+
+    fn caps (&self) -> &[Cap] {
+        &self.caps
+    }
+
+    fn phi_max (&self) -> f32 {
+        self.phi_max
+    }
+
+    fn mat (&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+}
+
+// Bowl-shaped paraboloid with its vertex at the origin, widening to `radius` at y = `height`, open at the top unless
+// capped. Reflectors and dishes
+pub struct Paraboloid {
+    radius: f32,
+    height: f32,
+    phi_max: f32,
+    caps: Vec<Cap>,
+    mat: Arc<dyn Material>,
+}
+
+impl Paraboloid {
+    pub fn new (radius: f32, height: f32, mat: Arc<dyn Material>) -> Self {
+        Paraboloid { radius, height, phi_max: 2.0 * PI, caps: Vec::new(), mat }
+    }
+
+    // Closes the rim with a disk
+    pub fn with_caps (mut self) -> Self {
+        self.caps = vec![Cap { y: self.height, radius: self.radius, upward: true }];
+        self
+    }
+
+    pub fn with_sweep (mut self, degrees: f32) -> Self {
+        self.phi_max = sweep(degrees);
+        self
+    }
+}
+
+impl Revolution for Paraboloid {
+    // x^2 + z^2 = k y with k = radius^2 / height
+    fn intersect (&self, r: &Ray, roots: &mut [f32; 4]) -> usize {
+        let (o, d) = ray64(r);
+        let k = (self.radius as f64).powi(2) / self.height as f64;
+        let a = d[0] * d[0] + d[2] * d[2];
+        let b = 2.0 * (d[0] * o[0] + d[2] * o[2]) - k * d[1];
+        let c = o[0] * o[0] + o[2] * o[2] - k * o[1];
+        solve_quadratic(a, b, c, roots)
+    }
+
+    fn profile (&self, p: &Point3) -> Option<Profile> {
+        if !(0.0..=self.height).contains(&p.y()) {
+            return None;
+        }
+        // The radius grows with the square root of v, which has no derivative at the vertex itself
+        let v = (p.y() / self.height).max(1e-6);
+        let rho = self.radius * v.sqrt();
+        Some(Profile { v, rho, drho: rho / (2.0 * v), ddrho: -rho / (4.0 * v * v), dy: self.height, ddy: 0.0 })
+    }
+
+    fn extent (&self) -> (f32, f32, f32) {
+        (self.radius, 0.0, self.height)
+    }
+
+    fn caps (&self) -> &[Cap] {
+        &self.caps
+    }
+
+    fn phi_max (&self) -> f32 {
+        self.phi_max
+    }
+
+    fn mat (&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+}
+
+// Hyperboloid of one sheet centered on the origin, `waist_radius` across at y = 0 and `end_radius` at y = +-height / 2,
+// like a cooling tower. An end radius smaller than the waist gives a barrel instead. Open at both ends unless capped
+pub struct Hyperboloid {
+    waist_radius: f32,
+    end_radius: f32,
+    height: f32,
+    // x^2 + z^2 - k y^2 = waist_radius^2
+    k: f32,
+    phi_max: f32,
+    caps: Vec<Cap>,
+    mat: Arc<dyn Material>,
+}
+
+impl Hyperboloid {
+    pub fn new (waist_radius: f32, end_radius: f32, height: f32, mat: Arc<dyn Material>) -> Self {
+        let half = 0.5 * height;
+        let k = (end_radius * end_radius - waist_radius * waist_radius) / (half * half);
+        Hyperboloid { waist_radius, end_radius, height, k, phi_max: 2.0 * PI, caps: Vec::new(), mat }
+    }
+
+    // Closes both ends with disks
+    pub fn with_caps (mut self) -> Self {
+        let half = 0.5 * self.height;
+        self.caps = vec![Cap { y: -half, radius: self.end_radius, upward: false }, Cap { y: half, radius: self.end_radius, upward: true }];
+        self
+    }
+
+    pub fn with_sweep (mut self, degrees: f32) -> Self {
+        self.phi_max = sweep(degrees);
+        self
+    }
+}
+
+impl Revolution for Hyperboloid {
+    fn intersect (&self, r: &Ray, roots: &mut [f32; 4]) -> usize {
+        let (o, d) = ray64(r);
+        let k = self.k as f64;
+        let a = d[0] * d[0] + d[2] * d[2] - k * d[1] * d[1];
+        let b = 2.0 * (d[0] * o[0] + d[2] * o[2] - k * d[1] * o[1]);
+        let c = o[0] * o[0] + o[2] * o[2] - k * o[1] * o[1] - (self.waist_radius as f64).powi(2);
+        solve_quadratic(a, b, c, roots)
+    }
+
+    fn profile (&self, p: &Point3) -> Option<Profile> {
+        let half = 0.5 * self.height;
+        if !(-half..=half).contains(&p.y()) {
+            return None;
+        }
+        let (y, h, a2) = (p.y(), self.height, self.waist_radius * self.waist_radius);
+        let rho = (a2 + self.k * y * y).max(1e-12).sqrt();
+        // Derivatives in y, scaled by dy / dv = height
+        let drho = self.k * y / rho;
+        let ddrho = self.k * a2 / (rho * rho * rho);
+        Some(Profile { v: (y + half) / h, rho, drho: h * drho, ddrho: h * h * ddrho, dy: h, ddy: 0.0 })
+    }
+
+    fn extent (&self) -> (f32, f32, f32) {
+        let half = 0.5 * self.height;
+        (self.waist_radius.max(self.end_radius), -half, half)
+    }
+
+    fn caps (&self) -> &[Cap] {
+        &self.caps
+    }
+
+    fn phi_max (&self) -> f32 {
+        self.phi_max
+    }
+
+    fn mat (&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+}
+
+// Torus around the y axis: a tube of `minor_radius` whose center circles the origin at `major_radius`. v goes once
+// around the tube, starting on its outer equator and heading up
+pub struct Torus {
+    major_radius: f32,
+    minor_radius: f32,
+    phi_max: f32,
+    mat: Arc<dyn Material>,
+}
+
+impl Torus {
+    pub fn new (major_radius: f32, minor_radius: f32, mat: Arc<dyn Material>) -> Self {
+        Torus { major_radius, minor_radius, phi_max: 2.0 * PI, mat }
+    }
+
+    pub fn with_sweep (mut self, degrees: f32) -> Self {
+        self.phi_max = sweep(degrees);
+        self
+    }
+}
+
+impl Revolution for Torus {
+    // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + z^2) is quartic in t. It is solved along a unit direction from the point
+    // of the ray nearest the center, which keeps the coefficients small
+    fn intersect (&self, r: &Ray, roots: &mut [f32; 4]) -> usize {
+        let (o, d) = ray64(r);
+        let length = (d[0] * d[0] + d[1] * d[1] + d[2] * d[2]).sqrt();
+        if length == 0.0 {
+            return 0;
+        }
+        let d = [d[0] / length, d[1] / length, d[2] / length];
+        let t0 = -(o[0] * d[0] + o[1] * d[1] + o[2] * d[2]);
+        let o = [o[0] + t0 * d[0], o[1] + t0 * d[1], o[2] + t0 * d[2]];
+
+        let (major, minor) = (self.major_radius as f64, self.minor_radius as f64);
+        let four_r2 = 4.0 * major * major;
+        let f = o[0] * d[0] + o[1] * d[1] + o[2] * d[2];
+        let oo = o[0] * o[0] + o[1] * o[1] + o[2] * o[2];
+        let g = oo + major * major - minor * minor;
+        let mut solutions = solve_quartic(
+            4.0 * f,
+            4.0 * f * f + 2.0 * g - four_r2 * (1.0 - d[1] * d[1]),
+            4.0 * f * g - four_r2 * (2.0 * f - 2.0 * o[1] * d[1]),
+            g * g - four_r2 * (oo - o[1] * o[1]),
+        );
+        solutions.sort_by(|a, b| a.total_cmp(b));
+        for (root, s) in roots.iter_mut().zip(&solutions) {
+            *root = ((s + t0) / length) as f32;
+        }
+        solutions.len().min(4)
+    }
+
+    fn profile (&self, p: &Point3) -> Option<Profile> {
+        let (major, minor) = (self.major_radius, self.minor_radius);
+        let from_axis = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let theta = p.y().atan2(from_axis - major);
+        let theta = if theta < 0.0 { theta + 2.0 * PI } else { theta };
+        let (sin_theta, cos_theta) = theta.sin_cos();
+        let turn = 2.0 * PI;
+        Some(Profile {
+            v: theta / turn,
+            rho: major + minor * cos_theta,
+            drho: -turn * minor * sin_theta,
+            ddrho: -turn * turn * minor * cos_theta,
+            dy: turn * minor * cos_theta,
+            ddy: -turn * turn * minor * sin_theta,
+        })
+    }
+
+    fn extent (&self) -> (f32, f32, f32) {
+        (self.major_radius + self.minor_radius, -self.minor_radius, self.minor_radius)
+    }
+
+    fn caps (&self) -> &[Cap] {
+        &[]
+    }
+
+    fn phi_max (&self) -> f32 {
+        self.phi_max
+    }
+
+    fn mat (&self) -> &Arc<dyn Material> {
+        &self.mat
+    }
+}
+
+impl Hittable for Cylinder {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        hit_revolution(self, r, ray_t, rec)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        revolution_box(self)
+    }
+}
+
+impl Hittable for Cone {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        hit_revolution(self, r, ray_t, rec)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        revolution_box(self)
+    }
+}
+
+impl Hittable for Paraboloid {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        hit_revolution(self, r, ray_t, rec)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        revolution_box(self)
+    }
+}
+
+impl Hittable for Hyperboloid {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        hit_revolution(self, r, ray_t, rec)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        revolution_box(self)
+    }
+}
+
+impl Hittable for Torus {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        hit_revolution(self, r, ray_t, rec)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        revolution_box(self)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::constants::INFINITY;
+
+    fn gray () -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn shoot (shape: &dyn Hittable, origin: Point3, direction: Vec3) -> Option<HitRecord> {
+        shape.hit(Ray::new(origin, direction), Interval::new(0.0001, INFINITY), &mut HitRecord::default())
+    }
+
+    fn assert_close (a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-3, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_cylinder_side_and_caps(){
+        let open = Cylinder::new(1.0, 2.0, gray());
+        let hit = shoot(&open, Point3::new(5.0, 0.5, 0.0), Vec3::new(-1.0, 0.0, 0.0)).unwrap();
+        assert!((hit.t() - 4.0).abs() < 1e-4);
+        assert_close(hit.normal(), Vec3::new(1.0, 0.0, 0.0));
+        assert!(hit.u().abs() < 1e-4 && (hit.v() - 0.25).abs() < 1e-4);
+
+        // Down the axis an open tube lets the ray through, a capped one stops it at the top
+        assert!(shoot(&open, Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).is_none());
+        let capped = Cylinder::new(1.0, 2.0, gray()).with_caps();
+        let hit = shoot(&capped, Point3::new(0.5, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!((hit.t() - 3.0).abs() < 1e-4);
+        assert_eq!(hit.primitive_id(), 2);
+        assert_close(hit.normal(), Vec3::new(0.0, 1.0, 0.0));
+        let hit = shoot(&capped, Point3::new(0.5, -5.0, 0.0), Vec3::new(0.0, 1.0, 0.0)).unwrap();
+        assert_eq!(hit.primitive_id(), 1);
+
+        // From inside, the wall is seen from the back
+        let hit = shoot(&capped, Point3::new(0.0, 1.0, 0.0), Vec3::new(0.0, 0.0, 1.0)).unwrap();
+        assert!(!hit.front_face());
+        assert_eq!(hit.primitive_id(), 0);
+    }
+
+    // Checks the outward normal against the implicit function's gradient where two rays hit
+    fn assert_normals_follow (shape: &dyn Hittable, gradient: fn(Point3) -> Vec3) {
+        for (origin, direction) in [
+            (Point3::new(3.0, 0.6, 0.4), Vec3::new(-1.0, 0.1, 0.0)),
+            (Point3::new(-0.3, 0.9, -3.0), Vec3::new(0.1, -0.05, 1.0)),
+        ] {
+            let hit = shoot(shape, origin, direction).unwrap();
+            let outward = if hit.front_face() { hit.normal() } else { -hit.normal() };
+            assert_close(outward, gradient(hit.p()).unit_vector());
+        }
+    }
+
+    #[test]
+    fn test_normals_follow_the_implicit_gradient(){
+        // Half the gradients of x^2 + z^2 - k (h - y)^2, x^2 + z^2 - k y and x^2 + z^2 - k y^2 - a^2
+        assert_normals_follow(&Cone::new(1.0, 2.0, gray()), |p| Vec3::new(p.x(), 0.25 * (2.0 - p.y()), p.z()));
+        assert_normals_follow(&Paraboloid::new(1.0, 2.0, gray()), |p| Vec3::new(p.x(), -0.25, p.z()));
+        assert_normals_follow(&Hyperboloid::new(0.5, 1.0, 2.0, gray()), |p| Vec3::new(p.x(), -0.75 * p.y(), p.z()));
+    }
+
+    #[test]
+    fn test_partials_are_tangent_and_consistent(){
+        let shapes: [Box<dyn Hittable>; 5] = [
+            Box::new(Cylinder::new(0.8, 1.5, gray())),
+            Box::new(Cone::new(1.0, 2.0, gray())),
+            Box::new(Paraboloid::new(1.0, 2.0, gray())),
+            Box::new(Hyperboloid::new(0.5, 1.0, 2.0, gray())),
+            Box::new(Torus::new(1.0, 0.3, gray())),
+        ];
+        for shape in &shapes {
+            let mut hits = 0;
+            for _ in 0..200 {
+                let origin = 4.0 * Vec3::random_unit_vector();
+                let target = Point3::new(0.0, 0.5, 0.0) + 0.8 * Vec3::random_unit_vector();
+                let Some(hit) = shoot(shape.as_ref(), origin, target - origin) else { continue };
+                hits += 1;
+                let n = hit.normal();
+                assert!((n.length() - 1.0).abs() < 1e-4);
+                assert!(hit.tangent().dot(&n).abs() < 1e-3);
+                assert!(hit.bitangent().dot(&n).abs() < 1e-3);
+                assert!((0.0..=1.0).contains(&hit.u()) && (0.0..=1.0).contains(&hit.v()));
+                assert!(shape.bounding_box().padded(1e-3).contains(&hit.p()));
+            }
+            assert!(hits > 20);
+        }
+    }
+
+    #[test]
+    fn test_torus_hits(){
+        let torus = Torus::new(2.0, 0.5, gray());
+
+        // Through the tube from outside, then straight down the hole
+        let hit = shoot(&torus, Point3::new(5.0, 0.0, 0.0), Vec3::new(-2.0, 0.0, 0.0)).unwrap();
+        assert!((hit.t() - 1.25).abs() < 1e-4);
+        assert_close(hit.normal(), Vec3::new(1.0, 0.0, 0.0));
+        assert!(hit.v().abs() < 1e-4 || (hit.v() - 1.0).abs() < 1e-4);
+        assert!(shoot(&torus, Point3::new(0.0, 5.0, 0.0), Vec3::new(0.0, -1.0, 0.0)).is_none());
+
+        // From above onto the top of the tube, a quarter of the way round it
+        let hit = shoot(&torus, Point3::new(0.0, 5.0, -2.0), Vec3::new(0.0, -1.0, 0.0)).unwrap();
+        assert!((hit.p().y() - 0.5).abs() < 1e-4);
+        assert!((hit.v() - 0.25).abs() < 1e-4);
+        assert!((hit.u() - 0.25).abs() < 1e-4);
+
+        // Along a line through the hole that clips the tube on both sides, all four roots matter
+        let hit = shoot(&torus, Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert!((hit.t() - 2.5).abs() < 1e-4);
+        let hit = shoot(&torus, Point3::new(0.0, 0.0, 1.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert!((hit.t() - 2.5).abs() < 1e-4);
+        assert!(hit.front_face());
+    }
+
+    #[test]
+    fn test_sweep_cuts_away_part_of_the_turn(){
+        // Half a cylinder keeps the side facing -z, where phi runs from 0 to pi
+        let half = Cylinder::new(1.0, 2.0, gray()).with_sweep(180.0).with_caps();
+        let hit = shoot(&half, Point3::new(0.0, 1.0, -5.0), Vec3::new(0.0, 0.0, 1.0)).unwrap();
+        assert!((hit.p().z() + 1.0).abs() < 1e-4);
+        assert!((hit.u() - 0.5).abs() < 1e-4);
+        // Coming from +z the ray passes the cut and hits the inside of the far wall
+        let hit = shoot(&half, Point3::new(0.0, 1.0, 5.0), Vec3::new(0.0, 0.0, -1.0)).unwrap();
+        assert!((hit.p().z() + 1.0).abs() < 1e-4);
+        assert!(!hit.front_face());
+        // Caps are cut the same way
+        assert!(shoot(&half, Point3::new(0.0, 5.0, 0.5), Vec3::new(0.0, -1.0, 0.0)).is_none());
+        assert_eq!(shoot(&half, Point3::new(0.0, 5.0, -0.5), Vec3::new(0.0, -1.0, 0.0)).unwrap().primitive_id(), 2);
+
+        let ring = Torus::new(2.0, 0.5, gray()).with_sweep(90.0);
+        assert!(shoot(&ring, Point3::new(5.0, 0.0, 0.1), Vec3::new(-1.0, 0.0, 0.0)).is_none());
+        assert!(shoot(&ring, Point3::new(5.0, 0.0, -0.1), Vec3::new(-1.0, 0.0, 0.0)).is_some());
+    }
+
+    #[test]
+    fn test_solve_quartic(){
+        // (x - 1)(x + 2)(x - 3)(x + 0.5) = x^4 - 1.5 x^3 - 6 x^2 + 3.5 x + 3
+        let mut roots = solve_quartic(-1.5, -6.0, 3.5, 3.0);
+        roots.sort_by(|a, b| a.total_cmp(b));
+        let expected = [-2.0, -0.5, 1.0, 3.0];
+        assert_eq!(roots.len(), 4);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < 1e-9);
+        }
+        // x^4 + 1 has no real roots
+        assert!(solve_quartic(0.0, 0.0, 0.0, 1.0).is_empty());
+    }
+}
@@ -1,10 +1,25 @@
 use crate::vec3::Vec3;
 use crate::vec3::Point3;
 
+// Two offset rays through the neighbouring pixels in x and y. Where they land relative to the main ray tells texture
+// lookups how large an area of the surface one pixel covers
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct RayDifferential {
+    pub rx_origin: Point3,
+    pub rx_direction: Vec3,
+    pub ry_origin: Point3,
+    pub ry_direction: Vec3,
+}
+
 #[derive(Copy, Clone, Debug, PartialEq)]
 pub struct Ray {
     origin: Point3, 
     direction: Vec3,
+    // Wavelength in nanometres carried by spectral paths that have collapsed to a single wavelength
+    wavelength: Option<f32>,
+    differentials: Option<RayDifferential>,
+    // Instant within the shutter interval the ray samples, which places moving objects
+    time: f32,
 }
 
 
@@ -14,22 +29,52 @@ impl Default for Ray {
         let origin = Point3::default();
         let direction = Vec3::default();
 
-        Ray { origin, direction }
+        Ray { origin, direction, wavelength: None, differentials: None, time: 0.0 }
     }
 }
 
 
 impl Ray {
     pub fn new (origin: Point3, direction: Vec3) -> Self{
-        Ray { origin, direction }
+        Ray { origin, direction, wavelength: None, differentials: None, time: 0.0 }
+    }
+
+    pub fn with_differentials (self, differentials: RayDifferential) -> Self {
+        Ray { differentials: Some(differentials), ..self }
+    }
+
+    pub fn differentials (&self) -> Option<RayDifferential> {
+        self.differentials
+    }
+
+    // The same ray placed elsewhere, e.g. seen from another coordinate frame. Keeps the wavelength and time but drops
+    // the differentials, which have to be moved along with it
+    pub fn with_origin_and_direction (self, origin: Point3, direction: Vec3) -> Self {
+        Ray { origin, direction, differentials: None, ..self }
+    }
+
+    pub fn with_wavelength (self, wavelength: f32) -> Self {
+        Ray { wavelength: Some(wavelength), ..self }
+    }
+
+    pub fn with_time (self, time: f32) -> Self {
+        Ray { time, ..self }
+    }
+
+    pub fn time (&self) -> f32 {
+        self.time
     }
 
     pub fn origin (&self) -> Point3 {
-        return self.origin
+        self.origin
     }
 
     pub fn direction (&self) -> Vec3 {
-        return self.direction
+        self.direction
+    }
+
+    pub fn wavelength (&self) -> Option<f32> {
+        self.wavelength
     }
 
     pub fn at (&self, t: f32) -> Point3 {
@@ -86,4 +131,19 @@ mod tests {
 
         assert_eq!(v.at(2.0), ans);
     }
+
+    #[test]
+    fn test_ray_wavelength(){
+        let v = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(v.wavelength(), None);
+        assert_eq!(v.with_wavelength(550.0).wavelength(), Some(550.0));
+    }
+
+    #[test]
+    fn test_ray_time(){
+        let v = Ray::new(Point3::new(0.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0));
+        assert_eq!(v.time(), 0.0);
+        let moved = v.with_time(0.25).with_origin_and_direction(Point3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert_eq!(moved.time(), 0.25);
+    }
 }
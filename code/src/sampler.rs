@@ -0,0 +1,41 @@
+use crate::constants::random_generator;
+
+// A sampler hands out the random numbers an integrator consumes along a path
+pub trait Sampler {
+    fn next_1d (&mut self) -> f32;
+
+    fn next_2d (&mut self) -> (f32, f32) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+// Independent uniform samples from the thread-local generator
+#[derive(Default)]
+pub struct RandomSampler;
+
+impl RandomSampler {
+    pub fn new () -> Self {
+        RandomSampler
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn next_1d (&mut self) -> f32 {
+        random_generator()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_random_sampler_range(){
+        let mut sampler = RandomSampler::new();
+        for _ in 0..100 {
+            let (a, b) = sampler.next_2d();
+            assert!((0.0..1.0).contains(&a));
+            assert!((0.0..1.0).contains(&b));
+        }
+    }
+}
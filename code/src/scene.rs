@@ -0,0 +1,81 @@
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::interval::{Interval};
+use crate::hittable::{HitRecord, HittableList, Hittable};
+use crate::colorspace::ColorSpace;
+use crate::medium::AtmosphericFog;
+use std::sync::Arc;
+
+// Everything an integrator needs to know about the world: the geometry, the objects worth sampling directly, the sky
+// and the air in between. Material and light colors are taken to be in `color_space`, the working space the whole
+// render is carried out in
+#[derive(Default)]
+pub struct Scene {
+    pub world: HittableList,
+    pub lights: HittableList,
+    pub color_space: ColorSpace,
+    // Scene-wide fog every ray travels through. None means clear air
+    pub fog: Option<AtmosphericFog>,
+}
+
+impl Scene {
+    pub fn new (world: HittableList, lights: HittableList) -> Self {
+        Scene { world, lights, color_space: ColorSpace::default(), fog: None }
+    }
+
+    // The first thing `r` interacts with: a surface, or the fog in front of it
+    pub fn hit (&self, r: Ray, ray_t: Interval) -> Option<HitRecord> {
+        let mut rec: HitRecord = HitRecord::default();
+        let surface = self.world.hit(r, Interval::new(ray_t.min(), ray_t.max()), &mut rec);
+        match &self.fog {
+            Some(fog) => {
+                let t_max = surface.as_ref().map_or(ray_t.max(), |hit| hit.t());
+                fog.hit(r, Interval::new(ray_t.min(), t_max)).or(surface)
+            }
+            None => surface,
+        }
+    }
+
+    // Position of a world object (by its index in `world`) in `lights`, if it was registered as a light
+    pub fn light_index (&self, object_id: usize) -> Option<usize> {
+        let object = self.world.objects().get(object_id)?;
+        self.lights.objects().iter().position(|light| std::ptr::addr_eq(Arc::as_ptr(light), Arc::as_ptr(object)))
+    }
+
+    // Radiance arriving along rays that escape the scene. The sky gradient is defined in linear sRGB
+    pub fn background (&self, r: &Ray) -> Color {
+        let t: f32 = 0.5 * (r.direction().unit_vector().y() + 1.0);
+        let sky = Color::new(
+            (1.0 - t) * 1.0 + t * 0.5,
+            (1.0 - t) * 1.0 + t * 0.7,
+            (1.0 - t) * 1.0 + t * 1.0,
+        );
+        ColorSpace::LinearSrgb.convert(sky, self.color_space)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::vec3::Point3;
+    use crate::hittable::Sphere;
+    use crate::material::DiffuseLight;
+
+    #[test]
+    fn test_scene_light_index(){
+        let light: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new(0.0, 2.0, 0.0), 0.5, Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)))));
+        let other: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::new(0.0, 0.0, 0.0), 0.5, Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)))));
+
+        let mut world = HittableList::new();
+        world.add(other);
+        world.add(light.clone());
+        let mut lights = HittableList::new();
+        lights.add(light);
+
+        let scene = Scene::new(world, lights);
+        assert_eq!(scene.light_index(0), None);
+        assert_eq!(scene.light_index(1), Some(0));
+        assert_eq!(scene.light_index(2), None);
+    }
+}
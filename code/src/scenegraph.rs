@@ -0,0 +1,228 @@
+use crate::hittable::{Hittable, HittableList, Instance};
+use crate::transform::{Transform, AnimatedTransform, TransformChain};
+use std::sync::Arc;
+
+// A node in a transform hierarchy, as found in imported scenes and animation rigs. Each node's transform is relative
+// to its parent, so moving a node carries all of its children with it. Integrators only see flat lists, so the
+// hierarchy is flattened into instances placed by the product of the transforms from the root down. Nodes can also
+// move while the shutter is open; objects under a moving node become instances that multiply the whole chain out at
+// each ray's time, so they follow every joint's motion at once
+pub struct SceneNode {
+    name: String,
+    transform: Transform,
+    // Replaces `transform` when set
+    motion: Option<AnimatedTransform>,
+    // Each object, and whether it is also registered as a light
+    objects: Vec<(Arc<dyn Hittable>, bool)>,
+    children: Vec<SceneNode>,
+}
+
+impl SceneNode {
+    pub fn new (name: &str, transform: Transform) -> Self {
+        SceneNode { name: name.to_string(), transform, motion: None, objects: Vec::new(), children: Vec::new() }
+    }
+
+    // A node that moves relative to its parent while the shutter is open
+    pub fn animated (name: &str, motion: AnimatedTransform) -> Self {
+        SceneNode { motion: Some(motion), ..SceneNode::new(name, motion.at(0.0)) }
+    }
+
+    pub fn name (&self) -> &str {
+        &self.name
+    }
+
+    // Where the node is when the shutter opens
+    pub fn transform (&self) -> Transform {
+        match &self.motion {
+            Some(motion) => motion.at(0.0),
+            None => self.transform,
+        }
+    }
+
+    pub fn motion (&self) -> Option<AnimatedTransform> {
+        self.motion
+    }
+
+    // Poses the node, e.g. for the current frame of an animation, and stops it moving
+    pub fn set_transform (&mut self, transform: Transform) {
+        self.transform = transform;
+        self.motion = None;
+    }
+
+    pub fn set_motion (&mut self, motion: AnimatedTransform) {
+        self.motion = Some(motion);
+    }
+
+    pub fn children (&self) -> &[SceneNode] {
+        &self.children
+    }
+
+    pub fn with_object (mut self, object: Arc<dyn Hittable>) -> Self {
+        self.objects.push((object, false));
+        self
+    }
+
+    // An object that is also sampled directly as a light once flattened
+    pub fn with_light (mut self, object: Arc<dyn Hittable>) -> Self {
+        self.objects.push((object, true));
+        self
+    }
+
+    pub fn with_child (mut self, child: SceneNode) -> Self {
+        self.children.push(child);
+        self
+    }
+
+    // The first node called `name` in depth first order, this one included
+    pub fn find_mut (&mut self, name: &str) -> Option<&mut SceneNode> {
+        if self.name == name {
+            return Some(self);
+        }
+        self.children.iter_mut().find_map(|child| child.find_mut(name))
+    }
+
+    // Appends every object in the hierarchy to `world`, and the lights to `lights` as well. Lights go into both
+    // lists as the same instance, which is how the scene recognizes them
+    pub fn flatten_into (&self, world: &mut HittableList, lights: &mut HittableList) {
+        self.flatten_under(&TransformChain::new(), world, lights);
+    }
+
+    // `parent` takes objects from this node's parent to world space
+    fn flatten_under (&self, parent: &TransformChain, world: &mut HittableList, lights: &mut HittableList) {
+        let chain = match &self.motion {
+            Some(motion) => parent.clone().with_motion(*motion),
+            None => parent.clone().with_transform(self.transform),
+        };
+        for (object, is_light) in &self.objects {
+            let to_world = chain.at(0.0);
+            let placed: Arc<dyn Hittable> = if chain.is_animated() {
+                Arc::new(Instance::chained(object.clone(), chain.clone()))
+            } else if to_world == Transform::identity() {
+                object.clone()
+            } else {
+                Arc::new(Instance::new(object.clone(), to_world))
+            };
+            world.add(placed.clone());
+            if *is_light {
+                lights.add(placed);
+            }
+        }
+        for child in &self.children {
+            child.flatten_under(&chain, world, lights);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::{Vec3, Point3};
+    use crate::color::Color;
+    use crate::ray::Ray;
+    use crate::interval::Interval;
+    use crate::hittable::{HitRecord, Sphere};
+    use crate::material::{Lambertian, DiffuseLight};
+    use crate::transform::{Quaternion, Pose};
+    use crate::scene::Scene;
+    use crate::constants::{INFINITY, PI};
+
+    fn ball (radius: f32) -> Arc<dyn Hittable> {
+        Arc::new(Sphere::new(Point3::default(), radius, Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))))
+    }
+
+    // Where a ray straight down the y axis towards -y, offset to (x, z), first meets the world
+    fn drop_onto (world: &HittableList, x: f32, z: f32) -> Option<Point3> {
+        drop_onto_at(world, x, z, 0.0)
+    }
+
+    // The same, for a ray at `time` within the shutter interval
+    fn drop_onto_at (world: &HittableList, x: f32, z: f32, time: f32) -> Option<Point3> {
+        let r = Ray::new(Point3::new(x, 10.0, z), Vec3::new(0.0, -1.0, 0.0)).with_time(time);
+        world.hit(r, Interval::new(0.0001, INFINITY), &mut HitRecord::default()).map(|hit| hit.p())
+    }
+
+    // A shoulder at the origin with an arm reaching two units along x, and a hand at the end of the arm
+    fn rig () -> SceneNode {
+        let hand = SceneNode::new("hand", Transform::translate(Vec3::new(2.0, 0.0, 0.0))).with_object(ball(0.25));
+        let arm = SceneNode::new("arm", Transform::identity()).with_child(hand);
+        SceneNode::new("shoulder", Transform::translate(Vec3::new(0.0, 0.0, -3.0))).with_object(ball(0.5)).with_child(arm)
+    }
+
+    #[test]
+    fn test_scene_graph_composes_parent_transforms(){
+        let mut root = rig();
+        let mut world = HittableList::new();
+        root.flatten_into(&mut world, &mut HittableList::new());
+        assert_eq!(world.objects().len(), 2);
+        assert!((drop_onto(&world, 2.0, -3.0).unwrap() - Point3::new(2.0, 0.25, -3.0)).length() < 1e-4);
+
+        // Raising the arm a quarter turn about z swings the hand up above the shoulder
+        root.find_mut("arm").unwrap().set_transform(Transform::from_quaternion(Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0)));
+        let mut world = HittableList::new();
+        root.flatten_into(&mut world, &mut HittableList::new());
+        assert!(drop_onto(&world, 2.0, -3.0).is_none());
+        assert!((drop_onto(&world, 0.0, -3.0).unwrap() - Point3::new(0.0, 2.25, -3.0)).length() < 1e-4);
+        assert!(root.find_mut("elbow").is_none());
+    }
+
+    #[test]
+    fn test_scene_graph_moving_parent_carries_children(){
+        // The shoulder slides two units along z while the shutter is open, and the hand stays on the end of the arm
+        let mut root = rig();
+        let start = Pose { translation: Vec3::new(0.0, 0.0, -3.0), ..Pose::default() };
+        root.set_motion(AnimatedTransform::new(start, Pose { translation: Vec3::new(0.0, 0.0, -5.0), ..start }));
+        let mut world = HittableList::new();
+        root.flatten_into(&mut world, &mut HittableList::new());
+        assert!((drop_onto_at(&world, 2.0, -3.0, 0.0).unwrap() - Point3::new(2.0, 0.25, -3.0)).length() < 1e-4);
+        assert!(drop_onto_at(&world, 2.0, -3.0, 1.0).is_none());
+        assert!((drop_onto_at(&world, 2.0, -4.0, 0.5).unwrap() - Point3::new(2.0, 0.25, -4.0)).length() < 1e-4);
+        assert!((drop_onto_at(&world, 2.0, -5.0, 1.0).unwrap() - Point3::new(2.0, 0.25, -5.0)).length() < 1e-4);
+
+        // Posing the shoulder again stops it moving
+        root.set_transform(Transform::translate(Vec3::new(0.0, 0.0, -3.0)));
+        assert!(root.motion().is_none());
+    }
+
+    #[test]
+    fn test_scene_graph_turning_joint_swings_children_along_an_arc(){
+        // Raising the arm a quarter turn while the shutter is open swings the hand, whose top is 0.25 above its
+        // center, from the end of the arm to above the shoulder through the points in between on the circle
+        let mut root = rig();
+        let raised = Pose { rotation: Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0), ..Pose::default() };
+        root.find_mut("arm").unwrap().set_motion(AnimatedTransform::new(Pose::default(), raised));
+        let mut world = HittableList::new();
+        root.flatten_into(&mut world, &mut HittableList::new());
+
+        let half = 2.0_f32.sqrt();
+        let top = Vec3::new(0.0, 0.25, 0.0);
+        assert!((drop_onto_at(&world, 2.0, -3.0, 0.0).unwrap() - (Point3::new(2.0, 0.0, -3.0) + top)).length() < 1e-4);
+        assert!((drop_onto_at(&world, half, -3.0, 0.5).unwrap() - (Point3::new(half, half, -3.0) + top)).length() < 1e-4);
+        assert!((drop_onto_at(&world, 0.0, -3.0, 1.0).unwrap() - (Point3::new(0.0, 2.0, -3.0) + top)).length() < 1e-4);
+
+        // The hand's bounds hold it all the way along the arc
+        let bounds = world.objects()[1].bounding_box();
+        for i in 0..=20 {
+            let angle = i as f32 / 20.0 * PI / 2.0;
+            let center = Point3::new(2.0 * angle.cos(), 2.0 * angle.sin(), -3.0);
+            assert!(bounds.contains(&(center + top)), "hand outside the bounds at {}", center);
+        }
+    }
+
+    #[test]
+    fn test_scene_graph_registers_lights(){
+        let lamp: Arc<dyn Hittable> = Arc::new(Sphere::new(Point3::default(), 0.5, Arc::new(DiffuseLight::new(Color::new(4.0, 4.0, 4.0)))));
+        let root = SceneNode::new("room", Transform::translate(Vec3::new(0.0, 1.0, 0.0)))
+            .with_object(ball(1.0))
+            .with_child(SceneNode::new("lamp", Transform::translate(Vec3::new(0.0, 3.0, 0.0))).with_light(lamp));
+
+        let mut world = HittableList::new();
+        let mut lights = HittableList::new();
+        root.flatten_into(&mut world, &mut lights);
+        assert_eq!(lights.objects().len(), 1);
+
+        let scene = Scene::new(world, lights);
+        assert_eq!(scene.light_index(0), None);
+        assert_eq!(scene.light_index(1), Some(0));
+        assert!((drop_onto(&scene.world, 0.0, 0.0).unwrap() - Point3::new(0.0, 4.5, 0.0)).length() < 1e-4);
+    }
+}
@@ -0,0 +1,378 @@
+use crate::vec3::{Vec3, Point3};
+use crate::ray::Ray;
+use crate::interval::Interval;
+use crate::hittable::{HitRecord, Hittable};
+use crate::material::Material;
+use crate::onb::Onb;
+use crate::aabb::Aabb;
+use crate::transform::Quaternion;
+use crate::constants::{INFINITY, PI, random_generator, random_generator_range};
+use std::sync::Arc;
+
+// Flat shapes have no thickness, so their boxes are grown by this much to keep them from collapsing
+const FLAT_PADDING: f32 = 1e-4;
+
+// Where `r` crosses the plane of points x with normal . x = d, if it does within `ray_t`
+fn hit_plane (normal: &Vec3, d: f32, r: &Ray, ray_t: &Interval) -> Option<(f32, Point3)> {
+    let denominator = normal.dot(&r.direction());
+    if denominator.abs() < 1e-8 {
+        return None;
+    }
+    let t = (d - normal.dot(&r.origin())) / denominator;
+    if !ray_t.surrounds(t) {
+        return None;
+    }
+    Some((t, r.at(t)))
+}
+
+// Solid angle density of sampling a point uniformly over a flat shape of `area` with unit `normal`, for the point
+// at `origin_to_hit` from where it is seen
+fn flat_pdf (origin_to_hit: Vec3, normal: &Vec3, area: f32) -> f32 {
+    let distance_squared = origin_to_hit.length_squared();
+    let cosine = (origin_to_hit.dot(normal) / distance_squared.sqrt()).abs();
+    if cosine < 1e-8 {
+        return 0.0;
+    }
+    distance_squared / (cosine * area)
+}
+
+// An infinite plane through `point`, for floors, walls and water surfaces. u and v are distances along a frame in the
+// plane, so textures repeat once per scene unit
+pub struct Plane {
+    point: Point3,
+    frame: Onb,
+    d: f32,
+    mat: Arc<dyn Material>,
+}
+
+impl Plane {
+    pub fn new (point: Point3, normal: Vec3, mat: Arc<dyn Material>) -> Self {
+        let frame = Onb::new(&normal);
+        Plane { point, d: frame.w().dot(&point), frame, mat }
+    }
+}
+
+impl Hittable for Plane {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        let (t, p) = hit_plane(&self.frame.w(), self.d, &r, &ray_t)?;
+        let local = p - self.point;
+
+        let mut hit_record = HitRecord::new(p, t, self.mat.clone());
+        hit_record.set_face_normal(&r, self.frame.w());
+        hit_record.set_uv(local.dot(&self.frame.u()), local.dot(&self.frame.v()));
+        hit_record.set_tangents(self.frame.u(), self.frame.v());
+        hit_record.set_partials(self.frame.u(), self.frame.v(), Vec3::default(), Vec3::default());
+        *rec = hit_record.clone();
+        Some(hit_record)
+    }
+}
+
+// A parallelogram with corner `q` and edges `u` and `v`, which are also the directions of increasing u and v. Its
+// normal is u x v. Quads can be sampled as area lights
+pub struct Quad {
+    q: Point3,
+    u: Vec3,
+    v: Vec3,
+    // u x v over its squared length, which turns a point's offset from q into its uv coordinates
+    w: Vec3,
+    normal: Vec3,
+    d: f32,
+    area: f32,
+    mat: Arc<dyn Material>,
+}
+
+impl Quad {
+    pub fn new (q: Point3, u: Vec3, v: Vec3, mat: Arc<dyn Material>) -> Self {
+        let n = u.cross(&v);
+        let normal = n.unit_vector();
+        Quad { q, u, v, w: n / n.length_squared(), normal, d: normal.dot(&q), area: n.length(), mat }
+    }
+
+    pub fn area (&self) -> f32 {
+        self.area
+    }
+}
+
+impl Hittable for Quad {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        let (t, p) = hit_plane(&self.normal, self.d, &r, &ray_t)?;
+        let planar = p - self.q;
+        let alpha = self.w.dot(&planar.cross(&self.v));
+        let beta = self.w.dot(&self.u.cross(&planar));
+        if !(0.0..=1.0).contains(&alpha) || !(0.0..=1.0).contains(&beta) {
+            return None;
+        }
+
+        let mut hit_record = HitRecord::new(p, t, self.mat.clone());
+        hit_record.set_face_normal(&r, self.normal);
+        hit_record.set_uv(alpha, beta);
+        hit_record.set_tangents(self.u.unit_vector(), self.v.unit_vector());
+        hit_record.set_partials(self.u, self.v, Vec3::default(), Vec3::default());
+        *rec = hit_record.clone();
+        Some(hit_record)
+    }
+
+    fn pdf_value (&self, origin: Point3, direction: Vec3) -> f32 {
+        let mut rec = HitRecord::default();
+        match self.hit(Ray::new(origin, direction), Interval::new(0.001, INFINITY), &mut rec) {
+            Some(hit) => flat_pdf(hit.p() - origin, &self.normal, self.area),
+            None => 0.0,
+        }
+    }
+
+    fn random (&self, origin: Point3) -> Vec3 {
+        self.q + random_generator() * self.u + random_generator() * self.v - origin
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        let diagonals = Aabb::new(self.q, self.q + self.u + self.v).union(&Aabb::new(self.q + self.u, self.q + self.v));
+        diagonals.padded(FLAT_PADDING)
+    }
+}
+
+// A flat disk, such as a round area light or a table top. u goes once around the center and v from the center out
+// to the rim
+pub struct Disk {
+    center: Point3,
+    radius: f32,
+    frame: Onb,
+    d: f32,
+    mat: Arc<dyn Material>,
+}
+
+impl Disk {
+    pub fn new (center: Point3, normal: Vec3, radius: f32, mat: Arc<dyn Material>) -> Self {
+        let frame = Onb::new(&normal);
+        Disk { center, radius, d: frame.w().dot(&center), frame, mat }
+    }
+}
+
+impl Hittable for Disk {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        let (t, p) = hit_plane(&self.frame.w(), self.d, &r, &ray_t)?;
+        let local = p - self.center;
+        let distance = local.length();
+        if distance > self.radius {
+            return None;
+        }
+
+        let phi = local.dot(&self.frame.v()).atan2(local.dot(&self.frame.u()));
+        let phi = if phi < 0.0 { phi + 2.0 * PI } else { phi };
+        let (sin_phi, cos_phi) = phi.sin_cos();
+        let radial = cos_phi * self.frame.u() + sin_phi * self.frame.v();
+        let around = -sin_phi * self.frame.u() + cos_phi * self.frame.v();
+
+        let mut hit_record = HitRecord::new(p, t, self.mat.clone());
+        hit_record.set_face_normal(&r, self.frame.w());
+        hit_record.set_uv(phi / (2.0 * PI), distance / self.radius);
+        hit_record.set_tangents(around, radial);
+        hit_record.set_partials(2.0 * PI * distance * around, self.radius * radial, Vec3::default(), Vec3::default());
+        *rec = hit_record.clone();
+        Some(hit_record)
+    }
+
+    fn pdf_value (&self, origin: Point3, direction: Vec3) -> f32 {
+        let mut rec = HitRecord::default();
+        match self.hit(Ray::new(origin, direction), Interval::new(0.001, INFINITY), &mut rec) {
+            Some(hit) => flat_pdf(hit.p() - origin, &self.frame.w(), PI * self.radius * self.radius),
+            None => 0.0,
+        }
+    }
+
+    fn random (&self, origin: Point3) -> Vec3 {
+        let r = self.radius * random_generator().sqrt();
+        let (sin_phi, cos_phi) = (2.0 * PI * random_generator()).sin_cos();
+        self.center + r * cos_phi * self.frame.u() + r * sin_phi * self.frame.v() - origin
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        let n = self.frame.w();
+        let extent = |axis: usize| self.radius * (1.0 - n[axis] * n[axis]).max(0.0).sqrt();
+        let extent = Vec3::new(extent(0), extent(1), extent(2));
+        Aabb::new(self.center - extent, self.center + extent).padded(FLAT_PADDING)
+    }
+}
+
+// A box made of six quads with outward normals. The primitive id of a hit tells which face it was on: front, right,
+// back, left, top, bottom as seen along the edges it was built from
+pub struct Cuboid {
+    faces: [Quad; 6],
+}
+
+impl Cuboid {
+    // Axis-aligned box with opposite corners `a` and `b`
+    pub fn new (a: Point3, b: Point3, mat: Arc<dyn Material>) -> Self {
+        let bounds = Aabb::new(a, b);
+        let size = bounds.max() - bounds.min();
+        Cuboid::from_edges(bounds.min(), Vec3::new(size.x(), 0.0, 0.0), Vec3::new(0.0, size.y(), 0.0), Vec3::new(0.0, 0.0, size.z()), mat)
+    }
+
+    // A box of the given half extents around `center`, turned by `rotation`
+    pub fn oriented (center: Point3, half_extents: Vec3, rotation: Quaternion, mat: Arc<dyn Material>) -> Self {
+        let dx = rotation.rotate(&Vec3::new(2.0 * half_extents.x(), 0.0, 0.0));
+        let dy = rotation.rotate(&Vec3::new(0.0, 2.0 * half_extents.y(), 0.0));
+        let dz = rotation.rotate(&Vec3::new(0.0, 0.0, 2.0 * half_extents.z()));
+        Cuboid::from_edges(center - 0.5 * (dx + dy + dz), dx, dy, dz, mat)
+    }
+
+    // The box spanned from `corner` by three right-handed edges
+    fn from_edges (corner: Point3, dx: Vec3, dy: Vec3, dz: Vec3, mat: Arc<dyn Material>) -> Self {
+        let quad = |q: Point3, u: Vec3, v: Vec3| Quad::new(q, u, v, mat.clone());
+        Cuboid {
+            faces: [
+                quad(corner + dz, dx, dy),
+                quad(corner + dx + dz, -dz, dy),
+                quad(corner + dx, -dx, dy),
+                quad(corner, dz, dy),
+                quad(corner + dy + dz, dx, -dz),
+                quad(corner, dx, dz),
+            ],
+        }
+    }
+}
+
+impl Hittable for Cuboid {
+    fn hit (&self, r: Ray, ray_t: Interval, rec: &mut HitRecord) -> Option<HitRecord> {
+        let mut closest: Option<HitRecord> = None;
+        for (index, face) in self.faces.iter().enumerate() {
+            let t_max = closest.as_ref().map_or(ray_t.max(), |hit| hit.t());
+            if let Some(mut hit) = face.hit(r, Interval::new(ray_t.min(), t_max), rec) {
+                hit.set_primitive_id(index);
+                closest = Some(hit);
+            }
+        }
+        let hit_record = closest?;
+        *rec = hit_record.clone();
+        Some(hit_record)
+    }
+
+    // Picks a face uniformly, then a point on it
+    fn pdf_value (&self, origin: Point3, direction: Vec3) -> f32 {
+        self.faces.iter().map(|face| face.pdf_value(origin, direction)).sum::<f32>() / 6.0
+    }
+
+    fn random (&self, origin: Point3) -> Vec3 {
+        let index = (random_generator_range(0.0, 6.0) as usize).min(5);
+        self.faces[index].random(origin)
+    }
+
+    fn bounding_box (&self) -> Aabb {
+        self.faces.iter().fold(Aabb::empty(), |bounds, face| bounds.union(&face.bounding_box()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::color::Color;
+    use crate::material::Lambertian;
+    use crate::hittable::tests::integrate_pdf;
+
+    fn gray () -> Arc<dyn Material> {
+        Arc::new(Lambertian::new(Color::new(0.5, 0.5, 0.5)))
+    }
+
+    fn interval () -> Interval {
+        Interval::new(0.0001, INFINITY)
+    }
+
+    #[test]
+    fn test_plane_hit(){
+        let plane = Plane::new(Point3::new(0.0, -1.0, 0.0), Vec3::new(0.0, 1.0, 0.0), gray());
+        let mut rec = HitRecord::default();
+        let r = Ray::new(Point3::new(3.0, 1.0, -2.0), Vec3::new(1.0, -1.0, 0.0));
+        let hit = plane.hit(r, interval(), &mut rec).unwrap();
+        assert!((hit.t() - 2.0).abs() < 1e-5);
+        assert!((hit.p() - Point3::new(5.0, -1.0, -2.0)).length() < 1e-5);
+        assert!(hit.front_face());
+
+        // From below the same plane is hit on its back, and parallel rays miss it
+        let below = Ray::new(Point3::new(0.0, -3.0, 0.0), Vec3::new(0.0, 1.0, 0.0));
+        assert!(!plane.hit(below, interval(), &mut rec).unwrap().front_face());
+        assert!(plane.hit(Ray::new(Point3::default(), Vec3::new(1.0, 0.0, 0.0)), interval(), &mut rec).is_none());
+        assert!(!plane.bounding_box().is_finite());
+    }
+
+    #[test]
+    fn test_quad_hit_and_uv(){
+        let quad = Quad::new(Point3::new(-1.0, -1.0, -2.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 4.0, 0.0), gray());
+        assert_eq!(quad.area(), 8.0);
+        let mut rec = HitRecord::default();
+
+        let hit = quad.hit(Ray::new(Point3::new(0.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), interval(), &mut rec).unwrap();
+        assert!((hit.t() - 2.0).abs() < 1e-5);
+        assert!((hit.u() - 0.75).abs() < 1e-5 && (hit.v() - 0.25).abs() < 1e-5);
+        assert!((hit.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-5);
+        assert!((hit.tangent() - Vec3::new(1.0, 0.0, 0.0)).length() < 1e-5);
+        assert!(quad.hit(Ray::new(Point3::new(1.5, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), interval(), &mut rec).is_none());
+
+        let bounds = quad.bounding_box();
+        assert!(bounds.contains(&Point3::new(1.0, 3.0, -2.0)) && bounds.is_finite());
+    }
+
+    #[test]
+    fn test_quad_and_disk_pdfs_integrate_to_one(){
+        let origin = Point3::new(0.2, 0.0, 0.5);
+        let quad = Quad::new(Point3::new(-1.0, 1.0, -1.0), Vec3::new(2.0, 0.0, 0.0), Vec3::new(0.0, 0.0, 1.5), gray());
+        let disk = Disk::new(Point3::new(0.0, 1.0, 0.0), Vec3::new(0.3, -1.0, 0.2), 0.8, gray());
+        for object in [&quad as &dyn Hittable, &disk] {
+            let integral = integrate_pdf(object, origin);
+            assert!((integral - 1.0).abs() < 0.03, "integrated to {}", integral);
+            for _ in 0..100 {
+                assert!(object.pdf_value(origin, object.random(origin)) > 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn test_disk_hit_and_uv(){
+        let disk = Disk::new(Point3::new(0.0, 0.0, -3.0), Vec3::new(0.0, 0.0, 1.0), 2.0, gray());
+        let mut rec = HitRecord::default();
+        let hit = disk.hit(Ray::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -1.0)), interval(), &mut rec).unwrap();
+        assert!((hit.t() - 3.0).abs() < 1e-5);
+        assert!((hit.v() - 0.5).abs() < 1e-5);
+        assert!((0.0..1.0).contains(&hit.u()));
+        assert!(hit.tangent().dot(&hit.bitangent()).abs() < 1e-5);
+        assert!(disk.hit(Ray::new(Point3::new(1.5, 1.5, 0.0), Vec3::new(0.0, 0.0, -1.0)), interval(), &mut rec).is_none());
+        assert_eq!(disk.bounding_box().max().z(), -3.0 + FLAT_PADDING);
+    }
+
+    #[test]
+    fn test_cuboid_faces(){
+        let cuboid = Cuboid::new(Point3::new(1.0, 1.0, 1.0), Point3::new(-1.0, -1.0, -3.0), gray());
+        let mut rec = HitRecord::default();
+
+        // Front face from outside, then the back face from inside
+        let hit = cuboid.hit(Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0)), interval(), &mut rec).unwrap();
+        assert!((hit.t() - 4.0).abs() < 1e-5);
+        assert_eq!(hit.primitive_id(), 0);
+        assert!(hit.front_face());
+        let hit = cuboid.hit(Ray::new(Point3::new(0.0, 0.0, -1.0), Vec3::new(0.0, 0.0, -1.0)), interval(), &mut rec).unwrap();
+        assert_eq!(hit.primitive_id(), 2);
+        assert!(!hit.front_face());
+        assert!((hit.normal() - Vec3::new(0.0, 0.0, 1.0)).length() < 1e-5);
+
+        // Every face points outwards
+        for (direction, id) in [(Vec3::new(1.0, 0.0, 0.0), 1), (Vec3::new(-1.0, 0.0, 0.0), 3), (Vec3::new(0.0, 1.0, 0.0), 4), (Vec3::new(0.0, -1.0, 0.0), 5)] {
+            let outside = Point3::new(0.0, 0.0, -1.0) + 5.0 * direction;
+            let hit = cuboid.hit(Ray::new(outside, -direction), interval(), &mut rec).unwrap();
+            assert_eq!(hit.primitive_id(), id);
+            assert!((hit.normal() - direction).length() < 1e-5);
+        }
+
+        let bounds = cuboid.bounding_box();
+        assert!((bounds.min() - Point3::new(-1.0, -1.0, -3.0)).length() < 1e-3);
+        assert!((integrate_pdf(&cuboid, Point3::new(3.0, 2.0, 2.0)) - 1.0).abs() < 0.03);
+    }
+
+    #[test]
+    fn test_oriented_cuboid(){
+        // A unit cube turned 45 degrees about y reaches sqrt(2) / 2 along x
+        let rotation = Quaternion::from_axis_angle(Vec3::new(0.0, 1.0, 0.0), 45.0);
+        let cuboid = Cuboid::oriented(Point3::default(), Vec3::new(0.5, 0.5, 0.5), rotation, gray());
+        let mut rec = HitRecord::default();
+        let hit = cuboid.hit(Ray::new(Point3::new(5.0, 0.0, 0.0), Vec3::new(-1.0, 0.0, 0.0)), interval(), &mut rec).unwrap();
+        assert!((hit.p().x() - 0.5_f32.sqrt()).abs() < 1e-4);
+        assert!((cuboid.bounding_box().max().x() - 0.5_f32.sqrt()).abs() < 1e-3);
+    }
+}
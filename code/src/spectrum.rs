@@ -0,0 +1,298 @@
+use crate::color::Color;
+use crate::colorspace::{ColorSpace, Mat3, mat3_apply, mat3_inverse};
+use std::ops::{Add, Mul, Div};
+use std::sync::OnceLock;
+
+// Visible range the spectral renderer samples, in nanometres
+pub const LAMBDA_MIN: f32 = 360.0;
+pub const LAMBDA_MAX: f32 = 830.0;
+
+// Number of wavelengths carried by each path (one hero plus evenly spaced companions)
+pub const N_SPECTRUM_SAMPLES: usize = 4;
+
+// Wavelength of the Fraunhofer d line, where refractive indices are usually quoted
+pub const LAMBDA_D_LINE: f32 = 587.6;
+
+// Piecewise gaussian used by the analytic CIE fit, with different widths either side of the peak
+fn lobe (lambda: f32, mu: f32, sigma_left: f32, sigma_right: f32) -> f32 {
+    let sigma = if lambda < mu { sigma_left } else { sigma_right };
+    let t = (lambda - mu) / sigma;
+    (-0.5 * t * t).exp()
+}
+
+// CIE 1931 2° colour matching functions, using the multi-lobe fit of Wyman, Sloan and Shirley (2013)
+pub fn cie_xyz (lambda: f32) -> Color {
+    let x = 1.056 * lobe(lambda, 599.8, 37.9, 31.0) + 0.362 * lobe(lambda, 442.0, 16.0, 26.7)
+        - 0.065 * lobe(lambda, 501.1, 20.4, 26.2);
+    let y = 0.821 * lobe(lambda, 568.8, 46.9, 40.5) + 0.286 * lobe(lambda, 530.9, 16.3, 31.1);
+    let z = 1.217 * lobe(lambda, 437.0, 11.8, 36.0) + 0.681 * lobe(lambda, 459.0, 26.0, 13.8);
+    Color::new(x, y, z)
+}
+
+fn smoothstep (edge0: f32, edge1: f32, x: f32) -> f32 {
+    let t = ((x - edge0) / (edge1 - edge0)).clamp(0.0, 1.0);
+    t * t * (3.0 - 2.0 * t)
+}
+
+// Smooth blue, green and red spectra that sum to one at every wavelength, used to upsample RGB
+fn basis (lambda: f32) -> [f32; 3] {
+    let blue_to_green = smoothstep(480.0, 510.0, lambda);
+    let green_to_red = smoothstep(570.0, 600.0, lambda);
+    [green_to_red, blue_to_green - green_to_red, 1.0 - blue_to_green]
+}
+
+// Precomputed integrals shared by every conversion
+struct SpectralTables {
+    // Integral of the y matching function, so a flat spectrum of 1 has luminance 1
+    cie_y_integral: f32,
+    // Linear sRGB of a flat spectrum before white balancing; dividing by it keeps flat spectra neutral
+    white: Color,
+    // Maps linear sRGB to the weights of the basis spectra that reproduce it
+    rgb_to_basis: Mat3,
+}
+
+fn tables () -> &'static SpectralTables {
+    static TABLES: OnceLock<SpectralTables> = OnceLock::new();
+    TABLES.get_or_init(|| {
+        let steps = (LAMBDA_MAX - LAMBDA_MIN) as usize;
+        let mut cie_y_integral = 0.0;
+        let mut white_xyz = Color::new(0.0, 0.0, 0.0);
+        let mut basis_xyz = [Color::new(0.0, 0.0, 0.0); 3];
+        for i in 0..=steps {
+            let lambda = LAMBDA_MIN + i as f32;
+            let cmf = cie_xyz(lambda);
+            cie_y_integral += cmf.g();
+            white_xyz = white_xyz + cmf;
+            for (xyz, weight) in basis_xyz.iter_mut().zip(basis(lambda)) {
+                *xyz = *xyz + weight * cmf;
+            }
+        }
+
+        let white = ColorSpace::LinearSrgb.from_xyz(white_xyz / cie_y_integral);
+        let mut basis_to_rgb = [[0.0; 3]; 3];
+        for (j, xyz) in basis_xyz.iter().enumerate() {
+            let rgb = ColorSpace::LinearSrgb.from_xyz(*xyz / cie_y_integral);
+            basis_to_rgb[0][j] = rgb.r() / white.r();
+            basis_to_rgb[1][j] = rgb.g() / white.g();
+            basis_to_rgb[2][j] = rgb.b() / white.b();
+        }
+
+        SpectralTables { cie_y_integral, white, rgb_to_basis: mat3_inverse(&basis_to_rgb) }
+    })
+}
+
+// Linear sRGB of a spectrum given as a function of wavelength, integrated with `steps` evenly spaced samples and
+// white balanced like SampledSpectrum::to_rgb. Meant for smooth spectra computed once per shading point
+pub fn spectrum_to_rgb (spectrum: impl Fn(f32) -> f32, steps: usize) -> Color {
+    let width = (LAMBDA_MAX - LAMBDA_MIN) / steps as f32;
+    let mut xyz = Color::new(0.0, 0.0, 0.0);
+    for i in 0..steps {
+        let lambda = LAMBDA_MIN + (i as f32 + 0.5) * width;
+        xyz = xyz + cie_xyz(lambda) * spectrum(lambda);
+    }
+    let rgb = ColorSpace::LinearSrgb.from_xyz(xyz * (width / tables().cie_y_integral));
+    let white = tables().white;
+    Color::new(rgb.r() / white.r(), rgb.g() / white.g(), rgb.b() / white.b())
+}
+
+// Values of a spectral quantity at the wavelengths a path carries
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SampledSpectrum {
+    values: [f32; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledSpectrum {
+    pub fn new (value: f32) -> Self {
+        SampledSpectrum { values: [value; N_SPECTRUM_SAMPLES] }
+    }
+
+    pub fn values (&self) -> &[f32; N_SPECTRUM_SAMPLES] {
+        &self.values
+    }
+
+    pub fn max_component (&self) -> f32 {
+        self.values.iter().cloned().fold(0.0, f32::max)
+    }
+
+    // Upsamples a linear sRGB color to a smooth, non-negative spectrum evaluated at `lambda`.
+    // A grey of value v becomes a flat spectrum of v, and colors the basis can reach round trip exactly
+    pub fn from_rgb (c: Color, lambda: &SampledWavelengths) -> Self {
+        let weights = mat3_apply(&tables().rgb_to_basis, c);
+        let mut values = [0.0; N_SPECTRUM_SAMPLES];
+        for (value, l) in values.iter_mut().zip(lambda.lambda.iter()) {
+            let [r, g, b] = basis(*l);
+            *value = (weights.r() * r + weights.g() * g + weights.b() * b).max(0.0);
+        }
+        SampledSpectrum { values }
+    }
+
+    // Monte Carlo estimate of the spectrum's CIE XYZ, normalized so a flat spectrum of 1 has Y = 1
+    pub fn to_xyz (self, lambda: &SampledWavelengths) -> Color {
+        let mut xyz = Color::new(0.0, 0.0, 0.0);
+        for i in 0..N_SPECTRUM_SAMPLES {
+            if lambda.pdf[i] > 0.0 {
+                xyz = xyz + cie_xyz(lambda.lambda[i]) * (self.values[i] / lambda.pdf[i]);
+            }
+        }
+        xyz / (N_SPECTRUM_SAMPLES as f32 * tables().cie_y_integral)
+    }
+
+    // Converts back to linear sRGB, white balanced so flat spectra come out neutral
+    pub fn to_rgb (self, lambda: &SampledWavelengths) -> Color {
+        let rgb = ColorSpace::LinearSrgb.from_xyz(self.to_xyz(lambda));
+        let white = tables().white;
+        Color::new(rgb.r() / white.r(), rgb.g() / white.g(), rgb.b() / white.b())
+    }
+}
+
+impl Add for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn add (self, other: SampledSpectrum) -> Self::Output {
+        let mut values = self.values;
+        for (value, o) in values.iter_mut().zip(other.values) {
+            *value += o;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl Mul for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul (self, other: SampledSpectrum) -> Self::Output {
+        let mut values = self.values;
+        for (value, o) in values.iter_mut().zip(other.values) {
+            *value *= o;
+        }
+        SampledSpectrum { values }
+    }
+}
+
+impl Mul<f32> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn mul (self, t: f32) -> Self::Output {
+        SampledSpectrum { values: self.values.map(|value| value * t) }
+    }
+}
+
+impl Div<f32> for SampledSpectrum {
+    type Output = SampledSpectrum;
+
+    fn div (self, t: f32) -> Self::Output {
+        SampledSpectrum { values: self.values.map(|value| value / t) }
+    }
+}
+
+// The wavelengths a path carries. Hero wavelength sampling picks one uniformly and spaces the rest evenly across the
+// range, so together they cover the spectrum and every wavelength has the same density
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct SampledWavelengths {
+    lambda: [f32; N_SPECTRUM_SAMPLES],
+    pdf: [f32; N_SPECTRUM_SAMPLES],
+}
+
+impl SampledWavelengths {
+    pub fn sample_uniform (u: f32) -> Self {
+        let range = LAMBDA_MAX - LAMBDA_MIN;
+        let delta = range / N_SPECTRUM_SAMPLES as f32;
+
+        let mut lambda = [0.0; N_SPECTRUM_SAMPLES];
+        lambda[0] = LAMBDA_MIN + u * range;
+        for i in 1..N_SPECTRUM_SAMPLES {
+            lambda[i] = lambda[i - 1] + delta;
+            if lambda[i] > LAMBDA_MAX {
+                lambda[i] -= range;
+            }
+        }
+
+        SampledWavelengths { lambda, pdf: [1.0 / range; N_SPECTRUM_SAMPLES] }
+    }
+
+    pub fn hero (&self) -> f32 {
+        self.lambda[0]
+    }
+
+    pub fn lambda (&self) -> &[f32; N_SPECTRUM_SAMPLES] {
+        &self.lambda
+    }
+
+    pub fn secondary_terminated (&self) -> bool {
+        self.pdf[1..].iter().all(|pdf| *pdf == 0.0)
+    }
+
+    // After a wavelength-dependent event (like dispersion) only the hero wavelength's path is valid, so the
+    // others are dropped and the hero is reweighted to stand in for all of them
+    pub fn terminate_secondary (&mut self) {
+        if self.secondary_terminated() {
+            return;
+        }
+        for pdf in self.pdf[1..].iter_mut() {
+            *pdf = 0.0;
+        }
+        self.pdf[0] /= N_SPECTRUM_SAMPLES as f32;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_wavelengths_cover_range(){
+        let lambda = SampledWavelengths::sample_uniform(0.9);
+        for l in lambda.lambda() {
+            assert!((LAMBDA_MIN..=LAMBDA_MAX).contains(l));
+        }
+        assert!((lambda.hero() - (LAMBDA_MIN + 0.9 * (LAMBDA_MAX - LAMBDA_MIN))).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_flat_spectrum_is_white(){
+        // Averaged over many wavelength samples a flat spectrum converts to neutral white
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        let n = 1000;
+        for i in 0..n {
+            let lambda = SampledWavelengths::sample_uniform((i as f32 + 0.5) / n as f32);
+            sum = sum + SampledSpectrum::new(1.0).to_rgb(&lambda);
+        }
+        let average = sum / n as f32;
+        assert!((average - Color::new(1.0, 1.0, 1.0)).length() < 1e-2);
+    }
+
+    #[test]
+    fn test_spectrum_to_rgb(){
+        assert!((spectrum_to_rgb(|_| 1.0, 64) - Color::new(1.0, 1.0, 1.0)).length() < 1e-2);
+        // Long wavelengths only come out red
+        let red = spectrum_to_rgb(|lambda| if lambda > 600.0 { 1.0 } else { 0.0 }, 64);
+        assert!(red.r() > red.g() && red.r() > red.b());
+    }
+
+    #[test]
+    fn test_rgb_round_trip(){
+        let c = Color::new(0.6, 0.3, 0.1);
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        let n = 1000;
+        for i in 0..n {
+            let lambda = SampledWavelengths::sample_uniform((i as f32 + 0.5) / n as f32);
+            sum = sum + SampledSpectrum::from_rgb(c, &lambda).to_rgb(&lambda);
+        }
+        let average = sum / n as f32;
+        assert!((average - c).length() < 1e-2);
+    }
+
+    #[test]
+    fn test_terminate_secondary_keeps_estimate(){
+        let mut lambda = SampledWavelengths::sample_uniform(0.3);
+        let full = SampledSpectrum::new(1.0).to_xyz(&lambda);
+        lambda.terminate_secondary();
+        assert!(lambda.secondary_terminated());
+
+        // Only the hero wavelength contributes now, weighted up by the number of samples
+        let hero_only = SampledSpectrum::new(1.0).to_xyz(&lambda);
+        let expected = cie_xyz(lambda.hero()) * ((LAMBDA_MAX - LAMBDA_MIN) / tables().cie_y_integral);
+        assert!((hero_only - expected).length() < 1e-4);
+        assert!(full != hero_only);
+    }
+}
@@ -0,0 +1,156 @@
+use crate::color::Color;
+use crate::ray::Ray;
+use crate::hittable::HitRecord;
+use crate::material::{Material, ScatterRecord};
+use crate::microfacet::fresnel_dielectric;
+use crate::phase::{PhaseFunction, HenyeyGreenstein};
+use crate::constants::random_generator;
+
+// Translucent material scattered by a volumetric random walk inside a closed surface, for skin, wax, marble or milk.
+// Light refracts in through the front face and travels through a homogeneous medium. Every time a path hits the
+// back face, the segment it just crossed lay inside the object, so a free-flight distance is sampled along it: the
+// walk either scatters inside per the phase function, or reaches the boundary and refracts out or reflects back in.
+// Every step that stays inside is a ScatterRecord::Walk, so however long the walk, it costs the path two bounces:
+// the way in and the way out
+pub struct Subsurface {
+    // Single-scattering albedo: the fraction of each interaction that scatters rather than absorbs
+    albedo: Color,
+    // Average distance between interactions, in scene units. Color comes from the albedo, which compounds over the
+    // many events of a walk, rather than from per-channel distances that would make the walk's weights noisy
+    mean_free_path: f32,
+    phase: HenyeyGreenstein,
+    ior: f32,
+}
+
+impl Subsurface {
+    pub fn new (albedo: Color, mean_free_path: f32) -> Self {
+        Subsurface { albedo, mean_free_path: mean_free_path.max(1e-6), phase: HenyeyGreenstein::new(0.0), ior: 1.4 }
+    }
+
+    // Mean cosine of the phase function; tissue scatters strongly forwards, around 0.8
+    pub fn with_anisotropy (mut self, g: f32) -> Self {
+        self.phase = HenyeyGreenstein::new(g);
+        self
+    }
+
+    pub fn with_ior (mut self, ior: f32) -> Self {
+        self.ior = ior.max(1.0);
+        self
+    }
+
+    // Smooth dielectric boundary: reflects with the Fresnel probability and refracts otherwise. Also returns whether
+    // the ray was reflected
+    fn cross_boundary (&self, r_in: &Ray, rec: &HitRecord) -> (Ray, bool) {
+        let ri = if rec.front_face() { 1.0 / self.ior } else { self.ior };
+        let unit_direction = r_in.direction().unit_vector();
+        let cos_theta = (-unit_direction).dot(&rec.shading_normal()).min(1.0);
+
+        let reflected = random_generator() < fresnel_dielectric(cos_theta, 1.0 / ri);
+        let (direction, differentials) = if reflected {
+            let direction = unit_direction.reflect(&rec.shading_normal());
+            (direction, rec.reflect_differentials(r_in, direction))
+        } else {
+            let direction = unit_direction.refract(&rec.shading_normal(), ri);
+            (direction, rec.refract_differentials(r_in, direction, ri))
+        };
+
+        let mut ray = Ray::new(rec.p(), direction);
+        if let Some(differentials) = differentials {
+            ray = ray.with_differentials(differentials);
+        }
+        (ray, reflected)
+    }
+}
+
+impl Material for Subsurface {
+    fn scatter (&self, r_in: &Ray, rec: &HitRecord) -> Option<ScatterRecord> {
+        let white = Color::new(1.0, 1.0, 1.0);
+        if rec.front_face() {
+            let (ray, _) = self.cross_boundary(r_in, rec);
+            return Some(ScatterRecord::Specular { attenuation: white, ray });
+        }
+
+        // Free-flight distances are sampled proportionally to transmittance, which cancels it out of the weights:
+        // an interaction scatters with the albedo, and reaching the boundary carries no weight at all
+        let speed = r_in.direction().length();
+        let s = -(1.0 - random_generator()).ln() * self.mean_free_path;
+        if s < rec.t() * speed {
+            let ray = Ray::new(r_in.at(s / speed), self.phase.sample(&r_in.direction()));
+            return Some(ScatterRecord::Walk { attenuation: self.albedo, ray });
+        }
+        // Reflecting back in off the boundary carries on the walk
+        match self.cross_boundary(r_in, rec) {
+            (ray, true) => Some(ScatterRecord::Walk { attenuation: white, ray }),
+            (ray, false) => Some(ScatterRecord::Specular { attenuation: white, ray }),
+        }
+    }
+
+    fn albedo (&self, _rec: &HitRecord) -> Color {
+        self.albedo
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::vec3::{Vec3, Point3};
+    use crate::hittable::{HittableList, Sphere};
+    use crate::integrator::{Integrator, PathIntegrator};
+    use crate::sampler::RandomSampler;
+    use crate::scene::Scene;
+    use std::sync::Arc;
+
+    // Black surface glowing from both sides, to surround the object under test
+    struct Glow;
+
+    impl Material for Glow {
+        fn emitted (&self, _r_in: &Ray, _rec: &HitRecord) -> Color {
+            Color::new(1.0, 1.0, 1.0)
+        }
+    }
+
+    // Average radiance seen along rays from outside aimed through the center of a unit sphere of `material`,
+    // inside a glowing enclosure
+    fn radiance_through (material: Subsurface, n: usize) -> Color {
+        let mut world = HittableList::default();
+        world.add(Arc::new(Sphere::new(Point3::default(), 1.0, Arc::new(material))));
+        world.add(Arc::new(Sphere::new(Point3::default(), 10.0, Arc::new(Glow))));
+        let scene = Scene::new(world, HittableList::default());
+        let integrator = PathIntegrator::default();
+        let mut sampler = RandomSampler::new();
+
+        let r = Ray::new(Point3::new(0.0, 0.0, 5.0), Vec3::new(0.0, 0.0, -1.0));
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..n {
+            sum = sum + integrator.li(r, &scene, &mut sampler);
+        }
+        sum / n as f32
+    }
+
+    #[test]
+    fn test_subsurface_absorption_follows_beer_lambert(){
+        // A pure absorber with matched index only lets through exp(-length / mean free path)
+        for mean_free_path in [0.5, 2.0] {
+            let absorber = Subsurface::new(Color::new(0.0, 0.0, 0.0), mean_free_path).with_ior(1.0);
+            let radiance = radiance_through(absorber, 4000).r();
+            let expected = (-2.0 / mean_free_path).exp();
+            assert!((radiance - expected).abs() < 0.03, "transmitted {} expected {}", radiance, expected);
+        }
+    }
+
+    #[test]
+    fn test_subsurface_white_medium_conserves_energy(){
+        // In a uniformly glowing enclosure a non-absorbing object can neither add nor remove light
+        let white = Subsurface::new(Color::new(1.0, 1.0, 1.0), 0.5).with_anisotropy(0.3).with_ior(1.3);
+        let radiance = radiance_through(white, 4000);
+        assert!((radiance - Color::new(1.0, 1.0, 1.0)).length() < 0.05, "radiance {:?}", radiance);
+    }
+
+    #[test]
+    fn test_subsurface_dense_white_medium_conserves_energy(){
+        // Forty mean free paths across, so walks run to hundreds of steps, far more than the integrator's depth
+        let white = Subsurface::new(Color::new(1.0, 1.0, 1.0), 0.05).with_ior(1.3);
+        let radiance = radiance_through(white, 2000);
+        assert!((radiance - Color::new(1.0, 1.0, 1.0)).length() < 0.05, "radiance {:?}", radiance);
+    }
+}
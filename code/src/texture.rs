@@ -0,0 +1,607 @@
+use crate::color::Color;
+use crate::vec3::Point3;
+use crate::film::Image;
+use crate::colorspace::srgb_eotf;
+use crate::perlin::Perlin;
+use crate::hittable::HitRecord;
+use std::io;
+use std::path::Path;
+use std::sync::Arc;
+
+// How far the texture coordinates move between neighbouring pixels, found from ray differentials
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Footprint {
+    pub dudx: f32,
+    pub dvdx: f32,
+    pub dudy: f32,
+    pub dvdy: f32,
+}
+
+impl Footprint {
+    pub fn is_zero (&self) -> bool {
+        self.width() == 0.0
+    }
+
+    // Largest change in u or v across the footprint
+    pub fn width (&self) -> f32 {
+        self.dudx.abs().max(self.dudy.abs()).max(self.dvdx.abs()).max(self.dvdy.abs())
+    }
+}
+
+// A texture gives a color for every surface point, looked up by the hit's (u, v) coordinates and/or its position
+pub trait Texture: Send + Sync {
+    fn value (&self, u: f32, v: f32, p: &Point3) -> Color;
+
+    // The texture averaged over a pixel's footprint. Textures that can't prefilter fall back to a point sample
+    fn filtered_value (&self, u: f32, v: f32, p: &Point3, _footprint: &Footprint) -> Color {
+        self.value(u, v, p)
+    }
+
+    // Filtered value at a hit
+    fn evaluate (&self, rec: &HitRecord) -> Color {
+        self.filtered_value(rec.u(), rec.v(), &rec.p(), &rec.footprint())
+    }
+}
+
+pub struct SolidColor {
+    albedo: Color,
+}
+
+impl SolidColor {
+    pub fn new (albedo: Color) -> Self {
+        SolidColor { albedo }
+    }
+
+    pub fn from_rgb (red: f32, green: f32, blue: f32) -> Self {
+        SolidColor::new(Color::new(red, green, blue))
+    }
+}
+
+impl Texture for SolidColor {
+    fn value (&self, _u: f32, _v: f32, _p: &Point3) -> Color {
+        self.albedo
+    }
+}
+
+// Alternates between two textures in a 3D grid of cubes `scale` units wide, so it works on any surface without UVs
+pub struct CheckerTexture {
+    inv_scale: f32,
+    even: Arc<dyn Texture>,
+    odd: Arc<dyn Texture>,
+}
+
+impl CheckerTexture {
+    pub fn new (scale: f32, even: Arc<dyn Texture>, odd: Arc<dyn Texture>) -> Self {
+        CheckerTexture { inv_scale: 1.0 / scale, even, odd }
+    }
+
+    pub fn from_colors (scale: f32, even: Color, odd: Color) -> Self {
+        CheckerTexture::new(scale, Arc::new(SolidColor::new(even)), Arc::new(SolidColor::new(odd)))
+    }
+}
+
+impl CheckerTexture {
+    fn pick (&self, p: &Point3) -> &Arc<dyn Texture> {
+        let x = (self.inv_scale * p.x()).floor() as i64;
+        let y = (self.inv_scale * p.y()).floor() as i64;
+        let z = (self.inv_scale * p.z()).floor() as i64;
+
+        if (x + y + z) % 2 == 0 { &self.even } else { &self.odd }
+    }
+}
+
+impl Texture for CheckerTexture {
+    fn value (&self, u: f32, v: f32, p: &Point3) -> Color {
+        self.pick(p).value(u, v, p)
+    }
+
+    fn filtered_value (&self, u: f32, v: f32, p: &Point3, footprint: &Footprint) -> Color {
+        self.pick(p).filtered_value(u, v, p, footprint)
+    }
+}
+
+// What an image texture does with texel coordinates that fall outside the image
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum WrapMode {
+    // Tile the image
+    Repeat,
+    // Tile the image, flipping every other copy so the seams match
+    Mirror,
+    // Stretch the edge texels outwards
+    Clamp,
+}
+
+impl WrapMode {
+    fn wrap (&self, i: i64, size: usize) -> usize {
+        let n = size as i64;
+        match self {
+            WrapMode::Repeat => i.rem_euclid(n) as usize,
+            WrapMode::Mirror => {
+                let period = i.rem_euclid(2 * n);
+                (if period < n { period } else { 2 * n - 1 - period }) as usize
+            }
+            WrapMode::Clamp => i.clamp(0, n - 1) as usize,
+        }
+    }
+}
+
+// How an image texture averages texels over a pixel's footprint
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum TextureFilter {
+    // Blend the four nearest texels of the full resolution image, ignoring the footprint
+    Bilinear,
+    // Blend bilinear lookups in the two mip levels whose texel size brackets the footprint's widest axis
+    Trilinear,
+    // Elliptically weighted average over the footprint's ellipse, which stays sharp on surfaces seen at grazing angles
+    Ewa,
+}
+
+// An image mapped over the surface's (u, v) square, with v = 0 at the bottom row. A mip pyramid of successively
+// halved copies is built up front so minified lookups can average many texels cheaply
+pub struct ImageTexture {
+    levels: Vec<Image>,
+    wrap: WrapMode,
+    filter: TextureFilter,
+}
+
+impl ImageTexture {
+    // Longest ratio of major to minor footprint axis EWA accepts; longer ellipses are widened to bound the cost
+    const MAX_ANISOTROPY: f32 = 8.0;
+
+    // `image` holds linear colors
+    pub fn new (image: Image, wrap: WrapMode) -> Self {
+        assert!(image.width() > 0 && image.height() > 0, "image textures need at least one texel along each axis");
+        let mut levels = vec![image];
+        loop {
+            let last = &levels[levels.len() - 1];
+            if last.width() <= 1 && last.height() <= 1 {
+                break;
+            }
+            let next = ImageTexture::downsample(last, wrap);
+            levels.push(next);
+        }
+        ImageTexture { levels, wrap, filter: TextureFilter::Trilinear }
+    }
+
+    pub fn with_filter (self, filter: TextureFilter) -> Self {
+        ImageTexture { filter, ..self }
+    }
+
+    // Loads a PNG, JPEG or PPM file. 8 and 16-bit files are taken to be sRGB encoded and are linearized
+    pub fn load (path: &Path, wrap: WrapMode) -> io::Result<Self> {
+        ImageTexture::decode(path, wrap, srgb_eotf)
+    }
+
+    // Loads an image holding data rather than color, such as a normal or height map, with values kept as stored
+    pub fn load_data (path: &Path, wrap: WrapMode) -> io::Result<Self> {
+        ImageTexture::decode(path, wrap, |x| x)
+    }
+
+    fn decode (path: &Path, wrap: WrapMode, transfer: fn(f32) -> f32) -> io::Result<Self> {
+        let decoded = image::open(path).map_err(io::Error::other)?.to_rgb32f();
+        if decoded.width() == 0 || decoded.height() == 0 {
+            return Err(io::Error::new(io::ErrorKind::InvalidData, "image has no pixels"));
+        }
+
+        let mut image = Image::new(decoded.width() as usize, decoded.height() as usize);
+        for (x, y, pixel) in decoded.enumerate_pixels() {
+            let [r, g, b] = pixel.0;
+            image.set(x as usize, y as usize, Color::new(transfer(r), transfer(g), transfer(b)));
+        }
+        Ok(ImageTexture::new(image, wrap))
+    }
+
+    // Halves each dimension with a 2x2 box filter. Odd sizes round up and the extra texel is fetched through the
+    // wrap mode, so tiling textures stay seamless
+    fn downsample (image: &Image, wrap: WrapMode) -> Image {
+        let width = image.width().div_ceil(2).max(1);
+        let height = image.height().div_ceil(2).max(1);
+        let fetch = |x: usize, y: usize| image.get(wrap.wrap(x as i64, image.width()), wrap.wrap(y as i64, image.height()));
+
+        let mut output = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                let sum = fetch(2 * x, 2 * y) + fetch(2 * x + 1, 2 * y) + fetch(2 * x, 2 * y + 1) + fetch(2 * x + 1, 2 * y + 1);
+                output.set(x, y, 0.25 * sum);
+            }
+        }
+        output
+    }
+
+    pub fn image (&self) -> &Image {
+        &self.levels[0]
+    }
+
+    pub fn levels (&self) -> &[Image] {
+        &self.levels
+    }
+
+    fn texel (&self, level: usize, x: i64, y: i64) -> Color {
+        let image = &self.levels[level];
+        image.get(self.wrap.wrap(x, image.width()), self.wrap.wrap(y, image.height()))
+    }
+
+    fn bilinear (&self, level: usize, u: f32, v: f32) -> Color {
+        let image = &self.levels[level];
+        // Texel centers sit at half-integer coordinates; image rows run from the top
+        let x = u * image.width() as f32 - 0.5;
+        let y = (1.0 - v) * image.height() as f32 - 0.5;
+        let (x0, y0) = (x.floor(), y.floor());
+        let (fx, fy) = (x - x0, y - y0);
+        let (x0, y0) = (x0 as i64, y0 as i64);
+
+        let top = (1.0 - fx) * self.texel(level, x0, y0) + fx * self.texel(level, x0 + 1, y0);
+        let bottom = (1.0 - fx) * self.texel(level, x0, y0 + 1) + fx * self.texel(level, x0 + 1, y0 + 1);
+        (1.0 - fy) * top + fy * bottom
+    }
+
+    // Mip level where a footprint `width` (in uv units) spans about one texel
+    fn level_for (&self, width: f32) -> f32 {
+        let size = self.levels[0].width().max(self.levels[0].height()) as f32;
+        (width * size).max(1e-8).log2().clamp(0.0, (self.levels.len() - 1) as f32)
+    }
+
+    fn trilinear (&self, u: f32, v: f32, width: f32) -> Color {
+        let level = self.level_for(width);
+        let lower = level.floor() as usize;
+        if lower + 1 >= self.levels.len() {
+            return self.bilinear(lower, u, v);
+        }
+        let t = level - lower as f32;
+        lerp(self.bilinear(lower, u, v), self.bilinear(lower + 1, u, v), t)
+    }
+
+    fn ewa (&self, u: f32, v: f32, footprint: &Footprint) -> Color {
+        let mut major = (footprint.dudx, footprint.dvdx);
+        let mut minor = (footprint.dudy, footprint.dvdy);
+        let length = |axis: (f32, f32)| (axis.0 * axis.0 + axis.1 * axis.1).sqrt();
+        if length(major) < length(minor) {
+            std::mem::swap(&mut major, &mut minor);
+        }
+        let major_length = length(major);
+        let mut minor_length = length(minor);
+        if minor_length == 0.0 {
+            return self.bilinear(0, u, v);
+        }
+
+        // Very eccentric ellipses cover huge numbers of texels; widen the minor axis instead
+        if minor_length * ImageTexture::MAX_ANISOTROPY < major_length {
+            let scale = major_length / (minor_length * ImageTexture::MAX_ANISOTROPY);
+            minor = (minor.0 * scale, minor.1 * scale);
+            minor_length *= scale;
+        }
+
+        // Pick the level where the minor axis spans a few texels, and blend the two nearest levels
+        let level = self.level_for(minor_length);
+        let lower = level.floor() as usize;
+        if lower + 1 >= self.levels.len() {
+            return self.ewa_level(lower, u, v, major, minor);
+        }
+        let t = level - lower as f32;
+        lerp(self.ewa_level(lower, u, v, major, minor), self.ewa_level(lower + 1, u, v, major, minor), t)
+    }
+
+    // Gaussian weighted average over the ellipse spanned by the two footprint axes, in one mip level
+    fn ewa_level (&self, level: usize, u: f32, v: f32, axis0: (f32, f32), axis1: (f32, f32)) -> Color {
+        let image = &self.levels[level];
+        let (width, height) = (image.width() as f32, image.height() as f32);
+        let s = u * width - 0.5;
+        let t = (1.0 - v) * height - 0.5;
+        // Axes in texel units; v runs against image rows
+        let (ds0, dt0) = (axis0.0 * width, -axis0.1 * height);
+        let (ds1, dt1) = (axis1.0 * width, -axis1.1 * height);
+
+        // Implicit ellipse a*ss^2 + b*ss*tt + c*tt^2 < 1, padded by a texel so it never falls between samples
+        let mut a = dt0 * dt0 + dt1 * dt1 + 1.0;
+        let mut b = -2.0 * (ds0 * dt0 + ds1 * dt1);
+        let mut c = ds0 * ds0 + ds1 * ds1 + 1.0;
+        let inv_f = 1.0 / (a * c - b * b * 0.25);
+        a *= inv_f;
+        b *= inv_f;
+        c *= inv_f;
+
+        let det = -b * b + 4.0 * a * c;
+        let inv_det = 1.0 / det;
+        let u_sqrt = (det * c).sqrt();
+        let v_sqrt = (a * det).sqrt();
+        let s0 = (s - 2.0 * inv_det * u_sqrt).ceil() as i64;
+        let s1 = (s + 2.0 * inv_det * u_sqrt).floor() as i64;
+        let t0 = (t - 2.0 * inv_det * v_sqrt).ceil() as i64;
+        let t1 = (t + 2.0 * inv_det * v_sqrt).floor() as i64;
+
+        let alpha: f32 = 2.0;
+        let mut sum = Color::new(0.0, 0.0, 0.0);
+        let mut weight_sum = 0.0;
+        for it in t0..=t1 {
+            let tt = it as f32 - t;
+            for is in s0..=s1 {
+                let ss = is as f32 - s;
+                let r2 = a * ss * ss + b * ss * tt + c * tt * tt;
+                if r2 < 1.0 {
+                    let weight = (-alpha * r2).exp() - (-alpha).exp();
+                    sum = sum + weight * self.texel(level, is, it);
+                    weight_sum += weight;
+                }
+            }
+        }
+
+        if weight_sum > 0.0 { sum / weight_sum } else { self.bilinear(level, u, v) }
+    }
+}
+
+impl Texture for ImageTexture {
+    fn value (&self, u: f32, v: f32, _p: &Point3) -> Color {
+        self.bilinear(0, u, v)
+    }
+
+    fn filtered_value (&self, u: f32, v: f32, p: &Point3, footprint: &Footprint) -> Color {
+        if footprint.is_zero() {
+            return self.value(u, v, p);
+        }
+        match self.filter {
+            TextureFilter::Bilinear => self.bilinear(0, u, v),
+            TextureFilter::Trilinear => self.trilinear(u, v, 2.0 * footprint.width()),
+            TextureFilter::Ewa => self.ewa(u, v, footprint),
+        }
+    }
+}
+
+fn lerp (a: Color, b: Color, t: f32) -> Color {
+    (1.0 - t) * a + t * b
+}
+
+// Plain Perlin noise remapped to [0, 1] and used to modulate a color
+pub struct NoiseTexture {
+    noise: Perlin,
+    // Spatial frequency: larger values give finer detail
+    scale: f32,
+    color: Color,
+}
+
+impl NoiseTexture {
+    pub fn new (seed: u64, scale: f32, color: Color) -> Self {
+        NoiseTexture { noise: Perlin::new(seed), scale, color }
+    }
+}
+
+impl Texture for NoiseTexture {
+    fn value (&self, _u: f32, _v: f32, p: &Point3) -> Color {
+        let n = 0.5 * (1.0 + self.noise.noise(&(*p * self.scale)));
+        n.clamp(0.0, 1.0) * self.color
+    }
+}
+
+// Sine stripes along z whose phase is disturbed by turbulence, giving veins between `base` and `vein`
+pub struct MarbleTexture {
+    noise: Perlin,
+    scale: f32,
+    // How strongly turbulence bends the stripes
+    turbulence: f32,
+    base: Color,
+    vein: Color,
+}
+
+impl MarbleTexture {
+    pub fn new (seed: u64, scale: f32, base: Color, vein: Color) -> Self {
+        MarbleTexture { noise: Perlin::new(seed), scale, turbulence: 10.0, base, vein }
+    }
+}
+
+impl Texture for MarbleTexture {
+    fn value (&self, _u: f32, _v: f32, p: &Point3) -> Color {
+        let phase = self.scale * p.z() + self.turbulence * self.noise.turbulence(p, 7);
+        lerp(self.vein, self.base, 0.5 * (1.0 + phase.sin()))
+    }
+}
+
+// Concentric growth rings around the y axis, made irregular with noise
+pub struct WoodTexture {
+    noise: Perlin,
+    // Rings per unit of distance from the axis
+    rings: f32,
+    // How far noise shifts the rings, in rings
+    distortion: f32,
+    light: Color,
+    dark: Color,
+}
+
+impl WoodTexture {
+    pub fn new (seed: u64, rings: f32, light: Color, dark: Color) -> Self {
+        WoodTexture { noise: Perlin::new(seed), rings, distortion: 0.4, light, dark }
+    }
+}
+
+impl Texture for WoodTexture {
+    fn value (&self, _u: f32, _v: f32, p: &Point3) -> Color {
+        let radius = (p.x() * p.x() + p.z() * p.z()).sqrt();
+        let ring = radius * self.rings + self.distortion * self.noise.fbm(&(*p * 2.0), 4, 2.0, 0.5);
+        // Sharpen the ring profile so the dark late-wood is narrower than the light early-wood
+        let t = (ring - ring.floor()).powi(3);
+        lerp(self.light, self.dark, t)
+    }
+}
+
+// Fractal noise thresholded into soft clouds over a sky color
+pub struct CloudTexture {
+    noise: Perlin,
+    scale: f32,
+    octaves: u32,
+    // Fraction of the sky the clouds roughly cover, in [0, 1]
+    coverage: f32,
+    sky: Color,
+    cloud: Color,
+}
+
+impl CloudTexture {
+    pub fn new (seed: u64, scale: f32, coverage: f32, sky: Color, cloud: Color) -> Self {
+        CloudTexture { noise: Perlin::new(seed), scale, octaves: 6, coverage, sky, cloud }
+    }
+}
+
+impl Texture for CloudTexture {
+    fn value (&self, _u: f32, _v: f32, p: &Point3) -> Color {
+        let density = 0.5 * (1.0 + self.noise.fbm(&(*p * self.scale), self.octaves, 2.0, 0.5));
+        let threshold = 1.0 - self.coverage;
+        let t = ((density - threshold) / (1.0 - threshold).max(1e-3)).clamp(0.0, 1.0);
+        lerp(self.sky, self.cloud, t)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn two_texel_image () -> Image {
+        let mut image = Image::new(2, 1);
+        image.set(0, 0, Color::new(0.0, 0.0, 0.0));
+        image.set(1, 0, Color::new(1.0, 1.0, 1.0));
+        image
+    }
+
+    #[test]
+    fn test_checker_texture(){
+        let checker = CheckerTexture::from_colors(1.0, Color::new(1.0, 1.0, 1.0), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(checker.value(0.0, 0.0, &Point3::new(0.5, 0.5, 0.5)), Color::new(1.0, 1.0, 1.0));
+        assert_eq!(checker.value(0.0, 0.0, &Point3::new(1.5, 0.5, 0.5)), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(checker.value(0.0, 0.0, &Point3::new(-0.5, 0.5, 0.5)), Color::new(0.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn test_image_texture_bilinear(){
+        let texture = ImageTexture::new(two_texel_image(), WrapMode::Clamp);
+        let p = Point3::default();
+        // Texel centers return their own color, halfway between them is the average
+        assert_eq!(texture.value(0.25, 0.5, &p), Color::new(0.0, 0.0, 0.0));
+        assert_eq!(texture.value(0.75, 0.5, &p), Color::new(1.0, 1.0, 1.0));
+        assert!((texture.value(0.5, 0.5, &p).r() - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_image_texture_wrap_modes(){
+        let p = Point3::default();
+        // Just past the right edge: clamp keeps the edge texel, repeat blends towards the first texel
+        let clamp = ImageTexture::new(two_texel_image(), WrapMode::Clamp);
+        let repeat = ImageTexture::new(two_texel_image(), WrapMode::Repeat);
+        let mirror = ImageTexture::new(two_texel_image(), WrapMode::Mirror);
+        assert_eq!(clamp.value(1.0, 0.5, &p).r(), 1.0);
+        assert!((repeat.value(1.0, 0.5, &p).r() - 0.5).abs() < 1e-6);
+        assert_eq!(mirror.value(1.0, 0.5, &p).r(), 1.0);
+
+        assert_eq!(WrapMode::Mirror.wrap(-1, 4), 0);
+        assert_eq!(WrapMode::Mirror.wrap(5, 4), 2);
+        assert_eq!(WrapMode::Repeat.wrap(-1, 4), 3);
+    }
+
+    #[test]
+    fn test_image_texture_load_ppm(){
+        let path = std::env::temp_dir().join(format!("raytracer_texture_{}.ppm", std::process::id()));
+        std::fs::write(&path, "P3\n2 1\n255\n255 0 0\n0 0 0\n").unwrap();
+        let texture = ImageTexture::load(&path, WrapMode::Clamp).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(texture.image().width(), 2);
+        assert_eq!(texture.image().get(0, 0), Color::new(1.0, 0.0, 0.0));
+
+        std::fs::write(&path, "P3\n1 1\n255\n128 128 255\n").unwrap();
+        let data = ImageTexture::load_data(&path, WrapMode::Clamp).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert!((data.image().get(0, 0).r() - 128.0 / 255.0).abs() < 1e-6);
+        assert!(ImageTexture::load(Path::new("does/not/exist.png"), WrapMode::Clamp).is_err());
+    }
+
+    #[test]
+    fn test_procedural_textures_stay_between_their_colors(){
+        let black = Color::new(0.0, 0.0, 0.0);
+        let white = Color::new(1.0, 1.0, 1.0);
+        let textures: Vec<Box<dyn Texture>> = vec![
+            Box::new(NoiseTexture::new(3, 4.0, white)),
+            Box::new(MarbleTexture::new(3, 4.0, white, black)),
+            Box::new(WoodTexture::new(3, 8.0, white, black)),
+            Box::new(CloudTexture::new(3, 2.0, 0.5, black, white)),
+        ];
+        for texture in &textures {
+            for i in 0..200 {
+                let t = i as f32 * 0.071;
+                let c = texture.value(0.0, 0.0, &Point3::new(t, 0.5 * t, -t));
+                assert!((0.0..=1.0 + 1e-5).contains(&c.r()));
+            }
+        }
+
+        // The same seed reproduces the same pattern
+        let p = Point3::new(0.3, 0.2, 0.1);
+        assert_eq!(MarbleTexture::new(9, 4.0, white, black).value(0.0, 0.0, &p), MarbleTexture::new(9, 4.0, white, black).value(0.0, 0.0, &p));
+    }
+
+    fn checkerboard (size: usize) -> Image {
+        let mut image = Image::new(size, size);
+        for y in 0..size {
+            for x in 0..size {
+                let c = if (x + y) % 2 == 0 { 1.0 } else { 0.0 };
+                image.set(x, y, Color::new(c, c, c));
+            }
+        }
+        image
+    }
+
+    #[test]
+    fn test_mip_pyramid_preserves_average(){
+        let texture = ImageTexture::new(checkerboard(8), WrapMode::Repeat);
+        assert_eq!(texture.levels().len(), 4);
+        assert_eq!(texture.levels()[3].width(), 1);
+        assert!((texture.levels()[1].get(0, 0).r() - 0.5).abs() < 1e-6);
+        assert!((texture.levels()[3].get(0, 0).r() - 0.5).abs() < 1e-6);
+
+        // Odd sizes still reduce down to a single texel
+        assert_eq!(ImageTexture::new(Image::new(5, 3), WrapMode::Clamp).levels().last().unwrap().width(), 1);
+        assert_eq!(ImageTexture::new(Image::new(1, 6), WrapMode::Repeat).levels().len(), 4);
+    }
+
+    #[test]
+    #[should_panic(expected = "at least one texel")]
+    fn test_image_texture_rejects_empty_images(){
+        ImageTexture::new(Image::new(0, 4), WrapMode::Repeat);
+    }
+
+    #[test]
+    fn test_filtering_averages_minified_checkerboard(){
+        let p = Point3::default();
+        // A footprint covering many texels should see the average of the checkerboard, not one of its squares
+        let footprint = Footprint { dudx: 0.1, dvdx: 0.0, dudy: 0.0, dvdy: 0.1 };
+        for filter in [TextureFilter::Trilinear, TextureFilter::Ewa] {
+            let texture = ImageTexture::new(checkerboard(64), WrapMode::Repeat).with_filter(filter);
+            let c = texture.filtered_value(0.3, 0.6, &p, &footprint);
+            assert!((c.r() - 0.5).abs() < 0.05);
+        }
+
+        // Point sampling lands on a single square
+        let texture = ImageTexture::new(checkerboard(64), WrapMode::Repeat).with_filter(TextureFilter::Bilinear);
+        let c = texture.filtered_value(0.5 / 64.0, 1.0 - 0.5 / 64.0, &p, &footprint);
+        assert_eq!(c.r(), 1.0);
+    }
+
+    #[test]
+    fn test_ewa_stays_sharp_across_short_axis(){
+        // Vertical stripes, seen with a footprint stretched along them: EWA keeps the stripe, trilinear blurs it
+        let mut stripes = Image::new(64, 64);
+        for y in 0..64 {
+            for x in 0..64 {
+                let c = if (x / 8) % 2 == 0 { 1.0 } else { 0.0 };
+                stripes.set(x, y, Color::new(c, c, c));
+            }
+        }
+        let footprint = Footprint { dudx: 0.002, dvdx: 0.0, dudy: 0.0, dvdy: 0.1 };
+        let (u, v) = (4.0 / 64.0, 0.5);
+        let p = Point3::default();
+        let ewa = ImageTexture::new(stripes.clone(), WrapMode::Repeat).with_filter(TextureFilter::Ewa);
+        let trilinear = ImageTexture::new(stripes, WrapMode::Repeat).with_filter(TextureFilter::Trilinear);
+        assert!(ewa.filtered_value(u, v, &p, &footprint).r() > 0.9);
+        assert!(trilinear.filtered_value(u, v, &p, &footprint).r() < 0.75);
+    }
+
+    #[test]
+    fn test_zero_footprint_is_bilinear(){
+        let texture = ImageTexture::new(two_texel_image(), WrapMode::Clamp).with_filter(TextureFilter::Ewa);
+        let p = Point3::default();
+        assert_eq!(texture.filtered_value(0.5, 0.5, &p, &Footprint::default()), texture.value(0.5, 0.5, &p));
+    }
+}
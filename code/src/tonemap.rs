@@ -0,0 +1,158 @@
+use crate::color::Color;
+use crate::film::Image;
+use std::str::FromStr;
+
+// Curves that compress linear scene radiance into the [0, 1] display range
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub enum ToneMapOperator {
+    // Leave values alone; anything above 1 is clipped by the 8-bit encoder
+    Clamp,
+    // L / (1 + L) on luminance, which never quite reaches white
+    Reinhard,
+    // Reinhard scaled so luminance `white_point` maps to exactly 1
+    ExtendedReinhard { white_point: f32 },
+    // John Hable's Uncharted 2 filmic curve
+    Hable,
+    // Krzysztof Narkowicz's fit of the ACES filmic reference transform
+    Aces,
+}
+
+impl FromStr for ToneMapOperator {
+    type Err = String;
+
+    fn from_str (s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "clamp" => Ok(ToneMapOperator::Clamp),
+            "reinhard" => Ok(ToneMapOperator::Reinhard),
+            "extended-reinhard" => Ok(ToneMapOperator::ExtendedReinhard { white_point: 4.0 }),
+            "hable" | "uncharted" => Ok(ToneMapOperator::Hable),
+            "aces" => Ok(ToneMapOperator::Aces),
+            _ => Err(format!("unknown tone mapping operator '{}'", s)),
+        }
+    }
+}
+
+// Exposure followed by a tone curve, applied to the linear framebuffer before it is encoded to 8 bits
+#[derive(Copy, Clone, Debug)]
+pub struct ToneMapper {
+    pub operator: ToneMapOperator,
+    // Exposure in stops: every +1 EV doubles the scene radiance
+    pub exposure: f32,
+}
+
+impl Default for ToneMapper {
+    fn default() -> Self {
+        ToneMapper::new(ToneMapOperator::Clamp, 0.0)
+    }
+}
+
+impl ToneMapper {
+    pub fn new (operator: ToneMapOperator, exposure: f32) -> Self {
+        ToneMapper { operator, exposure }
+    }
+
+    // Scales color so its luminance becomes `mapped`, keeping the hue
+    fn scale_luminance (c: Color, mapped: f32) -> Color {
+        let luminance = c.luminance();
+        if luminance <= 0.0 {
+            return Color::new(0.0, 0.0, 0.0);
+        }
+        c * (mapped / luminance)
+    }
+
+    fn hable_partial (x: f32) -> f32 {
+        let (a, b, c, d, e, f) = (0.15, 0.50, 0.10, 0.20, 0.02, 0.30);
+        ((x * (a * x + c * b) + d * e) / (x * (a * x + b) + d * f)) - e / f
+    }
+
+    fn hable (x: f32) -> f32 {
+        let exposure_bias = 2.0;
+        let white_point = 11.2;
+        (ToneMapper::hable_partial(x * exposure_bias) / ToneMapper::hable_partial(white_point)).clamp(0.0, 1.0)
+    }
+
+    fn aces (x: f32) -> f32 {
+        let (a, b, c, d, e) = (2.51, 0.03, 2.43, 0.59, 0.14);
+        ((x * (a * x + b)) / (x * (c * x + d) + e)).clamp(0.0, 1.0)
+    }
+
+    pub fn map (&self, color: Color) -> Color {
+        let c = color * 2.0_f32.powf(self.exposure);
+
+        match self.operator {
+            ToneMapOperator::Clamp => c,
+            ToneMapOperator::Reinhard => {
+                let l = c.luminance();
+                ToneMapper::scale_luminance(c, l / (1.0 + l))
+            }
+            ToneMapOperator::ExtendedReinhard { white_point } => {
+                let l = c.luminance();
+                ToneMapper::scale_luminance(c, l * (1.0 + l / (white_point * white_point)) / (1.0 + l))
+            }
+            ToneMapOperator::Hable => Color::new(ToneMapper::hable(c.r()), ToneMapper::hable(c.g()), ToneMapper::hable(c.b())),
+            ToneMapOperator::Aces => Color::new(ToneMapper::aces(c.r()), ToneMapper::aces(c.g()), ToneMapper::aces(c.b())),
+        }
+    }
+
+    pub fn apply (&self, image: &Image) -> Image {
+        let mut output = Image::new(image.width(), image.height());
+        for y in 0..image.height() {
+            for x in 0..image.width() {
+                output.set(x, y, self.map(image.get(x, y)));
+            }
+        }
+        output
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tonemap_default_is_identity(){
+        let c = Color::new(0.2, 1.5, 3.0);
+        assert_eq!(ToneMapper::default().map(c), c);
+    }
+
+    #[test]
+    fn test_tonemap_exposure(){
+        let mapper = ToneMapper::new(ToneMapOperator::Clamp, 1.0);
+        assert_eq!(mapper.map(Color::new(0.25, 0.5, 1.0)), Color::new(0.5, 1.0, 2.0));
+    }
+
+    #[test]
+    fn test_tonemap_reinhard(){
+        let mapper = ToneMapper::new(ToneMapOperator::Reinhard, 0.0);
+        let mapped = mapper.map(Color::new(1.0, 1.0, 1.0));
+        assert!((mapped.luminance() - 0.5).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_tonemap_extended_reinhard_white_point(){
+        let mapper = ToneMapper::new(ToneMapOperator::ExtendedReinhard { white_point: 4.0 }, 0.0);
+        let mapped = mapper.map(Color::new(4.0, 4.0, 4.0));
+        assert!((mapped.luminance() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_tonemap_filmic_curves_stay_in_range(){
+        for operator in [ToneMapOperator::Hable, ToneMapOperator::Aces] {
+            let mapper = ToneMapper::new(operator, 0.0);
+            let mut previous = -1.0;
+            for i in 0..100 {
+                let x = i as f32 * 0.5;
+                let y = mapper.map(Color::new(x, x, x)).r();
+                assert!((-1e-5..=1.0 + 1e-5).contains(&y));
+                assert!(y >= previous);
+                previous = y;
+            }
+        }
+    }
+
+    #[test]
+    fn test_tonemap_operator_from_str(){
+        assert_eq!("aces".parse::<ToneMapOperator>(), Ok(ToneMapOperator::Aces));
+        assert!("sepia".parse::<ToneMapOperator>().is_err());
+    }
+}
@@ -0,0 +1,598 @@
+use crate::vec3::{Vec3, Point3};
+use crate::ray::{Ray, RayDifferential};
+use crate::constants::degrees_to_radians;
+use std::ops::Mul;
+
+// A 4x4 matrix in row major order, acting on column vectors. Points carry an implicit w of 1 and vectors a w of 0
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Mat4 {
+    m: [[f32; 4]; 4],
+}
+
+impl Default for Mat4 {
+    fn default() -> Self {
+        Mat4::identity()
+    }
+}
+
+impl Mul for Mat4 {
+    type Output = Mat4;
+
+    fn mul (self, other: Mat4) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (row, out) in m.iter_mut().enumerate() {
+            for (col, value) in out.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.m[row][k] * other.m[k][col]).sum();
+            }
+        }
+        Mat4 { m }
+    }
+}
+
+impl Mat4 {
+    pub fn new (m: [[f32; 4]; 4]) -> Self {
+        Mat4 { m }
+    }
+
+    pub fn identity () -> Self {
+        let mut m = [[0.0; 4]; 4];
+        for (i, row) in m.iter_mut().enumerate() {
+            row[i] = 1.0;
+        }
+        Mat4 { m }
+    }
+
+    pub fn get (&self, row: usize, col: usize) -> f32 {
+        self.m[row][col]
+    }
+
+    pub fn transpose (&self) -> Mat4 {
+        let mut m = [[0.0; 4]; 4];
+        for (row, out) in m.iter_mut().enumerate() {
+            for (col, value) in out.iter_mut().enumerate() {
+                *value = self.m[col][row];
+            }
+        }
+        Mat4 { m }
+    }
+
+    // Gauss-Jordan elimination with partial pivoting. None if the matrix is singular
+    pub fn inverse (&self) -> Option<Mat4> {
+        let mut a = self.m;
+        let mut inverse = Mat4::identity().m;
+        for col in 0..4 {
+            let pivot = (col..4).max_by(|&i, &j| a[i][col].abs().total_cmp(&a[j][col].abs()))?;
+            if a[pivot][col].abs() < 1e-12 {
+                return None;
+            }
+            a.swap(col, pivot);
+            inverse.swap(col, pivot);
+
+            let scale = 1.0 / a[col][col];
+            for k in 0..4 {
+                a[col][k] *= scale;
+                inverse[col][k] *= scale;
+            }
+            for row in 0..4 {
+                if row != col {
+                    let factor = a[row][col];
+                    for k in 0..4 {
+                        a[row][k] -= factor * a[col][k];
+                        inverse[row][k] -= factor * inverse[col][k];
+                    }
+                }
+            }
+        }
+        Some(Mat4 { m: inverse })
+    }
+
+    // Determinant of the upper 3x3 block, the factor by which the transform scales volumes
+    pub fn linear_determinant (&self) -> f32 {
+        let m = &self.m;
+        m[0][0] * (m[1][1] * m[2][2] - m[1][2] * m[2][1])
+            - m[0][1] * (m[1][0] * m[2][2] - m[1][2] * m[2][0])
+            + m[0][2] * (m[1][0] * m[2][1] - m[1][1] * m[2][0])
+    }
+
+    pub fn transform_point (&self, p: &Point3) -> Point3 {
+        let m = &self.m;
+        let row = |i: usize| m[i][0] * p.x() + m[i][1] * p.y() + m[i][2] * p.z() + m[i][3];
+        let w = row(3);
+        let p = Point3::new(row(0), row(1), row(2));
+        if w == 1.0 { p } else { p / w }
+    }
+
+    pub fn transform_vector (&self, v: &Vec3) -> Vec3 {
+        let m = &self.m;
+        let row = |i: usize| m[i][0] * v.x() + m[i][1] * v.y() + m[i][2] * v.z();
+        Vec3::new(row(0), row(1), row(2))
+    }
+}
+
+// A rotation stored as a unit quaternion w + xi + yj + zk. Unlike matrices, quaternions interpolate smoothly, which
+// is what keyframed rotations need
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Quaternion {
+    w: f32,
+    v: Vec3,
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Quaternion::identity()
+    }
+}
+
+// Composes rotations: `a * b` rotates by b first, then a
+impl Mul for Quaternion {
+    type Output = Quaternion;
+
+    fn mul (self, other: Quaternion) -> Quaternion {
+        Quaternion {
+            w: self.w * other.w - self.v.dot(&other.v),
+            v: self.w * other.v + other.w * self.v + self.v.cross(&other.v),
+        }
+    }
+}
+
+impl Quaternion {
+    // Normalized on creation, so any nonzero quaternion describes a rotation
+    pub fn new (w: f32, x: f32, y: f32, z: f32) -> Self {
+        Quaternion { w, v: Vec3::new(x, y, z) }.normalized()
+    }
+
+    pub fn identity () -> Self {
+        Quaternion { w: 1.0, v: Vec3::default() }
+    }
+
+    // Counterclockwise rotation by `degrees` looking down `axis` towards the origin, like Transform::rotate
+    pub fn from_axis_angle (axis: Vec3, degrees: f32) -> Self {
+        let (sin, cos) = (0.5 * degrees_to_radians(degrees)).sin_cos();
+        Quaternion { w: cos, v: sin * axis.unit_vector() }
+    }
+
+    pub fn w (&self) -> f32 {
+        self.w
+    }
+
+    // The vector part x, y, z
+    pub fn v (&self) -> Vec3 {
+        self.v
+    }
+
+    pub fn dot (&self, other: &Quaternion) -> f32 {
+        self.w * other.w + self.v.dot(&other.v)
+    }
+
+    pub fn conjugate (&self) -> Quaternion {
+        Quaternion { w: self.w, v: -self.v }
+    }
+
+    fn normalized (&self) -> Quaternion {
+        let length = self.dot(self).sqrt();
+        if length == 0.0 {
+            return Quaternion::identity();
+        }
+        Quaternion { w: self.w / length, v: self.v / length }
+    }
+
+    pub fn rotate (&self, v: &Vec3) -> Vec3 {
+        let t = 2.0 * self.v.cross(v);
+        *v + self.w * t + self.v.cross(&t)
+    }
+
+    // Spherical linear interpolation: turns from `self` at t = 0 to `other` at t = 1 at constant angular speed,
+    // the short way round
+    pub fn slerp (&self, other: &Quaternion, t: f32) -> Quaternion {
+        let mut cos_theta = self.dot(other);
+        let mut other = *other;
+        // q and -q are the same rotation; pick the one on the near side
+        if cos_theta < 0.0 {
+            cos_theta = -cos_theta;
+            other = Quaternion { w: -other.w, v: -other.v };
+        }
+        // Nearly parallel: the arc is straight enough to interpolate linearly
+        let (a, b) = if cos_theta > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos_theta.min(1.0).acos();
+            let sin_theta = theta.sin();
+            (((1.0 - t) * theta).sin() / sin_theta, (t * theta).sin() / sin_theta)
+        };
+        Quaternion { w: a * self.w + b * other.w, v: a * self.v + b * other.v }.normalized()
+    }
+
+    pub fn to_mat4 (self) -> Mat4 {
+        let (w, x, y, z) = (self.w, self.v.x(), self.v.y(), self.v.z());
+        Mat4::new([
+            [1.0 - 2.0 * (y * y + z * z), 2.0 * (x * y - w * z), 2.0 * (x * z + w * y), 0.0],
+            [2.0 * (x * y + w * z), 1.0 - 2.0 * (x * x + z * z), 2.0 * (y * z - w * x), 0.0],
+            [2.0 * (x * z - w * y), 2.0 * (y * z + w * x), 1.0 - 2.0 * (x * x + y * y), 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ])
+    }
+}
+
+// An invertible affine transform, kept together with its inverse so neither has to be recomputed per ray.
+// `a * b` applies b first, then a
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Transform {
+    matrix: Mat4,
+    inverse: Mat4,
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform::identity()
+    }
+}
+
+impl Mul for Transform {
+    type Output = Transform;
+
+    fn mul (self, other: Transform) -> Transform {
+        Transform { matrix: self.matrix * other.matrix, inverse: other.inverse * self.inverse }
+    }
+}
+
+impl Transform {
+    // None if the matrix can't be inverted, e.g. a scale by zero
+    pub fn new (matrix: Mat4) -> Option<Self> {
+        Some(Transform { matrix, inverse: matrix.inverse()? })
+    }
+
+    pub fn identity () -> Self {
+        Transform { matrix: Mat4::identity(), inverse: Mat4::identity() }
+    }
+
+    pub fn translate (offset: Vec3) -> Self {
+        let translation = |d: Vec3| Mat4::new([
+            [1.0, 0.0, 0.0, d.x()],
+            [0.0, 1.0, 0.0, d.y()],
+            [0.0, 0.0, 1.0, d.z()],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Transform { matrix: translation(offset), inverse: translation(-offset) }
+    }
+
+    // Scales each axis by the matching component, which must be nonzero
+    pub fn scale (factors: Vec3) -> Self {
+        let scaling = |x: f32, y: f32, z: f32| Mat4::new([
+            [x, 0.0, 0.0, 0.0],
+            [0.0, y, 0.0, 0.0],
+            [0.0, 0.0, z, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        Transform {
+            matrix: scaling(factors.x(), factors.y(), factors.z()),
+            inverse: scaling(1.0 / factors.x(), 1.0 / factors.y(), 1.0 / factors.z()),
+        }
+    }
+
+    // Counterclockwise rotation by `degrees` looking down `axis` towards the origin
+    pub fn rotate (axis: Vec3, degrees: f32) -> Self {
+        let a = axis.unit_vector();
+        let (sin, cos) = degrees_to_radians(degrees).sin_cos();
+        let matrix = Mat4::new([
+            [a.x() * a.x() + (1.0 - a.x() * a.x()) * cos, a.x() * a.y() * (1.0 - cos) - a.z() * sin, a.x() * a.z() * (1.0 - cos) + a.y() * sin, 0.0],
+            [a.x() * a.y() * (1.0 - cos) + a.z() * sin, a.y() * a.y() + (1.0 - a.y() * a.y()) * cos, a.y() * a.z() * (1.0 - cos) - a.x() * sin, 0.0],
+            [a.x() * a.z() * (1.0 - cos) - a.y() * sin, a.y() * a.z() * (1.0 - cos) + a.x() * sin, a.z() * a.z() + (1.0 - a.z() * a.z()) * cos, 0.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        // Rotations are orthogonal, so the inverse is the transpose
+        Transform { matrix, inverse: matrix.transpose() }
+    }
+
+    pub fn from_quaternion (q: Quaternion) -> Self {
+        let matrix = q.to_mat4();
+        Transform { matrix, inverse: matrix.transpose() }
+    }
+
+    // Scale, then rotate, then translate: the usual decomposition in scene files and animation rigs
+    pub fn from_trs (translation: Vec3, rotation: Quaternion, scale: Vec3) -> Self {
+        Transform::translate(translation) * Transform::from_quaternion(rotation) * Transform::scale(scale)
+    }
+
+    pub fn rotate_x (degrees: f32) -> Self {
+        Transform::rotate(Vec3::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotate_y (degrees: f32) -> Self {
+        Transform::rotate(Vec3::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotate_z (degrees: f32) -> Self {
+        Transform::rotate(Vec3::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    pub fn matrix (&self) -> Mat4 {
+        self.matrix
+    }
+
+    pub fn inverse (&self) -> Transform {
+        Transform { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn point (&self, p: &Point3) -> Point3 {
+        self.matrix.transform_point(p)
+    }
+
+    pub fn vector (&self, v: &Vec3) -> Vec3 {
+        self.matrix.transform_vector(v)
+    }
+
+    // Normals transform by the inverse transpose so they stay perpendicular to the surface under non-uniform scaling.
+    // The result isn't normalized
+    pub fn normal (&self, n: &Vec3) -> Vec3 {
+        self.inverse.transpose().transform_vector(n)
+    }
+
+    // The ray's direction isn't renormalized, so distances along it keep the same parameter t on both sides
+    pub fn ray (&self, r: &Ray) -> Ray {
+        let transformed = r.with_origin_and_direction(self.point(&r.origin()), self.vector(&r.direction()));
+        match r.differentials() {
+            Some(d) => transformed.with_differentials(RayDifferential {
+                rx_origin: self.point(&d.rx_origin),
+                rx_direction: self.vector(&d.rx_direction),
+                ry_origin: self.point(&d.ry_origin),
+                ry_direction: self.vector(&d.ry_direction),
+            }),
+            None => transformed,
+        }
+    }
+}
+
+// A placement split into scale, then rotation, then translation, the parts that interpolate sensibly on their own
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct Pose {
+    pub translation: Vec3,
+    pub rotation: Quaternion,
+    pub scale: Vec3,
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Pose { translation: Vec3::default(), rotation: Quaternion::identity(), scale: Vec3::new(1.0, 1.0, 1.0) }
+    }
+}
+
+impl Pose {
+    pub fn new (translation: Vec3, rotation: Quaternion, scale: Vec3) -> Self {
+        Pose { translation, rotation, scale }
+    }
+
+    pub fn transform (&self) -> Transform {
+        Transform::from_trs(self.translation, self.rotation, self.scale)
+    }
+
+    // Linear in translation and scale, spherical in rotation
+    pub fn interpolate (&self, other: &Pose, t: f32) -> Pose {
+        Pose {
+            translation: (1.0 - t) * self.translation + t * other.translation,
+            rotation: self.rotation.slerp(&other.rotation, t),
+            scale: (1.0 - t) * self.scale + t * other.scale,
+        }
+    }
+}
+
+// A transform that moves over the shutter interval, from `start` at time 0 to `end` at time 1
+#[derive(Copy, Clone, Debug, PartialEq)]
+pub struct AnimatedTransform {
+    start: Pose,
+    end: Pose,
+}
+
+impl AnimatedTransform {
+    pub fn new (start: Pose, end: Pose) -> Self {
+        AnimatedTransform { start, end }
+    }
+
+    pub fn start (&self) -> Pose {
+        self.start
+    }
+
+    pub fn end (&self) -> Pose {
+        self.end
+    }
+
+    // Whether anything other than translation and scale changes, which is when points stop moving in straight lines
+    pub fn rotates (&self) -> bool {
+        self.start.rotation.dot(&self.end.rotation).abs() < 1.0 - 1e-6
+    }
+
+    pub fn at (&self, time: f32) -> Transform {
+        self.start.interpolate(&self.end, time.clamp(0.0, 1.0)).transform()
+    }
+}
+
+// One link of a TransformChain
+#[derive(Copy, Clone, Debug, PartialEq)]
+enum ChainLink {
+    Fixed(Transform),
+    Animated(AnimatedTransform),
+}
+
+// A placement made of transforms applied one inside the other, some of which move over the shutter interval, such as
+// the nodes from a scene graph's root down to an object. Each link is evaluated at the time asked for before they are
+// multiplied, so a child of a turning parent swings along an arc rather than cutting across it
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TransformChain {
+    // Outermost first
+    links: Vec<ChainLink>,
+}
+
+impl TransformChain {
+    pub fn new () -> Self {
+        TransformChain::default()
+    }
+
+    // Appends a transform applied before every link so far, e.g. a child node's placement within its parent
+    pub fn with_transform (mut self, transform: Transform) -> Self {
+        match self.links.last_mut() {
+            Some(ChainLink::Fixed(last)) => *last = *last * transform,
+            _ => self.links.push(ChainLink::Fixed(transform)),
+        }
+        self
+    }
+
+    // Appends a moving transform applied before every link so far
+    pub fn with_motion (mut self, motion: AnimatedTransform) -> Self {
+        self.links.push(ChainLink::Animated(motion));
+        self
+    }
+
+    pub fn is_animated (&self) -> bool {
+        self.links.iter().any(|link| matches!(link, ChainLink::Animated(_)))
+    }
+
+    // Whether every point moves along a straight line: only when a single link moves, and without rotating
+    pub fn moves_in_straight_lines (&self) -> bool {
+        let mut animated = self.links.iter().filter_map(|link| match link {
+            ChainLink::Animated(motion) => Some(motion),
+            ChainLink::Fixed(_) => None,
+        });
+        match (animated.next(), animated.next()) {
+            (None, _) => true,
+            (Some(motion), None) => !motion.rotates(),
+            _ => false,
+        }
+    }
+
+    pub fn at (&self, time: f32) -> Transform {
+        self.links.iter().fold(Transform::identity(), |transform, link| match link {
+            ChainLink::Fixed(fixed) => transform * *fixed,
+            ChainLink::Animated(motion) => transform * motion.at(time),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close (a: Vec3, b: Vec3) {
+        assert!((a - b).length() < 1e-5, "{} != {}", a, b);
+    }
+
+    #[test]
+    fn test_mat4_inverse(){
+        let m = Mat4::new([
+            [2.0, 0.0, 1.0, 3.0],
+            [0.0, 0.0, 4.0, -1.0],
+            [1.0, 3.0, 0.0, 2.0],
+            [0.0, 0.0, 0.0, 1.0],
+        ]);
+        let product = m * m.inverse().unwrap();
+        for row in 0..4 {
+            for col in 0..4 {
+                let expected = if row == col { 1.0 } else { 0.0 };
+                assert!((product.get(row, col) - expected).abs() < 1e-5);
+            }
+        }
+        assert!((m.linear_determinant() + 24.0).abs() < 1e-5);
+        assert_eq!(Mat4::new([[0.0; 4]; 4]).inverse(), None);
+    }
+
+    #[test]
+    fn test_transform_composition(){
+        // Scale first, then rotate a quarter turn about y, then move
+        let t = Transform::translate(Vec3::new(1.0, 2.0, 3.0)) * Transform::rotate_y(90.0) * Transform::scale(Vec3::new(2.0, 1.0, 1.0));
+        assert_close(t.point(&Point3::new(1.0, 0.0, 0.0)), Point3::new(1.0, 2.0, 1.0));
+        assert_close(t.vector(&Vec3::new(0.0, 1.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+        assert_close(t.inverse().point(&t.point(&Point3::new(0.3, -0.7, 2.0))), Point3::new(0.3, -0.7, 2.0));
+        assert_close(Transform::rotate_z(90.0).vector(&Vec3::new(1.0, 0.0, 0.0)), Vec3::new(0.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_normals_stay_perpendicular(){
+        // Squashing a 45 degree slope makes it steeper; its normal must tilt the other way
+        let t = Transform::scale(Vec3::new(1.0, 0.25, 1.0)) * Transform::rotate_x(30.0);
+        let tangent = Vec3::new(1.0, 1.0, 0.0);
+        let normal = Vec3::new(1.0, -1.0, 0.0);
+        assert!(t.vector(&tangent).dot(&t.normal(&normal)).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_transform_ray_keeps_t(){
+        let t = Transform::translate(Vec3::new(0.0, 1.0, 0.0)) * Transform::scale(Vec3::new(3.0, 3.0, 3.0));
+        let r = Ray::new(Point3::new(1.0, 0.0, 0.0), Vec3::new(0.0, 0.0, -2.0)).with_wavelength(550.0);
+        let moved = t.ray(&r);
+        assert_close(moved.at(1.5), t.point(&r.at(1.5)));
+        assert_eq!(moved.wavelength(), Some(550.0));
+    }
+
+    #[test]
+    fn test_quaternion_matches_matrix_rotation(){
+        let axis = Vec3::new(1.0, 2.0, -0.5);
+        let q = Quaternion::from_axis_angle(axis, 70.0);
+        let v = Vec3::new(0.3, -1.0, 2.0);
+        assert_close(q.rotate(&v), Transform::rotate(axis, 70.0).vector(&v));
+        assert_close(Transform::from_quaternion(q).vector(&v), q.rotate(&v));
+
+        // Composition applies the right hand rotation first
+        let p = Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0);
+        assert_close((p * q).rotate(&v), p.rotate(&q.rotate(&v)));
+        assert_close(q.conjugate().rotate(&q.rotate(&v)), v);
+    }
+
+    #[test]
+    fn test_quaternion_slerp(){
+        let axis = Vec3::new(0.0, 1.0, 0.0);
+        let a = Quaternion::from_axis_angle(axis, 10.0);
+        let b = Quaternion::from_axis_angle(axis, 130.0);
+        let x = Vec3::new(1.0, 0.0, 0.0);
+        assert_close(a.slerp(&b, 0.0).rotate(&x), a.rotate(&x));
+        assert_close(a.slerp(&b, 1.0).rotate(&x), b.rotate(&x));
+        assert_close(a.slerp(&b, 0.25).rotate(&x), Quaternion::from_axis_angle(axis, 40.0).rotate(&x));
+
+        // -b is the same rotation as b, and interpolating towards it still takes the short way
+        let negated = Quaternion::new(-b.w(), -b.v().x(), -b.v().y(), -b.v().z());
+        assert_close(a.slerp(&negated, 0.5).rotate(&x), Quaternion::from_axis_angle(axis, 70.0).rotate(&x));
+    }
+
+    #[test]
+    fn test_transform_from_trs(){
+        let t = Transform::from_trs(Vec3::new(0.0, 0.0, -2.0), Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0), Vec3::new(3.0, 1.0, 1.0));
+        assert_close(t.point(&Point3::new(1.0, 0.0, 0.0)), Point3::new(0.0, 3.0, -2.0));
+    }
+
+    #[test]
+    fn test_animated_transform_interpolates_pose(){
+        let start = Pose::new(Vec3::new(0.0, 0.0, 0.0), Quaternion::identity(), Vec3::new(1.0, 1.0, 1.0));
+        let end = Pose::new(Vec3::new(4.0, 0.0, 0.0), Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0), Vec3::new(3.0, 3.0, 3.0));
+        let motion = AnimatedTransform::new(start, end);
+        assert!(motion.rotates());
+        assert_eq!(motion.at(0.0), start.transform());
+
+        // Halfway: turned 45 degrees, scaled by two and moved two units
+        let p = motion.at(0.5).point(&Point3::new(1.0, 0.0, 0.0));
+        let half = 2.0_f32.sqrt();
+        assert_close(p, Point3::new(2.0 + half, half, 0.0));
+        assert_close(motion.at(1.0).point(&Point3::new(1.0, 0.0, 0.0)), Point3::new(4.0, 3.0, 0.0));
+        assert!(!AnimatedTransform::new(start, Pose { translation: Vec3::new(1.0, 2.0, 3.0), ..start }).rotates());
+    }
+
+    #[test]
+    fn test_transform_chain_evaluates_links_at_time(){
+        // An arm turning a quarter turn about z, with a hand two units along it
+        let raised = Pose { rotation: Quaternion::from_axis_angle(Vec3::new(0.0, 0.0, 1.0), 90.0), ..Pose::default() };
+        let reach = Pose { translation: Vec3::new(1.0, 0.0, 0.0), ..Pose::default() };
+        let chain = TransformChain::new()
+            .with_transform(Transform::translate(Vec3::new(0.0, 0.0, -3.0)))
+            .with_motion(AnimatedTransform::new(Pose::default(), raised))
+            .with_transform(Transform::translate(Vec3::new(2.0, 0.0, 0.0)));
+        assert!(chain.is_animated());
+        assert!(!chain.moves_in_straight_lines());
+
+        // Halfway the hand is on the arc, not on the chord between its end positions
+        let half = 2.0_f32.sqrt();
+        assert_close(chain.at(0.0).point(&Point3::default()), Point3::new(2.0, 0.0, -3.0));
+        assert_close(chain.at(0.5).point(&Point3::default()), Point3::new(half, half, -3.0));
+        assert_close(chain.at(1.0).point(&Point3::default()), Point3::new(0.0, 2.0, -3.0));
+
+        // Sliding the hand one unit further out as the arm turns compounds the two motions
+        let extended = chain.with_motion(AnimatedTransform::new(Pose::default(), reach));
+        assert_close(extended.at(0.5).point(&Point3::default()), Point3::new(1.25 * half, 1.25 * half, -3.0));
+        assert!(TransformChain::new().with_motion(AnimatedTransform::new(Pose::default(), reach)).moves_in_straight_lines());
+    }
+}
@@ -1,6 +1,6 @@
 use std::ops::{Neg, Index, IndexMut, AddAssign, MulAssign, DivAssign, Add, Sub, Mul, Div};
 use std::fmt;
-use crate::constants::{random_generator, random_generator_range};
+use crate::constants::{PI, random_generator, random_generator_range};
 
 pub type Point3 = Vec3;
 
@@ -17,7 +17,7 @@ impl Default for Vec3 {
 
 impl fmt::Display for Vec3 {
     fn fmt (&self, f: &mut fmt::Formatter) -> fmt::Result {
-        write!(f, "{:.1} {:.1} {:.1}", self[0] as f32, self[1] as f32, self[2] as f32)
+        write!(f, "{:.1} {:.1} {:.1}", self[0], self[1], self[2])
     }
 }
 
@@ -168,9 +168,9 @@ impl Vec3 {
 
     pub fn random_unit_vector() -> Vec3 {
         loop {
-            let mut p = Vec3::random_range(-1.0, 1.0);
+            let p = Vec3::random_range(-1.0, 1.0);
             let lensq = p.length_squared();
-            if (lensq >= 10e-38 && lensq <= 1.0) {
+            if (10e-38..=1.0).contains(&lensq) {
                 return p / lensq.sqrt();
             }
         }
@@ -179,13 +179,37 @@ impl Vec3 {
     pub fn random_on_hemisphere(normal: &Vec3) -> Vec3 {
         let on_unit_sphere = Vec3::random_unit_vector();
         if on_unit_sphere.dot(normal) > 0.0 {
-            return on_unit_sphere;
+            on_unit_sphere
         } else {
-            return -on_unit_sphere;
+            -on_unit_sphere
         }
 
     }
 
+    // Cosine-weighted direction about +z
+    pub fn random_cosine_direction() -> Vec3 {
+        let r1 = random_generator();
+        let r2 = random_generator();
+
+        let phi = 2.0 * PI * r1;
+        let x = phi.cos() * r2.sqrt();
+        let y = phi.sin() * r2.sqrt();
+        let z = (1.0 - r2).sqrt();
+
+        Vec3::new(x, y, z)
+    }
+
+    pub fn reflect (&self, n: &Vec3) -> Vec3 {
+        *self - 2.0 * self.dot(n) * *n
+    }
+
+    pub fn refract (&self, n: &Vec3, etai_over_etat: f32) -> Vec3 {
+        let cos_theta = (-*self).dot(n).min(1.0);
+        let r_out_perp = etai_over_etat * (*self + cos_theta * *n);
+        let r_out_parallel = -(1.0 - r_out_perp.length_squared()).abs().sqrt() * *n;
+        r_out_perp + r_out_parallel
+    }
+
 }
 
 
@@ -351,6 +375,23 @@ mod tests {
 
     }
 
+    #[test]
+    fn test_vec3_reflect(){
+        let v = Vec3::new(1.0, -1.0, 0.0);
+        let n = Vec3::new(0.0, 1.0, 0.0);
+
+        assert_eq!(v.reflect(&n), Vec3::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn test_vec3_random_cosine_direction(){
+        for _ in 0..100 {
+            let d = Vec3::random_cosine_direction();
+            assert!(d.z() >= 0.0);
+            assert!((d.length() - 1.0).abs() < 1e-5);
+        }
+    }
+
     #[test]
     fn test_vec3_unit_vector(){
         // Testing alias
@@ -0,0 +1,166 @@
+use crate::vec3::Point3;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+// A dense 3D grid of scalar samples, such as smoke density or fire temperature, covering the unit cube. Samples sit
+// at cell centers and are blended trilinearly; the grid reads as zero outside the cube
+#[derive(Clone, Debug, PartialEq)]
+pub struct VoxelGrid {
+    resolution: [usize; 3],
+    // x varies fastest, then y, then z
+    data: Vec<f32>,
+    max_value: f32,
+}
+
+impl VoxelGrid {
+    pub fn new (resolution: [usize; 3], data: Vec<f32>) -> Self {
+        assert_eq!(data.len(), resolution.iter().product::<usize>(), "voxel data doesn't match the resolution");
+        assert!(resolution.iter().all(|&n| n > 0), "voxel grids need at least one cell along each axis");
+        let max_value = data.iter().cloned().fold(0.0, f32::max);
+        VoxelGrid { resolution, data, max_value }
+    }
+
+    // Procedural grid: `f` is evaluated at every cell center, given in unit cube coordinates
+    pub fn from_fn (resolution: [usize; 3], f: impl Fn(Point3) -> f32) -> Self {
+        let [nx, ny, nz] = resolution;
+        let mut data = Vec::with_capacity(nx * ny * nz);
+        for z in 0..nz {
+            for y in 0..ny {
+                for x in 0..nx {
+                    let center = Point3::new(
+                        (x as f32 + 0.5) / nx as f32,
+                        (y as f32 + 0.5) / ny as f32,
+                        (z as f32 + 0.5) / nz as f32,
+                    );
+                    data.push(f(center));
+                }
+            }
+        }
+        VoxelGrid::new(resolution, data)
+    }
+
+    pub fn resolution (&self) -> [usize; 3] {
+        self.resolution
+    }
+
+    // Largest sample, which bounds every lookup
+    pub fn max_value (&self) -> f32 {
+        self.max_value
+    }
+
+    fn get (&self, x: usize, y: usize, z: usize) -> f32 {
+        let [nx, ny, _] = self.resolution;
+        self.data[(z * ny + y) * nx + x]
+    }
+
+    // Trilinear interpolation at `p` in unit cube coordinates, clamping to the outermost cell centers at the faces
+    pub fn lookup (&self, p: &Point3) -> f32 {
+        if (0..3).any(|axis| !(0.0..=1.0).contains(&p[axis])) {
+            return 0.0;
+        }
+
+        let mut lower = [0; 3];
+        let mut upper = [0; 3];
+        let mut weight = [0.0; 3];
+        for axis in 0..3 {
+            let n = self.resolution[axis];
+            let x = (p[axis] * n as f32 - 0.5).clamp(0.0, (n - 1) as f32);
+            lower[axis] = x.floor() as usize;
+            upper[axis] = (lower[axis] + 1).min(n - 1);
+            weight[axis] = x - lower[axis] as f32;
+        }
+
+        let mut value = 0.0;
+        for corner in 0..8 {
+            let pick = |axis: usize| corner >> axis & 1 == 1;
+            let mut w = 1.0;
+            let mut index = [0; 3];
+            for axis in 0..3 {
+                index[axis] = if pick(axis) { upper[axis] } else { lower[axis] };
+                w *= if pick(axis) { weight[axis] } else { 1.0 - weight[axis] };
+            }
+            value += w * self.get(index[0], index[1], index[2]);
+        }
+        value
+    }
+
+    // A minimal exchange format: the line `VOXEL`, then `nx ny nz` on a line, then nx * ny * nz little endian f32
+    // samples with x varying fastest
+    pub fn write (&self, out: &mut dyn Write) -> io::Result<()> {
+        let [nx, ny, nz] = self.resolution;
+        write!(out, "VOXEL\n{} {} {}\n", nx, ny, nz)?;
+        for value in &self.data {
+            out.write_all(&value.to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    pub fn read (input: &mut dyn Read) -> io::Result<Self> {
+        let mut bytes = Vec::new();
+        input.read_to_end(&mut bytes)?;
+        let invalid = |message: &str| io::Error::new(io::ErrorKind::InvalidData, message.to_string());
+
+        // The header is the first two lines
+        let mut newlines = bytes.iter().enumerate().filter(|(_, &b)| b == b'\n').map(|(i, _)| i);
+        let (first, second) = match (newlines.next(), newlines.next()) {
+            (Some(first), Some(second)) => (first, second),
+            _ => return Err(invalid("truncated voxel header")),
+        };
+        if &bytes[..first] != b"VOXEL" {
+            return Err(invalid("not a voxel grid"));
+        }
+        let dimensions = std::str::from_utf8(&bytes[first + 1..second]).map_err(|_| invalid("bad voxel resolution"))?;
+        let resolution: Vec<usize> = dimensions.split_whitespace().map(|n| n.parse()).collect::<Result<_, _>>()
+            .map_err(|_| invalid("bad voxel resolution"))?;
+        if resolution.len() != 3 || resolution.contains(&0) {
+            return Err(invalid("bad voxel resolution"));
+        }
+
+        let body = &bytes[second + 1..];
+        let count = resolution.iter().product::<usize>();
+        if body.len() != count * 4 {
+            return Err(invalid("voxel data doesn't match the resolution"));
+        }
+        let data = body.chunks_exact(4).map(|chunk| f32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]])).collect();
+        Ok(VoxelGrid::new([resolution[0], resolution[1], resolution[2]], data))
+    }
+
+    pub fn load (path: &Path) -> io::Result<Self> {
+        VoxelGrid::read(&mut BufReader::new(File::open(path)?))
+    }
+
+    pub fn save (&self, path: &Path) -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        self.write(&mut out)?;
+        out.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_voxel_lookup_is_trilinear(){
+        // A ramp along x is reproduced exactly between the first and last cell centers
+        let grid = VoxelGrid::from_fn([4, 2, 3], |p| p.x());
+        assert!((grid.lookup(&Point3::new(0.5, 0.3, 0.9)) - 0.5).abs() < 1e-5);
+        assert!((grid.lookup(&Point3::new(0.3, 0.7, 0.1)) - 0.3).abs() < 1e-5);
+        // Past the outer centers the value is held, and outside the cube it vanishes
+        assert!((grid.lookup(&Point3::new(0.0, 0.5, 0.5)) - 0.125).abs() < 1e-5);
+        assert_eq!(grid.lookup(&Point3::new(1.1, 0.5, 0.5)), 0.0);
+        assert_eq!(grid.max_value(), 0.875);
+    }
+
+    #[test]
+    fn test_voxel_round_trip(){
+        let grid = VoxelGrid::from_fn([3, 2, 2], |p| p.x() + 2.0 * p.y() + 4.0 * p.z());
+        let mut bytes = Vec::new();
+        grid.write(&mut bytes).unwrap();
+        assert_eq!(VoxelGrid::read(&mut bytes.as_slice()).unwrap(), grid);
+
+        assert!(VoxelGrid::read(&mut &b"VOXEL\n2 2 2\n"[..]).is_err());
+        assert!(VoxelGrid::read(&mut &b"PF\n1 1\n"[..]).is_err());
+    }
+}